@@ -0,0 +1,38 @@
+use std::{
+    io::{self, Write as _},
+    process::{Command, Stdio},
+};
+
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+use crate::error::Error;
+
+// Copy to the system clipboard with the OSC 52 escape sequence, which the terminal handles, also
+// over ssh. Some terminals limit the size, or need it to be enabled.
+pub fn copy(text: &str) -> Result<(), Error> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Copy by piping the text into a command like `wl-copy` or `pbcopy`, which may come with
+// arguments.
+pub fn copy_with_command(command: &str, text: &str) -> Result<(), Error> {
+    let mut args = command.split_whitespace();
+    let program = args.next().unwrap_or_default();
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|err| Error::Clipboard(format!("{program}: {err}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(Error::Clipboard(format!("{program} exited with {status}")));
+    }
+    Ok(())
+}
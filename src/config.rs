@@ -1,34 +1,209 @@
 use std::{fs, path::PathBuf};
 
 use confy::ConfyError;
-use ratatui::crossterm::style::Color;
+use ratatui::crossterm::style::{Attribute, Attributes, Color};
 use ratatui_image::picker::ProtocolType;
 use serde::{Deserialize, Serialize};
 
-use crate::error::Error;
+use crate::{error::Error, setup::BgColor};
+
+// Rendered with each candidate font in the font setup.
+pub const DEFAULT_FONT_PREVIEW_TEXT: &str = "The quick brown fox jumps over the lazy dog";
 
 // The configuration struct used throughout the program.
 //
 // Has implicit `Default` in `From<UserConfig>`.
 #[derive(Debug)]
 pub struct Config {
+    pub font_preview_text: String,
     pub padding: PaddingConfig,
     pub max_image_height: u16,
+    // Half again the `max_image_height` if `None`.
+    pub max_image_width: Option<ImageWidth>,
+    pub image_placeholder_height: u16,
+    pub image_margin: u16,
+    // Blank lines before and after headers, where other blocks are separated by one.
+    pub header_space_before: u16,
+    pub header_space_after: u16,
+    pub image_background: ImageBackground,
+    pub hero_image: bool,
+    pub max_image_pixels: u64,
+    // Bigger `data:` uri images are shown as broken images.
+    pub max_data_uri_bytes: usize,
+    // Play animated GIF, PNG and WebP images, instead of showing their first frame. Only with the
+    // Kitty and halfblocks protocols.
+    pub animate_images: bool,
+    pub image_follow_redirects: bool,
+    pub image_max_redirects: usize,
+    // Like `{url}{sep}w={width}`, off if `None`.
+    pub image_resize_url_template: Option<String>,
+    // All hosts if empty.
+    pub image_resize_hosts: Vec<String>,
+    // Keep downloaded images on disk, in `image_cache_dir` or the default cache directory.
+    pub image_cache: bool,
+    pub image_cache_dir: Option<PathBuf>,
+    // Cached images older than this are downloaded again, never if `None`.
+    pub image_cache_ttl_seconds: Option<u64>,
+    // The oldest cached images are removed beyond this size, when starting.
+    pub image_cache_max_megabytes: u64,
+    // Where relative image and link paths of stdin and `--text` documents are resolved.
+    pub basepath: Option<PathBuf>,
     pub watch_debounce_milliseconds: u64,
+    // How long to wait for the terminal to answer the graphics capability query.
+    pub capability_query_timeout_milliseconds: u64,
+    // How long to wait for input between checking for file changes and rendered images, when
+    // nothing is animating.
+    pub idle_poll_milliseconds: u64,
     pub enable_mouse_capture: bool,
     pub debug_override_protocol_type: Option<ProtocolType>,
     pub theme: Theme,
+    pub headers: HeaderMode,
+    pub render_html: bool,
+    pub show_html_comments: bool,
+    pub show_link_definitions: bool,
+    pub max_nesting_depth: usize,
+    pub snap_scroll: bool,
+    pub show_outline: bool,
+    // An overview of the whole document right of the content, with the visible part highlighted.
+    pub show_minimap: bool,
+    pub link_underline: bool,
+    pub autolink_bare_urls: bool,
+    pub header_emphasis: bool,
+    pub show_recent_on_launch: bool,
+    pub recent_files_max: usize,
+    // Start files at the scroll position they were closed at.
+    pub remember_position: bool,
+    pub show_source: bool,
+    pub long_line_mode: LongLineMode,
+    pub link_wrap: LinkWrap,
+    // Off by default, documents should not run external commands unless asked to.
+    pub render_diagrams: bool,
+    pub follow_local_links: bool,
+    pub mermaid_command: String,
+    pub dot_command: String,
+    // Opens links and images, with `{url}` replaced by the url or path.
+    pub open_command: String,
+    // Copies by piping into the command, with OSC 52 if `None`.
+    pub clipboard_command: Option<String>,
+    pub fill_background: bool,
+    pub inline_image_thumbnails: bool,
+    pub focus_mode: bool,
+    pub show_scroll_hints: bool,
+    // Off if `None`.
+    pub column_guide: Option<u16>,
+    pub deep_fry_headers: bool,
+    pub deep_fry_images: bool,
+    // Unlimited if `None`.
+    pub max_header_lines: Option<usize>,
+    // Never folded if `None`.
+    pub code_fold_threshold: Option<usize>,
+    // Text columns of squeezed tables are not wrapped narrower than this.
+    pub table_min_column_width: usize,
+    // Unlimited if `None`.
+    pub table_max_column_width: Option<usize>,
+    pub search_colors: SearchColors,
+    pub quote_gutter_color: ratatui::style::Color,
+    // Links to image files, styled apart from links to pages, if `distinct_image_links`.
+    pub image_link_color: ratatui::style::Color,
+    pub distinct_image_links: bool,
+    pub enter_action: EnterAction,
+    pub header_width: HeaderWidth,
 }
 
 impl From<UserConfig> for Config {
     fn from(uc: UserConfig) -> Self {
         Config {
+            font_preview_text: uc
+                .font_preview_text
+                .unwrap_or_else(|| String::from(DEFAULT_FONT_PREVIEW_TEXT)),
             padding: uc.padding.unwrap_or_default(),
             max_image_height: uc.max_image_height.unwrap_or(30),
+            max_image_width: uc.max_image_width,
+            image_placeholder_height: uc.image_placeholder_height.unwrap_or(1),
+            image_margin: uc.image_margin.unwrap_or(0),
+            header_space_before: uc.header_space_before.unwrap_or(1),
+            header_space_after: uc.header_space_after.unwrap_or(0),
+            image_background: uc.image_background.unwrap_or_default(),
+            hero_image: uc.hero_image.unwrap_or(false),
+            max_image_pixels: uc.max_image_pixels.unwrap_or(4096 * 4096),
+            max_data_uri_bytes: uc.max_data_uri_bytes.unwrap_or(10 * 1024 * 1024),
+            animate_images: uc.animate_images.unwrap_or(false),
+            image_follow_redirects: uc.image_follow_redirects.unwrap_or(true),
+            image_max_redirects: uc.image_max_redirects.unwrap_or(10),
+            image_resize_url_template: uc.image_resize_url_template,
+            image_resize_hosts: uc.image_resize_hosts.unwrap_or_default(),
+            image_cache: uc.image_cache.unwrap_or(true),
+            image_cache_dir: uc.image_cache_dir,
+            image_cache_ttl_seconds: uc.image_cache_ttl_seconds,
+            image_cache_max_megabytes: uc.image_cache_max_megabytes.unwrap_or(200),
+            basepath: uc.basepath,
             watch_debounce_milliseconds: uc.watch_debounce_milliseconds.unwrap_or(100),
+            capability_query_timeout_milliseconds: uc
+                .capability_query_timeout_milliseconds
+                .unwrap_or(2000)
+                .max(1),
+            idle_poll_milliseconds: uc.idle_poll_milliseconds.unwrap_or(500).max(1),
             enable_mouse_capture: uc.enable_mouse_capture.unwrap_or(false),
             debug_override_protocol_type: uc.debug_override_protocol_type,
             theme: uc.theme.unwrap_or_default(),
+            headers: uc.headers.unwrap_or_default(),
+            render_html: uc.render_html.unwrap_or(true),
+            show_html_comments: uc.show_html_comments.unwrap_or(false),
+            show_link_definitions: uc.show_link_definitions.unwrap_or(false),
+            max_nesting_depth: uc.max_nesting_depth.unwrap_or(16).max(1),
+            snap_scroll: uc.snap_scroll.unwrap_or(false),
+            show_outline: uc.show_outline.unwrap_or(false),
+            show_minimap: uc.show_minimap.unwrap_or(false),
+            link_underline: uc.link_underline.unwrap_or(true),
+            autolink_bare_urls: uc.autolink_bare_urls.unwrap_or(true),
+            header_emphasis: uc.header_emphasis.unwrap_or(true),
+            show_recent_on_launch: uc.show_recent_on_launch.unwrap_or(false),
+            recent_files_max: uc.recent_files_max.unwrap_or(10),
+            remember_position: uc.remember_position.unwrap_or(true),
+            show_source: uc.show_source.unwrap_or(false),
+            long_line_mode: uc.long_line_mode.unwrap_or_default(),
+            link_wrap: uc.link_wrap.unwrap_or_default(),
+            render_diagrams: uc.render_diagrams.unwrap_or(false),
+            follow_local_links: uc.follow_local_links.unwrap_or(true),
+            mermaid_command: uc.mermaid_command.unwrap_or_else(|| String::from("mmdc")),
+            dot_command: uc.dot_command.unwrap_or_else(|| String::from("dot")),
+            open_command: uc.open_command.map_or_else(
+                || String::from(DEFAULT_OPEN_COMMAND),
+                |command| command.trim().to_owned(),
+            ),
+            clipboard_command: uc
+                .clipboard_command
+                .map(|command| command.trim().to_owned())
+                .filter(|command| !command.is_empty()),
+            fill_background: uc.fill_background.unwrap_or(true),
+            inline_image_thumbnails: uc.inline_image_thumbnails.unwrap_or(false),
+            focus_mode: uc.focus_mode.unwrap_or(false),
+            show_scroll_hints: uc.show_scroll_hints.unwrap_or(false),
+            column_guide: uc.column_guide,
+            deep_fry_headers: uc.deep_fry_headers.unwrap_or(false),
+            deep_fry_images: uc.deep_fry_images.unwrap_or(false),
+            max_header_lines: uc.max_header_lines.map(|lines| lines.max(1)),
+            code_fold_threshold: uc.code_fold_threshold.map(|lines| lines.max(1)),
+            table_min_column_width: uc.table_min_column_width.unwrap_or(8),
+            table_max_column_width: uc.table_max_column_width.map(|width| width.max(3)),
+            search_colors: SearchColors {
+                fg: uc.search_match_fg.unwrap_or(ratatui::style::Color::Black),
+                bg: uc
+                    .search_match_bg
+                    .unwrap_or(ratatui::style::Color::Indexed(148)),
+                current_bg: uc
+                    .search_current_bg
+                    .unwrap_or(ratatui::style::Color::Indexed(197)),
+            },
+            quote_gutter_color: uc
+                .quote_gutter_color
+                .unwrap_or(ratatui::style::Color::Indexed(63)),
+            image_link_color: uc
+                .image_link_color
+                .unwrap_or(ratatui::style::Color::Indexed(141)),
+            distinct_image_links: uc.distinct_image_links.unwrap_or(true),
+            enter_action: uc.enter_action.unwrap_or_default(),
+            header_width: uc.header_width.unwrap_or_default(),
         }
     }
 }
@@ -37,12 +212,76 @@ impl From<UserConfig> for Config {
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct UserConfig {
     pub font_family: Option<String>,
+    pub font_preview_text: Option<String>,
     pub padding: Option<PaddingConfig>,
     pub max_image_height: Option<u16>,
+    pub max_image_width: Option<ImageWidth>,
+    pub image_placeholder_height: Option<u16>,
+    pub image_margin: Option<u16>,
+    pub header_space_before: Option<u16>,
+    pub header_space_after: Option<u16>,
+    pub image_background: Option<ImageBackground>,
+    pub hero_image: Option<bool>,
+    pub max_image_pixels: Option<u64>,
+    pub max_data_uri_bytes: Option<usize>,
+    pub animate_images: Option<bool>,
+    pub image_follow_redirects: Option<bool>,
+    pub image_max_redirects: Option<usize>,
+    pub image_resize_url_template: Option<String>,
+    pub image_resize_hosts: Option<Vec<String>>,
+    pub image_cache: Option<bool>,
+    pub image_cache_dir: Option<PathBuf>,
+    pub image_cache_ttl_seconds: Option<u64>,
+    pub image_cache_max_megabytes: Option<u64>,
+    pub basepath: Option<PathBuf>,
     pub watch_debounce_milliseconds: Option<u64>,
+    pub capability_query_timeout_milliseconds: Option<u64>,
+    pub idle_poll_milliseconds: Option<u64>,
     pub enable_mouse_capture: Option<bool>,
     pub debug_override_protocol_type: Option<ProtocolType>,
     pub theme: Option<Theme>,
+    pub headers: Option<HeaderMode>,
+    pub render_html: Option<bool>,
+    pub show_html_comments: Option<bool>,
+    pub show_link_definitions: Option<bool>,
+    pub max_nesting_depth: Option<usize>,
+    pub snap_scroll: Option<bool>,
+    pub show_outline: Option<bool>,
+    pub show_minimap: Option<bool>,
+    pub link_underline: Option<bool>,
+    pub autolink_bare_urls: Option<bool>,
+    pub header_emphasis: Option<bool>,
+    pub show_recent_on_launch: Option<bool>,
+    pub recent_files_max: Option<usize>,
+    pub remember_position: Option<bool>,
+    pub show_source: Option<bool>,
+    pub long_line_mode: Option<LongLineMode>,
+    pub link_wrap: Option<LinkWrap>,
+    pub render_diagrams: Option<bool>,
+    pub follow_local_links: Option<bool>,
+    pub mermaid_command: Option<String>,
+    pub dot_command: Option<String>,
+    pub open_command: Option<String>,
+    pub clipboard_command: Option<String>,
+    pub fill_background: Option<bool>,
+    pub inline_image_thumbnails: Option<bool>,
+    pub focus_mode: Option<bool>,
+    pub show_scroll_hints: Option<bool>,
+    pub column_guide: Option<u16>,
+    pub deep_fry_headers: Option<bool>,
+    pub deep_fry_images: Option<bool>,
+    pub max_header_lines: Option<usize>,
+    pub code_fold_threshold: Option<usize>,
+    pub table_min_column_width: Option<usize>,
+    pub table_max_column_width: Option<usize>,
+    pub search_match_fg: Option<ratatui::style::Color>,
+    pub search_match_bg: Option<ratatui::style::Color>,
+    pub search_current_bg: Option<ratatui::style::Color>,
+    pub quote_gutter_color: Option<ratatui::style::Color>,
+    pub image_link_color: Option<ratatui::style::Color>,
+    pub distinct_image_links: Option<bool>,
+    pub enter_action: Option<EnterAction>,
+    pub header_width: Option<HeaderWidth>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,6 +301,18 @@ impl Default for Theme {
 
         skin.quote_mark.set_fg(Color::AnsiValue(63));
         skin.bullet.set_fg(Color::AnsiValue(63));
+
+        // Headers that are rendered as plain lines, from bright and underlined to dim.
+        for (header, color) in skin.headers.iter_mut().zip([75, 75, 111, 111, 146, 244]) {
+            header.compound_style.object_style.attributes = Attributes::default();
+            header.set_fg(Color::AnsiValue(color));
+        }
+        for header in &mut skin.headers[..4] {
+            header.compound_style.add_attr(Attribute::Bold);
+        }
+        for header in &mut skin.headers[..2] {
+            header.compound_style.add_attr(Attribute::Underlined);
+        }
         Theme { skin }
     }
 }
@@ -93,6 +344,151 @@ impl PaddingConfig {
     }
 }
 
+// The colors of search matches, and of the current match that `n` and `N` jump to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchColors {
+    pub fg: ratatui::style::Color,
+    pub bg: ratatui::style::Color,
+    pub current_bg: ratatui::style::Color,
+}
+
+// What transparent images are composited onto, before being sent to the terminal.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageBackground {
+    // Left to the terminal, or the graphics protocol.
+    #[default]
+    Transparent,
+    // A solid color, like `[255, 255, 255]`.
+    #[serde(untagged)]
+    Rgb([u8; 3]),
+}
+
+impl ImageBackground {
+    pub fn bg_color(self) -> Option<BgColor> {
+        match self {
+            ImageBackground::Transparent => None,
+            ImageBackground::Rgb(rgb) => Some(BgColor::from(rgb)),
+        }
+    }
+}
+
+// The widest that images are rendered, in columns like `80`, or like `"80%"` of the document width.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ImageWidth {
+    Columns(u16),
+    #[serde(with = "percent")]
+    Percent(u16),
+}
+
+impl ImageWidth {
+    pub fn columns(self, width: u16) -> u16 {
+        match self {
+            ImageWidth::Columns(columns) => columns.max(1),
+            ImageWidth::Percent(percent) => {
+                (u32::from(width) * u32::from(percent) / 100).max(1) as u16
+            }
+        }
+    }
+}
+
+mod percent {
+    use serde::{Deserialize as _, Deserializer, Serializer, de::Error as _};
+
+    pub fn serialize<S: Serializer>(percent: &u16, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{percent}%"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.trim()
+            .strip_suffix('%')
+            .and_then(|percent| percent.trim().parse().ok())
+            .filter(|percent| (1..=100).contains(percent))
+            .ok_or_else(|| D::Error::custom(format!("expected a percentage like \"80%\": {text}")))
+    }
+}
+
+// How words that are longer than the whole line (like base64 or urls) are rendered.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LongLineMode {
+    // Hard-break over several lines.
+    #[default]
+    Wrap,
+    // Cut off with an ellipsis.
+    Truncate,
+}
+
+// How links with urls that don't fit on the line are rendered.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkWrap {
+    // Wrapped like any other text, splitting the url.
+    #[default]
+    Split,
+    // Only the link text is inline, the urls are listed after the paragraph.
+    Reference,
+}
+
+// What `Enter` does when there is no link or search cursor.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnterAction {
+    // Expand or collapse the first `<details>` or folded code block on screen.
+    #[default]
+    Details,
+    // Scroll down one line, like `less`.
+    Scroll,
+    // Open the first link on screen, like `gx`.
+    Link,
+    None,
+}
+
+// How wide header images are, over a `padding` centered body.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderWidth {
+    // As wide as the body text.
+    #[default]
+    Body,
+    // The full width of the terminal, without the padding.
+    Full,
+}
+
+// How headers are rendered.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderMode {
+    // Rendered as images, or with the text sizing protocol, if the terminal supports either.
+    #[default]
+    Big,
+    // Always plain text lines, without any font setup.
+    Text,
+}
+
+#[cfg(target_os = "macos")]
+const DEFAULT_OPEN_COMMAND: &str = "open {url}";
+// Not `cmd /c start`, where cmd.exe would run the `&` or `|` in a url as commands.
+#[cfg(windows)]
+const DEFAULT_OPEN_COMMAND: &str = "rundll32 url.dll,FileProtocolHandler {url}";
+#[cfg(not(any(target_os = "macos", windows)))]
+const DEFAULT_OPEN_COMMAND: &str = "xdg-open {url}";
+
+// The `open_command` is checked when loading the config, instead of when opening the first link.
+pub fn check_open_command(command: &str) -> Result<(), Error> {
+    if command.split_whitespace().next().is_none() {
+        return Err(Error::OpenCommand(String::from("`open_command` is empty")));
+    }
+    if !command.contains("{url}") {
+        return Err(Error::OpenCommand(format!(
+            "`open_command` has no {{url}} placeholder: {command:?}"
+        )));
+    }
+    Ok(())
+}
+
 const CONFIG_APP_NAME: &str = "mdfried";
 const CONFIG_CONFIG_NAME: &str = "config";
 
@@ -143,18 +539,26 @@ pub fn load_or_ask() -> Result<UserConfig, Error> {
     })
 }
 
+// The config file, first written with the defaults if there is none yet, for editing it.
+pub fn ensure_file() -> Result<PathBuf, Error> {
+    let path = confy::get_configuration_file_path(CONFIG_APP_NAME, CONFIG_CONFIG_NAME)?;
+    if !path.exists() {
+        store(&default_user_config())?;
+    }
+    Ok(path)
+}
+
+// Load the config file again, after it was edited.
+pub fn reload() -> Result<UserConfig, Error> {
+    Ok(confy::load::<UserConfig>(
+        CONFIG_APP_NAME,
+        CONFIG_CONFIG_NAME,
+    )?)
+}
+
 // Write a default config file to stdout.
 pub fn print_default() -> Result<(), Error> {
-    let config = Config::from(UserConfig::default());
-    let user_config = UserConfig {
-        padding: Some(config.padding),
-        font_family: None,
-        max_image_height: Some(config.max_image_height),
-        watch_debounce_milliseconds: Some(config.watch_debounce_milliseconds),
-        enable_mouse_capture: Some(config.enable_mouse_capture),
-        debug_override_protocol_type: config.debug_override_protocol_type,
-        theme: Some(config.theme),
-    };
+    let user_config = default_user_config();
 
     // We could use the toml crate to avoid doing the temp-file roundtrip, but doing it this way
     // means it's guaranteed to be good for `confy::load`.
@@ -170,3 +574,81 @@ pub fn print_default() -> Result<(), Error> {
     eprintln!("Config file default path: {default_config_path}",);
     Ok(())
 }
+
+// Every option with its default value.
+fn default_user_config() -> UserConfig {
+    let config = Config::from(UserConfig::default());
+    UserConfig {
+        padding: Some(config.padding),
+        font_family: None,
+        font_preview_text: Some(config.font_preview_text),
+        max_image_height: Some(config.max_image_height),
+        max_image_width: config.max_image_width,
+        image_placeholder_height: Some(config.image_placeholder_height),
+        image_margin: Some(config.image_margin),
+        header_space_before: Some(config.header_space_before),
+        header_space_after: Some(config.header_space_after),
+        image_background: Some(config.image_background),
+        hero_image: Some(config.hero_image),
+        max_image_pixels: Some(config.max_image_pixels),
+        max_data_uri_bytes: Some(config.max_data_uri_bytes),
+        animate_images: Some(config.animate_images),
+        image_follow_redirects: Some(config.image_follow_redirects),
+        image_max_redirects: Some(config.image_max_redirects),
+        image_resize_url_template: config.image_resize_url_template,
+        image_resize_hosts: Some(config.image_resize_hosts),
+        image_cache: Some(config.image_cache),
+        image_cache_dir: config.image_cache_dir,
+        image_cache_ttl_seconds: config.image_cache_ttl_seconds,
+        image_cache_max_megabytes: Some(config.image_cache_max_megabytes),
+        basepath: config.basepath,
+        watch_debounce_milliseconds: Some(config.watch_debounce_milliseconds),
+        capability_query_timeout_milliseconds: Some(config.capability_query_timeout_milliseconds),
+        idle_poll_milliseconds: Some(config.idle_poll_milliseconds),
+        enable_mouse_capture: Some(config.enable_mouse_capture),
+        debug_override_protocol_type: config.debug_override_protocol_type,
+        theme: Some(config.theme),
+        headers: Some(config.headers),
+        render_html: Some(config.render_html),
+        show_html_comments: Some(config.show_html_comments),
+        show_link_definitions: Some(config.show_link_definitions),
+        max_nesting_depth: Some(config.max_nesting_depth),
+        snap_scroll: Some(config.snap_scroll),
+        show_outline: Some(config.show_outline),
+        show_minimap: Some(config.show_minimap),
+        link_underline: Some(config.link_underline),
+        autolink_bare_urls: Some(config.autolink_bare_urls),
+        header_emphasis: Some(config.header_emphasis),
+        show_recent_on_launch: Some(config.show_recent_on_launch),
+        recent_files_max: Some(config.recent_files_max),
+        remember_position: Some(config.remember_position),
+        show_source: Some(config.show_source),
+        long_line_mode: Some(config.long_line_mode),
+        link_wrap: Some(config.link_wrap),
+        render_diagrams: Some(config.render_diagrams),
+        follow_local_links: Some(config.follow_local_links),
+        mermaid_command: Some(config.mermaid_command),
+        dot_command: Some(config.dot_command),
+        open_command: Some(config.open_command),
+        clipboard_command: config.clipboard_command,
+        fill_background: Some(config.fill_background),
+        inline_image_thumbnails: Some(config.inline_image_thumbnails),
+        focus_mode: Some(config.focus_mode),
+        show_scroll_hints: Some(config.show_scroll_hints),
+        column_guide: config.column_guide,
+        deep_fry_headers: Some(config.deep_fry_headers),
+        deep_fry_images: Some(config.deep_fry_images),
+        max_header_lines: config.max_header_lines,
+        code_fold_threshold: config.code_fold_threshold,
+        table_min_column_width: Some(config.table_min_column_width),
+        table_max_column_width: config.table_max_column_width,
+        search_match_fg: Some(config.search_colors.fg),
+        search_match_bg: Some(config.search_colors.bg),
+        search_current_bg: Some(config.search_colors.current_bg),
+        quote_gutter_color: Some(config.quote_gutter_color),
+        image_link_color: Some(config.image_link_color),
+        distinct_image_links: Some(config.distinct_image_links),
+        enter_action: Some(config.enter_action),
+        header_width: Some(config.header_width),
+    }
+}
@@ -31,4 +31,6 @@ pub struct CursorPointer {
 pub struct SearchState {
     pub needle: String,
     pub accepted: bool,
+    // The needle is a regular expression, instead of literal text.
+    pub regex: bool,
 }
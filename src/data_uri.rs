@@ -0,0 +1,69 @@
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use image::ImageFormat;
+
+// Whether the image is embedded in the url, like `data:image/png;base64,iVBO...`.
+pub fn is_data_uri(url: &str) -> bool {
+    url.starts_with("data:")
+}
+
+// The bytes and format of a base64 image data uri. `None` if it is malformed, not an image format
+// we know, or decodes to more than `max_bytes`, see `Config::max_data_uri_bytes`.
+pub fn decode(url: &str, max_bytes: usize) -> Option<(Vec<u8>, ImageFormat)> {
+    let (header, data) = url.strip_prefix("data:")?.split_once(',')?;
+    let mut params = header.split(';');
+    let format = ImageFormat::from_mime_type(params.next()?.trim().to_ascii_lowercase())?;
+    if !params.any(|param| param.trim().eq_ignore_ascii_case("base64")) {
+        return None;
+    }
+    if data.len() / 4 * 3 > max_bytes {
+        log::warn!("data uri of {} base64 characters is too big", data.len());
+        return None;
+    }
+    Some((base64(data)?, format))
+}
+
+// Whitespace is allowed, like in line-wrapped data uris.
+fn base64(text: &str) -> Option<Vec<u8>> {
+    let text: String = text.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    STANDARD.decode(text).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::ImageFormat;
+
+    use super::{base64, decode};
+
+    #[test]
+    fn decode_base64() {
+        assert_eq!(base64(""), Some(Vec::new()));
+        assert_eq!(base64("Zg=="), Some(b"f".to_vec()));
+        assert_eq!(base64("Zm8="), Some(b"fo".to_vec()));
+        assert_eq!(base64("Zm9v"), Some(b"foo".to_vec()));
+        assert_eq!(
+            base64("IyDDnGJl\ncgo="),
+            Some("# Über\n".as_bytes().to_vec())
+        );
+        assert_eq!(base64("Zm8"), None, "missing padding");
+        assert_eq!(base64("Zm9vY"), None, "a lone character");
+        assert_eq!(base64("Zm9v!"), None);
+        assert_eq!(base64("-_8="), None, "the url-safe alphabet");
+    }
+
+    #[test]
+    fn data_uris() {
+        assert_eq!(
+            decode("data:image/png;base64,Zm9v", 100),
+            Some((b"foo".to_vec(), ImageFormat::Png))
+        );
+        assert_eq!(
+            decode("data:image/gif;name=x.gif;base64,Zm9v", 100),
+            Some((b"foo".to_vec(), ImageFormat::Gif)),
+            "with parameters"
+        );
+        assert_eq!(decode("data:image/png,foo", 100), None, "not base64");
+        assert_eq!(decode("data:text/plain;base64,Zm9v", 100), None);
+        assert_eq!(decode("data:image/png;base64", 100), None, "no data");
+        assert_eq!(decode("data:image/png;base64,Zm9v", 2), None, "too big");
+    }
+}
@@ -5,15 +5,19 @@ use log::Record;
 use ratatui::{Frame, crossterm::style::Color, layout::Rect, widgets::Block};
 use ratskin::RatSkin;
 
-pub fn ui_logger(log_to_file: bool) -> Result<flexi_logger::LoggerHandle, FlexiLoggerError> {
+// An explicit `level` wins over `RUST_LOG`. The buffer for the snapshot only gets what passes it.
+pub fn ui_logger(
+    log_to_file: bool,
+    level: Option<&str>,
+) -> Result<flexi_logger::LoggerHandle, FlexiLoggerError> {
+    let logger = match level {
+        Some(level) => Logger::try_with_str(level)?,
+        None => Logger::try_with_env_or_str("info")?,
+    };
     if log_to_file {
-        Logger::try_with_env_or_str("info")?
-            .log_to_file(FileSpec::default())
-            .start()
+        logger.log_to_file(FileSpec::default()).start()
     } else {
-        Logger::try_with_env_or_str("info")?
-            .log_to_buffer(10000, Some(markdown_format))
-            .start()
+        logger.log_to_buffer(10000, Some(markdown_format)).start()
     }
 }
 
@@ -59,6 +63,7 @@ pub fn render_snapshot(snapshot: &flexi_logger::Snapshot, frame: &mut Frame) ->
         }
         if let Some(span) = line.spans.get_mut(0) {
             if let Some(color) = match span.content.to_string().as_str() {
+                "TRACE" => Some(ratatui::prelude::Color::DarkGray),
                 "DEBUG" => Some(ratatui::prelude::Color::LightBlue),
                 "INFO" => Some(ratatui::prelude::Color::Green),
                 "WARN" => Some(ratatui::prelude::Color::Yellow),
@@ -0,0 +1,108 @@
+use std::{
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::error::Error;
+
+// Fenced code blocks that can be rendered to images by an external tool.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Diagram {
+    Mermaid,
+    Dot,
+}
+
+impl Diagram {
+    // From the info string of a code fence, like "```mermaid".
+    pub fn from_info(info: &str) -> Option<Diagram> {
+        match info.split_whitespace().next()? {
+            "mermaid" => Some(Diagram::Mermaid),
+            "dot" | "graphviz" => Some(Diagram::Dot),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Diagram::Mermaid => "mmd",
+            Diagram::Dot => "dot",
+        }
+    }
+}
+
+impl Display for Diagram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagram::Mermaid => write!(f, "mermaid"),
+            Diagram::Dot => write!(f, "dot"),
+        }
+    }
+}
+
+// Whether `command` is a path to, or an executable somewhere in `PATH`.
+pub fn command_available(command: &str) -> bool {
+    if command.is_empty() {
+        return false;
+    }
+    let path = Path::new(command);
+    if path.components().count() > 1 {
+        return path.is_file();
+    }
+    env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths)
+            .any(|dir| dir.join(command).is_file() || dir.join(format!("{command}.exe")).is_file())
+    })
+}
+
+// Render the diagram `source` with `command` into a png in `dir`, returning its path.
+pub fn render(
+    diagram: Diagram,
+    command: &str,
+    source: &str,
+    dir: &Path,
+    name: &str,
+) -> Result<PathBuf, Error> {
+    fs::create_dir_all(dir)?;
+    let input = dir.join(format!("{name}.{}", diagram.extension()));
+    let output = dir.join(format!("{name}.png"));
+    fs::write(&input, source)?;
+
+    let mut cmd = Command::new(command);
+    match diagram {
+        Diagram::Mermaid => cmd.arg("--quiet").arg("-i").arg(&input).arg("-o"),
+        Diagram::Dot => cmd.arg("-Tpng").arg(&input).arg("-o"),
+    };
+    let result = cmd.arg(&output).output()?;
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        return Err(Error::Diagram(format!(
+            "{command} failed ({}): {}",
+            result.status,
+            stderr.trim()
+        )));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagram, command_available};
+
+    #[test]
+    fn from_info() {
+        assert_eq!(Some(Diagram::Mermaid), Diagram::from_info("mermaid"));
+        assert_eq!(Some(Diagram::Dot), Diagram::from_info("graphviz {.class}"));
+        assert_eq!(None, Diagram::from_info("rust"));
+        assert_eq!(None, Diagram::from_info(""));
+    }
+
+    #[test]
+    fn missing_command() {
+        assert!(!command_available(""));
+        assert!(!command_available("mdfried-no-such-command"));
+        assert!(!command_available("/no/such/command"));
+    }
+}
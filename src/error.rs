@@ -2,7 +2,9 @@ use core::fmt;
 use std::{
     error::Error as _,
     io,
+    path::PathBuf,
     sync::{PoisonError, mpsc::SendError},
+    time::Duration,
 };
 
 use color_eyre::eyre::InstallError;
@@ -28,7 +30,15 @@ pub enum Error {
     NoFont,
     Thread(String),
     UnknownImage(usize, String),
+    ImageTooBig(u32, u32),
     Notify(notify::Error),
+    Diagram(String),
+    OpenCommand(String),
+    Clipboard(String),
+    StdinTooLarge(u64),
+    StdinTimeout(Duration),
+    SocketInUse(PathBuf),
+    NotDownloaded(String),
     // Do not overuse this one!
     Generic(String),
 }
@@ -56,7 +66,23 @@ impl fmt::Display for Error {
             Error::NoFont => write!(f, "No font available"),
             Error::Thread(msg) => write!(f, "Thread error: {msg}"),
             Error::UnknownImage(_, url) => write!(f, "Unknown image format: {url}"),
+            Error::ImageTooBig(width, height) => {
+                write!(f, "Image of {width}x{height} pixels is too big")
+            }
             Error::Notify(err) => write!(f, "Watch error: {err}"),
+            Error::Diagram(msg) => write!(f, "Diagram rendering error: {msg}"),
+            Error::OpenCommand(msg) => write!(f, "Open command error: {msg}"),
+            Error::Clipboard(msg) => write!(f, "Clipboard error: {msg}"),
+            Error::StdinTooLarge(max_bytes) => {
+                write!(f, "Stdin is larger than {max_bytes} bytes")
+            }
+            Error::StdinTimeout(timeout) => {
+                write!(f, "Stdin did not finish within {}s", timeout.as_secs())
+            }
+            Error::SocketInUse(path) => {
+                write!(f, "Another instance is listening on {}", path.display())
+            }
+            Error::NotDownloaded(url) => write!(f, "Image has not been downloaded: {url}"),
             Error::Generic(msg) => write!(f, "Generic error: {msg}"),
         }
     }
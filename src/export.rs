@@ -0,0 +1,474 @@
+use std::fmt::Write as _;
+
+use minimad::{Composite, CompositeStyle, Compound, Line, Options, parse_text};
+use ratatui::layout::Alignment;
+use regex::Regex;
+
+use crate::markdown::{Block, ListItem, ParseOptions, header_spans, split_headers_and_images};
+
+// Convert markdown to a standalone HTML document, without any terminal rendering.
+// Images reference their original urls.
+pub fn html(text: &str, options: &ParseOptions) -> String {
+    let blocks = split_headers_and_images(text, options.render_html);
+    let title = blocks
+        .iter()
+        .find_map(|block| match block {
+            Block::Header(_, text) => Some(escape(&strip_emphasis(text))),
+            _ => None,
+        })
+        .unwrap_or_else(|| String::from("mdfried"));
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        blocks_html(&blocks, options)
+    )
+}
+
+fn blocks_html(blocks: &[Block], options: &ParseOptions) -> String {
+    let mut html = String::new();
+    for block in blocks {
+        match block {
+            Block::Header(tier, text) => {
+                let tier = (*tier).clamp(1, 6);
+                writeln!(html, "<h{tier}>{}</h{tier}>", header_html(text))
+                    .expect("write to string");
+            }
+            Block::Image(alt, url, _) if !is_safe_url(url, true) => {
+                writeln!(html, "<p>{}</p>", escape(alt)).expect("write to string");
+            }
+            Block::Image(alt, url, title) => {
+                let title = if title.is_empty() {
+                    String::new()
+                } else {
+                    format!(" title=\"{}\"", escape(title))
+                };
+                writeln!(
+                    html,
+                    "<p><img src=\"{}\" alt=\"{}\"{title}></p>",
+                    escape(url),
+                    escape(alt)
+                )
+                .expect("write to string");
+            }
+            Block::Markdown(text) => html.push_str(&markdown_html(text)),
+            Block::Details(summary, content) => {
+                let content = split_headers_and_images(content, options.render_html);
+                writeln!(
+                    html,
+                    "<details>\n<summary>{}</summary>\n{}</details>",
+                    escape(summary),
+                    blocks_html(&content, options)
+                )
+                .expect("write to string");
+            }
+            Block::Table(rows, alignments) => html.push_str(&table_html(rows, alignments)),
+            Block::List(items, _) => html.push_str(&list_html(items)),
+            Block::Quote(depth, text) => {
+                let depth = usize::from(*depth);
+                html.push_str(&"<blockquote>\n".repeat(depth));
+                html.push_str(&markdown_html(text));
+                html.push_str(&"</blockquote>\n".repeat(depth));
+            }
+            Block::Diagram(diagram, source) => {
+                writeln!(
+                    html,
+                    "<pre><code class=\"language-{diagram}\">{}</code></pre>",
+                    escape(source)
+                )
+                .expect("write to string");
+            }
+        }
+    }
+    html
+}
+
+fn table_html(rows: &[Vec<String>], alignments: &[Alignment]) -> String {
+    let mut html = String::from("<table>\n");
+    for (i, row) in rows.iter().enumerate() {
+        let tag = if i == 0 { "th" } else { "td" };
+        html.push_str("<tr>");
+        for (cell, alignment) in row.iter().zip(alignments) {
+            let style = match alignment {
+                Alignment::Center => " style=\"text-align: center\"",
+                Alignment::Right => " style=\"text-align: right\"",
+                _ => "",
+            };
+            let cell = inline_html(&Composite::from_inline(cell).compounds);
+            write!(html, "<{tag}{style}>{cell}</{tag}>").expect("write to string");
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+fn list_html(items: &[ListItem]) -> String {
+    let mut html = String::new();
+    // The closing tags of the nested lists that are open.
+    let mut open = Vec::new();
+    for item in items {
+        let depth = usize::from(item.depth) + 1;
+        while open.len() > depth {
+            html.push_str(open.pop().unwrap_or_default());
+        }
+        while open.len() < depth {
+            match item.number {
+                Some(1) => html.push_str("<ol>\n"),
+                Some(start) => {
+                    writeln!(html, "<ol start=\"{start}\">").expect("write to string");
+                }
+                None => html.push_str("<ul>\n"),
+            }
+            open.push(if item.number.is_some() {
+                "</ol>\n"
+            } else {
+                "</ul>\n"
+            });
+        }
+        let text = inline_html(&Composite::from_inline(&item.text).compounds);
+        let checkbox = match item.task {
+            Some(true) => "<input type=\"checkbox\" checked disabled> ",
+            Some(false) => "<input type=\"checkbox\" disabled> ",
+            None => "",
+        };
+        writeln!(html, "<li>{checkbox}{text}</li>").expect("write to string");
+    }
+    for close in open.into_iter().rev() {
+        html.push_str(close);
+    }
+    html
+}
+
+// What is open while converting consecutive markdown lines.
+#[derive(PartialEq)]
+enum Open {
+    Nothing,
+    Paragraph,
+    Quote,
+    List(u8),
+    Code,
+    Table,
+}
+
+fn markdown_html(text: &str) -> String {
+    let text = parse_text(
+        text,
+        Options {
+            keep_code_fences: true,
+            ..Options::default()
+        },
+    );
+    let mut html = String::new();
+    let mut open = Open::Nothing;
+    let mut in_fence = false;
+    for line in &text.lines {
+        match line {
+            Line::CodeFence(composite) => {
+                if in_fence {
+                    close(&mut html, &mut open);
+                } else {
+                    close(&mut html, &mut open);
+                    let language = composite_text(composite);
+                    if language.trim().is_empty() {
+                        html.push_str("<pre><code>");
+                    } else {
+                        write!(
+                            html,
+                            "<pre><code class=\"language-{}\">",
+                            escape(language.trim())
+                        )
+                        .expect("write to string");
+                    }
+                    open = Open::Code;
+                }
+                in_fence = !in_fence;
+            }
+            Line::Normal(composite) if composite.style == CompositeStyle::Code => {
+                if open != Open::Code {
+                    close(&mut html, &mut open);
+                    html.push_str("<pre><code>");
+                    open = Open::Code;
+                } else if !html.ends_with('>') {
+                    html.push('\n');
+                }
+                html.push_str(&escape(&composite_text(composite)));
+            }
+            Line::Normal(composite) if composite.compounds.is_empty() => {
+                close(&mut html, &mut open);
+            }
+            Line::Normal(composite) => match composite.style {
+                CompositeStyle::Paragraph => {
+                    if open == Open::Paragraph {
+                        html.push('\n');
+                    } else {
+                        close(&mut html, &mut open);
+                        html.push_str("<p>");
+                        open = Open::Paragraph;
+                    }
+                    html.push_str(&inline_html(&composite.compounds));
+                }
+                CompositeStyle::Quote => {
+                    if open == Open::Quote {
+                        html.push('\n');
+                    } else {
+                        close(&mut html, &mut open);
+                        html.push_str("<blockquote>");
+                        open = Open::Quote;
+                    }
+                    html.push_str(&inline_html(&composite.compounds));
+                }
+                CompositeStyle::ListItem(depth) => {
+                    let depth = depth + 1;
+                    let current = match open {
+                        Open::List(current) => current,
+                        _ => {
+                            close(&mut html, &mut open);
+                            0
+                        }
+                    };
+                    for _ in current..depth {
+                        html.push_str("<ul>\n");
+                    }
+                    for _ in depth..current {
+                        html.push_str("</ul>\n");
+                    }
+                    writeln!(html, "<li>{}</li>", inline_html(&composite.compounds))
+                        .expect("write to string");
+                    open = Open::List(depth);
+                }
+                CompositeStyle::Header(tier) => {
+                    close(&mut html, &mut open);
+                    let tier = tier.clamp(1, 6);
+                    writeln!(
+                        html,
+                        "<h{tier}>{}</h{tier}>",
+                        inline_html(&composite.compounds)
+                    )
+                    .expect("write to string");
+                }
+                CompositeStyle::Code => {}
+            },
+            Line::TableRow(row) => {
+                if open != Open::Table {
+                    close(&mut html, &mut open);
+                    html.push_str("<table>\n");
+                    open = Open::Table;
+                }
+                html.push_str("<tr>");
+                for cell in &row.cells {
+                    write!(html, "<td>{}</td>", inline_html(&cell.compounds))
+                        .expect("write to string");
+                }
+                html.push_str("</tr>\n");
+            }
+            Line::TableRule(_) => {
+                // The rows above the rule are the header.
+                if let Some(start) = html.rfind("<table>\n") {
+                    let (before, rows) = html.split_at(start);
+                    let rows = rows.replace("<td>", "<th>").replace("</td>", "</th>");
+                    html = format!("{before}{rows}");
+                }
+            }
+            Line::HorizontalRule => {
+                close(&mut html, &mut open);
+                html.push_str("<hr>\n");
+            }
+        }
+    }
+    close(&mut html, &mut open);
+    html
+}
+
+fn close(html: &mut String, open: &mut Open) {
+    match open {
+        Open::Nothing => {}
+        Open::Paragraph => html.push_str("</p>\n"),
+        Open::Quote => html.push_str("</blockquote>\n"),
+        Open::List(depth) => html.push_str(&"</ul>\n".repeat(usize::from(*depth))),
+        Open::Code => html.push_str("</code></pre>\n"),
+        Open::Table => html.push_str("</table>\n"),
+    }
+    *open = Open::Nothing;
+}
+
+fn composite_text(composite: &Composite) -> String {
+    composite.compounds.iter().map(|c| c.src).collect()
+}
+
+fn inline_html(compounds: &[Compound]) -> String {
+    let mut html = String::new();
+    for compound in compounds {
+        let mut text = if compound.code {
+            format!("<code>{}</code>", escape(compound.src))
+        } else {
+            links_html(compound.src)
+        };
+        if compound.strikeout {
+            text = format!("<del>{text}</del>");
+        }
+        if compound.italic {
+            text = format!("<em>{text}</em>");
+        }
+        if compound.bold {
+            text = format!("<strong>{text}</strong>");
+        }
+        html.push_str(&text);
+    }
+    html
+}
+
+fn header_html(text: &str) -> String {
+    let mut html = String::new();
+    for span in header_spans(text) {
+        let mut text = links_html(&span.text);
+        if span.italic {
+            text = format!("<em>{text}</em>");
+        }
+        if span.bold {
+            text = format!("<strong>{text}</strong>");
+        }
+        html.push_str(&text);
+    }
+    html
+}
+
+fn strip_emphasis(text: &str) -> String {
+    header_spans(text)
+        .into_iter()
+        .map(|span| span.text)
+        .collect()
+}
+
+// Inline images, links and bare urls, escaping the text around them. Links with unsafe urls are
+// only their text.
+#[expect(clippy::string_slice)] // Regex byte ranges are guaranteed to fall between characters.
+fn links_html(text: &str) -> String {
+    let links_re = Regex::new(
+        r#"!\[([^\]]*)\]\(([^)\s]+)\)|\[([^\]]+)\]\(([^)\s]+)\)|(https?://[^\s<>"]*[A-Za-z0-9/#=\-])"#,
+    )
+    .expect("regex");
+    let mut html = String::new();
+    let mut last_end = 0;
+    for captures in links_re.captures_iter(text) {
+        let Some(full_match) = captures.get(0) else {
+            continue;
+        };
+        html.push_str(&escape(&text[last_end..full_match.start()]));
+        last_end = full_match.end();
+        if let (Some(alt), Some(url)) = (captures.get(1), captures.get(2)) {
+            let (alt, url) = (escape(alt.as_str()), url.as_str());
+            if is_safe_url(url, true) {
+                write!(html, "<img src=\"{}\" alt=\"{alt}\">", escape(url))
+                    .expect("write to string");
+            } else {
+                html.push_str(&alt);
+            }
+        } else if let (Some(text), Some(url)) = (captures.get(3), captures.get(4)) {
+            let (text, url) = (escape(text.as_str()), url.as_str());
+            if is_safe_url(url, false) {
+                write!(html, "<a href=\"{}\">{text}</a>", escape(url)).expect("write to string");
+            } else {
+                html.push_str(&text);
+            }
+        } else {
+            let url = escape(full_match.as_str());
+            write!(html, "<a href=\"{url}\">{url}</a>").expect("write to string");
+        }
+    }
+    html.push_str(&escape(&text[last_end..]));
+    html
+}
+
+// Relative urls, and ones with a scheme that cannot run scripts, like not `javascript:`. Images can
+// also be `data:` urls.
+fn is_safe_url(url: &str, image: bool) -> bool {
+    let scheme_re = Regex::new(r"^([A-Za-z][A-Za-z0-9+.\-]*):").expect("regex");
+    let Some(captures) = scheme_re.captures(url) else {
+        return true;
+    };
+    let scheme = captures[1].to_ascii_lowercase();
+    match scheme.as_str() {
+        "http" | "https" | "mailto" => true,
+        "data" => {
+            image
+                && url
+                    .get(5..11)
+                    .is_some_and(|mime| mime.eq_ignore_ascii_case("image/"))
+        }
+        _ => false,
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{blocks_html, html};
+    use crate::markdown::{ParseOptions, split_headers_and_images};
+
+    fn body(text: &str) -> String {
+        blocks_html(
+            &split_headers_and_images(text, true),
+            &ParseOptions::default(),
+        )
+    }
+
+    #[test]
+    fn document() {
+        let html = html("# Title *x*\n\ntext", &ParseOptions::default());
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Title x</title>"));
+        assert!(html.contains("<h1>Title <em>x</em></h1>\n<p>text</p>\n"));
+    }
+
+    #[test]
+    fn paragraphs_and_links() {
+        assert_eq!(
+            "<p>some <strong>bold</strong> and <a href=\"http://a.b\">a link</a>\nnext line</p>\n<p>a &lt;b&gt; <code>[c](d)</code> <a href=\"http://e.f\">http://e.f</a></p>\n",
+            body("some **bold** and [a link](http://a.b)\nnext line\n\na <b> `[c](d)` http://e.f")
+        );
+    }
+
+    #[test]
+    fn unsafe_and_quoted_links() {
+        assert_eq!(
+            "<p>see &quot;<a href=\"http://a.b\">http://a.b</a>&quot;, x and y</p>\n",
+            body("see \"http://a.b\", [x](javascript:alert) and ![y](JavaScript:z)")
+        );
+        assert_eq!("<p>alt</p>\n", body("![alt](javascript:x)"));
+    }
+
+    #[test]
+    fn images() {
+        assert_eq!(
+            "<p><img src=\"./a.png\" alt=\"alt\"></p>\n",
+            body("![alt](./a.png)")
+        );
+    }
+
+    #[test]
+    fn code_blocks() {
+        assert_eq!(
+            "<pre><code class=\"language-rust\">fn x() {\n    &lt;y&gt;\n}</code></pre>\n",
+            body("```rust\nfn x() {\n    <y>\n}\n```")
+        );
+    }
+
+    #[test]
+    fn lists_and_tables() {
+        assert_eq!(
+            "<ul>\n<li>a</li>\n<ul>\n<li>b</li>\n</ul>\n<li>c</li>\n</ul>\n",
+            body("* a\n  * b\n* c")
+        );
+        assert_eq!(
+            "<table>\n<tr><th>h</th><th>i</th></tr>\n<tr><td>1</td><td>2</td></tr>\n</table>\n",
+            body("|h|i|\n|-|-|\n|1|2|")
+        );
+    }
+}
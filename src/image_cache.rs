@@ -0,0 +1,183 @@
+use std::{
+    env, fs,
+    hash::{DefaultHasher, Hash as _, Hasher as _},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use image::ImageFormat;
+
+// The formats that remote images are accepted in, see `ImageSource::fetch`.
+const FORMATS: [ImageFormat; 4] = [
+    ImageFormat::Jpeg,
+    ImageFormat::Png,
+    ImageFormat::WebP,
+    ImageFormat::Gif,
+];
+
+// Downloaded images on disk by url, so that reparsing, resizing, and the next runs do not fetch
+// them again. Loading and storing is blocking.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageCache {
+    pub dir: PathBuf,
+    // Older entries are fetched again, never if `None`.
+    pub ttl: Option<Duration>,
+    // The oldest entries are removed beyond this, see `ImageCache::prune`.
+    pub max_bytes: u64,
+}
+
+impl ImageCache {
+    // `$XDG_CACHE_HOME/mdfried/images`, or under `~/.cache`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let cache_home = env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(cache_home.join("mdfried").join("images"))
+    }
+
+    // The file of `url` in `format`. The hash is not stable across Rust versions, which only
+    // misses the cache after an update.
+    fn path(&self, url: &str, format: ImageFormat) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        let extension = format.extensions_str().first().unwrap_or(&"image");
+        self.dir
+            .join(format!("{:016x}.{extension}", hasher.finish()))
+    }
+
+    pub fn load(&self, url: &str) -> Option<(Vec<u8>, ImageFormat)> {
+        FORMATS.into_iter().find_map(|format| {
+            let path = self.path(url, format);
+            let modified = fs::metadata(&path).and_then(|metadata| metadata.modified());
+            let age = modified.ok()?.elapsed().unwrap_or_default();
+            if self.ttl.is_some_and(|ttl| age > ttl) {
+                log::debug!("stale cached image {url}");
+                return None;
+            }
+            let bytes = fs::read(&path).ok()?;
+            log::debug!("cached image {url}");
+            Some((bytes, format))
+        })
+    }
+
+    // Failing to write the cache only means downloading again. The entry is written to a temp file
+    // first, so that concurrent stores or a crash never leave a truncated entry behind.
+    pub fn store(&self, url: &str, bytes: &[u8], format: ImageFormat) {
+        static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = self.path(url, format);
+        let temp_path = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(&temp_path, bytes))
+            .and_then(|()| fs::rename(&temp_path, &path));
+        if let Err(err) = result {
+            log::warn!("could not cache image {url}: {err}");
+            if let Err(err) = fs::remove_file(&temp_path) {
+                log::debug!("could not remove {}: {err}", temp_path.display());
+            }
+        }
+    }
+
+    // Remove the entry of `url`, like when it does not decode.
+    pub fn remove(&self, url: &str) {
+        for format in FORMATS {
+            let path = self.path(url, format);
+            if path.exists()
+                && let Err(err) = fs::remove_file(&path)
+            {
+                log::warn!("could not remove cached image {url}: {err}");
+            }
+        }
+    }
+
+    // Remove the entries older than the `ttl`, and then the oldest ones until the cache fits into
+    // `max_bytes`. Blocking.
+    pub fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut files: Vec<(PathBuf, SystemTime, u64)> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let metadata = entry.metadata().ok()?;
+                if !metadata.is_file() {
+                    return None;
+                }
+                Some((entry.path(), metadata.modified().ok()?, metadata.len()))
+            })
+            .collect();
+        // Newest first, the oldest are removed from the end.
+        files.sort_by_key(|(_, modified, _)| std::cmp::Reverse(*modified));
+        let mut total = 0;
+        for (path, modified, len) in files {
+            let stale = self
+                .ttl
+                .is_some_and(|ttl| modified.elapsed().unwrap_or_default() > ttl);
+            total += len;
+            if (stale || total > self.max_bytes)
+                && let Err(err) = fs::remove_file(&path)
+            {
+                log::warn!("could not remove cached image {}: {err}", path.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use image::ImageFormat;
+
+    use super::ImageCache;
+
+    #[test]
+    fn store_and_load() {
+        let dir = std::env::temp_dir().join(format!("mdfried_cache_{}", std::process::id()));
+        let mut cache = ImageCache {
+            dir: dir.clone(),
+            ttl: None,
+            max_bytes: 1024,
+        };
+        let url = "https://example.com/a.png";
+        assert_eq!(cache.load(url), None);
+
+        cache.store(url, b"png", ImageFormat::Png);
+        assert_eq!(cache.load(url), Some((b"png".to_vec(), ImageFormat::Png)));
+        assert_eq!(cache.load("https://example.com/b.png"), None);
+
+        cache.remove(url);
+        assert_eq!(cache.load(url), None, "removed");
+
+        cache.store(url, b"png", ImageFormat::Png);
+        cache.ttl = Some(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(cache.load(url), None, "stale");
+        assert_eq!(
+            fs::read_dir(&dir).expect("read_dir").count(),
+            1,
+            "no temp files are left"
+        );
+        cache.prune();
+        assert_eq!(fs::read_dir(&dir).expect("read_dir").count(), 0, "stale");
+
+        cache.ttl = None;
+        cache.store("https://example.com/old.png", &[0; 800], ImageFormat::Png);
+        std::thread::sleep(Duration::from_millis(10));
+        cache.store(url, &[0; 800], ImageFormat::Png);
+        cache.prune();
+        assert_eq!(cache.load(url), Some((vec![0; 800], ImageFormat::Png)));
+        assert_eq!(
+            cache.load("https://example.com/old.png"),
+            None,
+            "the oldest entry is removed beyond max_bytes"
+        );
+
+        fs::remove_dir_all(dir).expect("remove_dir_all");
+    }
+}
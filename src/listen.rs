@@ -0,0 +1,106 @@
+use std::{
+    fs,
+    io::{self, BufRead as _, BufReader},
+    os::unix::{
+        fs::FileTypeExt as _,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    thread::{self, JoinHandle},
+};
+
+use crate::{Event, error::Error};
+
+// Listen on a unix socket for commands from editors, one per line:
+// - `open <path>` opens another file.
+// - `reload` reloads the current file.
+pub fn listen(path: &Path, tx: Sender<Event<'static>>) -> Result<JoinHandle<()>, Error> {
+    // A socket left over from a crashed instance would fail the bind, but one that still accepts
+    // connections belongs to a running instance.
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        match UnixStream::connect(path) {
+            Ok(_) => return Err(Error::SocketInUse(path.to_path_buf())),
+            Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path)?,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    let listener = UnixListener::bind(path)?;
+    log::info!("listening on {path:?}");
+
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(err) = read_commands(stream, &tx) {
+                        log::error!("listen: {err}");
+                        // The receiver is gone when quitting.
+                        if matches!(err, Error::Thread(_)) {
+                            return;
+                        }
+                    }
+                }
+                Err(err) => log::error!("listen accept error: {err}"),
+            }
+        }
+    }))
+}
+
+fn read_commands(stream: UnixStream, tx: &Sender<Event<'static>>) -> Result<(), Error> {
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        match parse_command(&line) {
+            Some(event) => tx.send(event)?,
+            None => log::warn!("listen: unknown command {line:?}"),
+        }
+    }
+    Ok(())
+}
+
+fn parse_command(line: &str) -> Option<Event<'static>> {
+    match line.trim().split_once(' ') {
+        Some(("open", path)) if !path.trim().is_empty() => {
+            Some(Event::OpenFile(PathBuf::from(path.trim())))
+        }
+        None if line.trim() == "reload" => Some(Event::FileChanged),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::net::UnixListener, path::PathBuf, sync::mpsc};
+
+    use super::{listen, parse_command};
+    use crate::{Event, error::Error};
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            parse_command("open /tmp/my file.md\n"),
+            Some(Event::OpenFile(PathBuf::from("/tmp/my file.md")))
+        );
+        assert_eq!(parse_command(" reload "), Some(Event::FileChanged));
+        assert_eq!(parse_command("open "), None);
+        assert_eq!(parse_command("quit"), None);
+    }
+
+    #[test]
+    fn stale_and_live_sockets() {
+        let dir = std::env::temp_dir().join(format!("mdfried_listen_{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("create_dir_all");
+        let path = dir.join("socket");
+        let (tx, _rx) = mpsc::channel();
+
+        let other = UnixListener::bind(&path).expect("bind");
+        assert!(
+            matches!(listen(&path, tx.clone()), Err(Error::SocketInUse(_))),
+            "another instance is listening"
+        );
+        drop(other);
+        // The thread keeps blocking on the socket until the tests end.
+        drop(listen(&path, tx).expect("the stale socket should have been replaced"));
+
+        fs::remove_dir_all(&dir).expect("remove_dir_all");
+    }
+}
@@ -1,9 +1,20 @@
+mod clipboard;
 mod config;
 mod cursor;
+mod data_uri;
 mod debug;
+mod diagram;
 mod error;
+mod export;
+mod image_cache;
+#[cfg(not(windows))]
+mod listen;
 mod markdown;
+mod minimap;
 mod model;
+mod outline;
+mod positions;
+mod recent;
 mod setup;
 mod watch;
 mod widget_sources;
@@ -13,49 +24,91 @@ mod worker;
 use std::os::fd::IntoRawFd as _;
 
 use std::{
+    borrow::Cow,
     fmt::Display,
     fs::{self, File},
-    io::{self, Read as _},
+    io::{self, Read as _, Write as _},
     path::{Path, PathBuf},
     sync::mpsc::{self},
-    time::Duration,
+    thread,
+    time::{Duration, Instant},
 };
 
-use clap::{ArgMatches, arg, command, value_parser};
+use clap::{ArgMatches, Command, arg, command, parser::ValueSource, value_parser};
 use flexi_logger::LoggerHandle;
 use ratatui::{
     DefaultTerminal, Frame, Terminal,
     crossterm::{
         event::{
             self, DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEventKind, KeyModifiers,
-            MouseEventKind,
+            MouseButton, MouseEventKind,
         },
         tty::IsTty as _,
     },
-    layout::{Rect, Size},
+    layout::{Position, Rect, Size},
     prelude::CrosstermBackend,
     style::{Color, Style, Stylize as _},
     text::{Line, Span, Text},
-    widgets::{Block, Paragraph, Widget},
+    widgets::{Block, Borders, Padding, Paragraph, Widget},
 };
 
 use ratatui_image::{Image, picker::ProtocolType};
 use setup::{SetupResult, setup_graphics};
+use unicode_width::UnicodeWidthStr as _;
 
 use crate::{
     config::Config,
     cursor::{Cursor, CursorPointer, SearchState},
+    diagram::Diagram,
     error::Error,
-    model::{DocumentId, Model},
-    watch::watch,
-    widget_sources::{BigText, LineExtra, SourceID, WidgetSource, WidgetSourceData},
+    markdown::{
+        CODE_FOLD_EXPANDED, DETAILS_EXPANDED, ParseOptions, TASK_CHECKED, TASK_UNCHECKED, raw_lines,
+    },
+    model::{DocumentId, Model, Status},
+    recent::RecentMenu,
+    watch::FileWatcher,
+    widget_sources::{
+        BigText, LineExtra, SourceID, WidgetSource, WidgetSourceData, with_link_urls,
+    },
     worker::worker_thread,
 };
 
 const OK_END: &str = " ok.";
 
-fn main() -> io::Result<()> {
-    let mut cmd = command!() // requires `cargo` feature
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+// How often to redraw while something is animating, see `Model::is_animating`.
+const ANIMATION_FRAME: Duration = Duration::from_millis(100);
+
+// Text outside of the current section, see `Model::focus_range`.
+const COLOR_OUT_OF_FOCUS: Color = Color::Indexed(240);
+
+// The backgrounds of kept search highlights, by group, see `Model::keep_search`.
+const COLORS_HIGHLIGHT: [Color; 4] = [
+    Color::Indexed(81),
+    Color::Indexed(213),
+    Color::Indexed(214),
+    Color::Indexed(120),
+];
+
+// The arrows shown with `Config::show_scroll_hints`.
+const COLOR_SCROLL_HINT: Color = Color::Indexed(63);
+
+// The background of the column set with `Config::column_guide`.
+const COLOR_COLUMN_GUIDE: Color = Color::Indexed(235);
+
+// The search bar's sign of regex needles, see `SearchState::regex`.
+const COLOR_REGEX: Color = Color::Indexed(244);
+// The search bar's `3/17` match counter, and the hint at matches off screen.
+const COLOR_SEARCH_COUNT: Color = Color::Indexed(244);
+
+// The status line count of headers and images that are still rendering.
+const COLOR_RENDERING: Color = Color::Indexed(244);
+// Status line messages that are not errors, see `Status::Info`.
+const COLOR_INFO: Color = Color::Indexed(32);
+
+fn cli() -> Command {
+    command!() // requires `cargo` feature
         .arg(arg!(-d --"deep-fry" "Extra deep fried images").value_parser(value_parser!(bool)))
         .arg(arg!(-w --"watch" "Watch markdown file").value_parser(value_parser!(bool)))
         .arg(arg!(-s --"setup" "Force font setup").value_parser(value_parser!(bool)))
@@ -72,10 +125,48 @@ fn main() -> io::Result<()> {
             arg!(--"log" "log to mdfried_<timestamp>.log file in working directory")
                 .value_parser(value_parser!(bool)),
         )
+        .arg(
+            arg!(--"log-level" <LEVEL> "Log level, instead of the RUST_LOG environment variable")
+                .value_parser(["trace", "debug", "info", "warn", "error"]),
+        )
+        .arg(arg!(-v --verbose ... "Log more, -v for debug and -vv for trace"))
+        .arg(
+            arg!(--"stdin-max-bytes" <BYTES> "Abort reading stdin after this many bytes")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"stdin-timeout" <SECONDS> "Abort reading stdin after this many seconds")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"export-html" <FILE> "Write the document as HTML to a file, and exit")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(arg!(-t --"text" <MARKDOWN> "Render the markdown text, instead of a file or stdin"))
+        .arg(
+            arg!(--"basepath" <DIR> "Resolve relative image and link paths against this directory, also for stdin")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"no-cache" "Download remote images again, instead of using the image cache")
+                .value_parser(value_parser!(bool)),
+        )
+        .arg(
+            arg!(--"listen" <SOCKET> "Accept `open <path>` and `reload` commands on a unix socket")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"no-panic-report" "On a crash, print one line instead of a bug report (or set MDFRIED_NO_PANIC_REPORT)")
+                .value_parser(value_parser!(bool)),
+        )
         .arg(
             arg!([path] "The markdown file path, or '-', or omit, for stdin")
                 .value_parser(value_parser!(PathBuf)),
-        );
+        )
+}
+
+fn main() -> io::Result<()> {
+    let mut cmd = cli();
     let matches = cmd.get_matches_mut();
 
     match main_with_args(&matches) {
@@ -106,58 +197,100 @@ fn main_with_args(matches: &ArgMatches) -> Result<(), Error> {
         .display_env_section(true)
         .into_hooks();
     eyre_hook.install()?;
+    // The bug report is noise when scripting, but the terminal must be restored either way.
+    let panic_report = !*matches.get_one("no-panic-report").unwrap_or(&false)
+        && std::env::var_os("MDFRIED_NO_PANIC_REPORT").is_none_or(|value| value.is_empty());
     std::panic::set_hook(Box::new(move |panic_info| {
         if let Err(err) = ratatui::crossterm::terminal::disable_raw_mode() {
             eprintln!("Unable to disable raw mode: {:?}", err);
         }
-        let msg = format!("{}", panic_hook.panic_report(panic_info));
+        let msg = if panic_report {
+            format!("{}", panic_hook.panic_report(panic_info))
+        } else {
+            let payload = panic_info
+                .payload()
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| {
+                    panic_info
+                        .payload()
+                        .downcast_ref::<String>()
+                        .map(String::as_str)
+                })
+                .unwrap_or("unknown cause");
+            match panic_info.location() {
+                Some(location) => format!("mdfried crashed: {payload} ({location})\n"),
+                None => format!("mdfried crashed: {payload}\n"),
+            }
+        };
         log::error!("Panic: {}", msg);
         eprint!("{msg}");
         #[expect(clippy::exit)]
         std::process::exit(libc::EXIT_FAILURE);
     }));
 
+    check_conflicts(matches)?;
+
     if *matches.get_one("print-config").unwrap_or(&false) {
         config::print_default()?;
         return Ok(());
     }
 
-    let ui_logger = debug::ui_logger(*matches.get_one("log").unwrap_or(&false))?;
+    let ui_logger = debug::ui_logger(
+        *matches.get_one("log").unwrap_or(&false),
+        log_level(matches),
+    )?;
 
     let path = matches.get_one::<PathBuf>("path");
+    let text_arg = matches.get_one::<String>("text");
+    let (text, basepath) = read_input(matches)?;
 
-    let (text, basepath) = match path {
-        Some(path) if path.as_os_str() == "-" => {
-            let mut text = String::new();
-            print!("Reading stdin...");
-            io::stdin().read_to_string(&mut text)?;
-            println!("{OK_END}");
-            (text, None)
-        }
-        None => {
-            if io::stdin().is_tty() {
-                return Err(Error::Usage(Some(
-                    "no path nor '-', and stdin is a tty (not a pipe)",
-                )));
-            }
-            let mut text = String::new();
-            print!("Reading stdin...");
-            io::stdin().read_to_string(&mut text)?;
-            println!("{OK_END}");
-            (text, None)
-        }
-        Some(path) => (
-            fs::read_to_string(path)?,
-            path.parent().map(Path::to_path_buf),
-        ),
-    };
+    let mut user_config = config::load_or_ask()?;
+    let mut config = Config::from(user_config.clone());
+    config::check_open_command(&config.open_command)?;
+    let basepath = basepath.or_else(|| config.basepath.clone());
 
-    if text.is_empty() {
-        return Err(Error::Usage(Some("no input or empty")));
+    #[cfg(windows)]
+    if matches.get_one::<PathBuf>("listen").is_some() {
+        return Err(Error::Usage(Some("--listen is not supported on windows")));
     }
 
-    let mut user_config = config::load_or_ask()?;
-    let config = Config::from(user_config.clone());
+    if let Some(export_path) = matches.get_one::<PathBuf>("export-html") {
+        if text.is_empty() {
+            return Err(Error::Usage(Some("no input or empty")));
+        }
+        fs::write(
+            export_path,
+            export::html(&text, &ParseOptions::from(&config)),
+        )?;
+        return Ok(());
+    }
+
+    let recent_menu = if path.is_none() && text_arg.is_none() && io::stdin().is_tty() {
+        if !config.show_recent_on_launch {
+            return Err(Error::Usage(Some(
+                "no path nor '-', and stdin is a tty (not a pipe)",
+            )));
+        }
+        let files = recent::load();
+        if files.is_empty() {
+            return Err(Error::Usage(Some(
+                "no path nor '-', stdin is a tty (not a pipe), and no recent files",
+            )));
+        }
+        Some(RecentMenu::new(files))
+    } else {
+        if text.is_empty() {
+            return Err(Error::Usage(Some("no input or empty")));
+        }
+        if config.show_recent_on_launch
+            && let Some(path) = path
+            && path.as_os_str() != "-"
+        {
+            recent::remember(path, config.recent_files_max);
+        }
+        None
+    };
 
     #[cfg(not(windows))]
     if !io::stdin().is_tty() {
@@ -192,24 +325,42 @@ fn main_with_args(matches: &ArgMatches) -> Result<(), Error> {
             _ => ProtocolType::Halfblocks,
         }));
 
-    let (picker, bg, renderer, has_text_size_protocol) = {
+    let (picker, bg, renderer, has_text_size_protocol, text_only) = {
         let setup_result = setup_graphics(
             &mut user_config,
             force_setup,
             no_cap_checks,
             debug_override_protocol_type,
+            Duration::from_millis(config.capability_query_timeout_milliseconds),
         );
         match setup_result {
             Ok(result) => match result {
                 SetupResult::Aborted => return Err(Error::UserAbort("cancelled setup")),
-                SetupResult::TextSizing(picker, bg) => (picker, bg, None, true),
-                SetupResult::Complete(picker, bg, renderer) => (picker, bg, Some(renderer), false),
+                SetupResult::TextSizing(picker, bg) => (picker, bg, None, true, false),
+                SetupResult::NoFont(picker, bg) => (picker, bg, None, false, false),
+                SetupResult::Complete(picker, bg, renderer) => {
+                    (picker, bg, Some(renderer), false, false)
+                }
+                SetupResult::TextOnly(picker, bg) => (picker, bg, None, false, true),
             },
             Err(err) => return Err(err),
         }
     };
 
+    // Diagrams and thumbnails are images too, leave them as their source.
+    if text_only {
+        config.render_diagrams = false;
+        config.inline_image_thumbnails = false;
+    }
+    let no_cache = *matches.get_one("no-cache").unwrap_or(&false);
+    if no_cache {
+        config.image_cache = false;
+    }
+
+    // `-d` fries everything, the config can pick headers or images.
     let deep_fry = *matches.get_one("deep-fry").unwrap_or(&false);
+    let deep_fry_headers = deep_fry || config.deep_fry_headers;
+    let deep_fry_images = deep_fry || config.deep_fry_images;
 
     let watchmode_path = if *matches.get_one("watch").unwrap_or(&false) {
         path.cloned()
@@ -220,20 +371,26 @@ fn main_with_args(matches: &ArgMatches) -> Result<(), Error> {
     let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
     let (event_tx, event_rx) = mpsc::channel::<Event>();
     let watch_event_tx = event_tx.clone();
+    #[cfg(not(windows))]
+    let listen_event_tx = event_tx.clone();
 
+    let protocol_type = picker.protocol_type();
     let config_max_image_height = config.max_image_height;
     let skin = config.theme.skin.clone();
+    let parse_options = ParseOptions::from(&config);
     let cmd_thread = worker_thread(
-        basepath,
+        basepath.clone(),
         picker,
         renderer,
         skin,
         bg,
         has_text_size_protocol,
-        deep_fry,
+        deep_fry_headers,
+        deep_fry_images,
         cmd_rx,
         event_tx,
         config_max_image_height,
+        parse_options,
     );
 
     ratatui::crossterm::terminal::enable_raw_mode()?;
@@ -244,10 +401,16 @@ fn main_with_args(matches: &ArgMatches) -> Result<(), Error> {
         ratatui::crossterm::execute!(io::stderr(), EnableMouseCapture)?;
     }
     let watch_debounce_milliseconds = config.watch_debounce_milliseconds;
+    // There is no alternate screen, the document stays on the terminal after quitting. Push
+    // whatever was on screen into the scrollback first, so that clearing doesn't wipe it.
+    let height = terminal.size()?.height;
+    terminal.set_cursor_position((0, height.saturating_sub(1)))?;
+    print!("{}", "\n".repeat(height as usize));
+    io::stdout().flush()?;
     terminal.clear()?;
 
     let terminal_size = terminal.size()?;
-    let model = Model::new(
+    let mut model = Model::new(
         bg,
         path.cloned(),
         cmd_tx,
@@ -255,18 +418,47 @@ fn main_with_args(matches: &ArgMatches) -> Result<(), Error> {
         terminal.size()?,
         config,
     );
-    model.open(terminal_size, text)?;
+    model.protocol_type = Some(protocol_type);
+    model.deep_fry = deep_fry_headers || deep_fry_images;
+    model.text_only = text_only;
+    model.no_cache = no_cache;
+    model.basepath = basepath;
+    model.debug = *matches.get_one("log").unwrap_or(&false);
+    if recent_menu.is_some() {
+        model.recent = recent_menu;
+    } else {
+        model.open(terminal_size, text)?;
+    }
 
-    let debouncer = if let Some(path) = watchmode_path {
+    let mut watcher = if let Some(path) = watchmode_path {
         log::info!("watching file");
-        Some(watch(&path, watch_event_tx, watch_debounce_milliseconds)?)
+        Some(FileWatcher::new(
+            path,
+            watch_event_tx,
+            watch_debounce_milliseconds,
+        )?)
     } else {
         drop(watch_event_tx);
         None
     };
 
-    run(&mut terminal, model, &ui_logger)?;
-    drop(debouncer);
+    #[cfg(not(windows))]
+    let listen_path = matches.get_one::<PathBuf>("listen");
+    #[cfg(not(windows))]
+    if let Some(listen_path) = listen_path {
+        // The thread is left blocking on the socket, it ends with the process.
+        listen::listen(listen_path, listen_event_tx)?;
+    }
+
+    run(&mut terminal, &mut model, &mut watcher, &ui_logger)?;
+    model.remember_position();
+    drop(watcher);
+    #[cfg(not(windows))]
+    if let Some(listen_path) = listen_path
+        && let Err(err) = fs::remove_file(listen_path)
+    {
+        log::error!("Failed to remove {}: {err}", listen_path.display());
+    }
 
     // Cursor might be in wird places, prompt or whatever should always show at the bottom now.
     terminal.set_cursor_position((0, terminal_size.height - 1))?;
@@ -282,11 +474,164 @@ fn main_with_args(matches: &ArgMatches) -> Result<(), Error> {
     Ok(())
 }
 
+// The document text from `--text`, the path, or stdin, and the directory of the path.
+fn read_input(matches: &ArgMatches) -> Result<(String, Option<PathBuf>), Error> {
+    let stdin_max_bytes = matches.get_one::<u64>("stdin-max-bytes").copied();
+    let stdin_timeout = matches
+        .get_one::<u64>("stdin-timeout")
+        .map(|seconds| Duration::from_secs(*seconds));
+
+    // Without a file, the config's `basepath` is the fallback.
+    let basepath = matches.get_one::<PathBuf>("basepath").cloned();
+    if let Some(text) = matches.get_one::<String>("text") {
+        return Ok((text.clone(), basepath));
+    }
+    Ok(match matches.get_one::<PathBuf>("path") {
+        Some(path) if path.as_os_str() == "-" => {
+            (read_stdin(stdin_max_bytes, stdin_timeout)?, basepath)
+        }
+        // Without any input, the recent files menu might be shown, once the config is loaded.
+        None if io::stdin().is_tty() => (String::new(), basepath),
+        None => (read_stdin(stdin_max_bytes, stdin_timeout)?, basepath),
+        Some(path) => (
+            fs::read_to_string(path)?,
+            basepath.or_else(|| path.parent().map(Path::to_path_buf)),
+        ),
+    })
+}
+
+// Flag combinations that contradict each other, or where one would be silently ignored.
+fn check_conflicts(matches: &ArgMatches) -> Result<(), Error> {
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let is_stdin = !given("text")
+        && matches
+            .get_one::<PathBuf>("path")
+            .is_none_or(|path| path.as_os_str() == "-");
+
+    if given("print-config")
+        && matches
+            .ids()
+            .any(|id| id != "print-config" && id != "no-panic-report" && given(id.as_str()))
+    {
+        return Err(Error::Usage(Some(
+            "--print-config cannot be combined with other arguments",
+        )));
+    }
+    if given("text") && given("path") {
+        return Err(Error::Usage(Some(
+            "--text cannot be combined with a path or '-'",
+        )));
+    }
+    if given("setup") && given("no-cap-checks") {
+        return Err(Error::Usage(Some(
+            "--setup needs the terminal capabilities, cannot be combined with --no-cap-checks",
+        )));
+    }
+    if given("watch") && (is_stdin || given("text")) {
+        return Err(Error::Usage(Some(
+            "--watch needs a file path, not stdin or --text",
+        )));
+    }
+    if (given("stdin-max-bytes") || given("stdin-timeout")) && !is_stdin {
+        return Err(Error::Usage(Some(
+            "--stdin-max-bytes and --stdin-timeout only apply to reading stdin",
+        )));
+    }
+    if given("log-level") && given("verbose") {
+        return Err(Error::Usage(Some("--log-level cannot be combined with -v")));
+    }
+    if given("export-html") && (given("watch") || given("listen") || given("setup")) {
+        return Err(Error::Usage(Some(
+            "--export-html cannot be combined with --watch, --listen or --setup",
+        )));
+    }
+    Ok(())
+}
+
+// The log level from `--log-level`, or `-v` for debug and `-vv` for trace.
+// `None` leaves it to `RUST_LOG`, or the default.
+fn log_level(matches: &ArgMatches) -> Option<&str> {
+    if let Some(level) = matches.get_one::<String>("log-level") {
+        return Some(level.as_str());
+    }
+    match matches.get_count("verbose") {
+        0 => None,
+        1 => Some("debug"),
+        _ => Some("trace"),
+    }
+}
+
+// Read all of stdin, showing a byte counter, and bail out early on `max_bytes` or `timeout`.
+// Reading happens on a thread so that the counter keeps updating while a pipe stalls.
+fn read_stdin(max_bytes: Option<u64>, timeout: Option<Duration>) -> Result<String, Error> {
+    let (chunk_tx, chunk_rx) = mpsc::channel::<io::Result<Vec<u8>>>();
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        let mut buf = [0; 64 * 1024];
+        loop {
+            let chunk = match stdin.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => Ok(buf[..n].to_vec()),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => Err(err),
+            };
+            let failed = chunk.is_err();
+            if chunk_tx.send(chunk).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let started = Instant::now();
+    let mut bytes = Vec::new();
+    let mut tick = 0;
+    loop {
+        print!(
+            "\rReading stdin... {} {} bytes",
+            SPINNER[tick % SPINNER.len()],
+            bytes.len()
+        );
+        io::stdout().flush()?;
+        tick += 1;
+
+        match chunk_rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(chunk) => bytes.extend(chunk?),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        if let Some(max_bytes) = max_bytes
+            && bytes.len() as u64 > max_bytes
+        {
+            println!();
+            return Err(Error::StdinTooLarge(max_bytes));
+        }
+        if let Some(timeout) = timeout
+            && started.elapsed() > timeout
+        {
+            println!();
+            return Err(Error::StdinTimeout(timeout));
+        }
+    }
+    println!("\rReading stdin... {} bytes{OK_END}", bytes.len());
+
+    String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into())
+}
+
 #[derive(Debug)]
 enum Cmd {
     Parse(DocumentId, u16, String),
     UrlImage(DocumentId, usize, u16, String, String, String),
     Header(DocumentId, usize, u16, u8, String),
+    Diagram(DocumentId, usize, u16, Diagram, String),
+    OpenImage(String),
+    // Download images again instead of reusing the kept downloads, on a hard reload.
+    ForgetDownloads,
+    SetBasepath(Option<PathBuf>),
+    SetProtocol(ProtocolType),
+    SetDeepFry(bool),
+    // The parse options and skin of the edited config file.
+    SetConfig(Box<ParseOptions>, ratskin::MadSkin),
 }
 
 impl Display for Cmd {
@@ -303,6 +648,16 @@ impl Display for Cmd {
                 f,
                 "Cmd::Header({document_id}, {source_id}, {width}, {tier}, {text})"
             ),
+            Cmd::Diagram(document_id, source_id, width, diagram, _) => write!(
+                f,
+                "Cmd::Diagram({document_id}, {source_id}, {width}, {diagram}, _)"
+            ),
+            Cmd::OpenImage(url) => write!(f, "Cmd::OpenImage({url})"),
+            Cmd::ForgetDownloads => write!(f, "Cmd::ForgetDownloads"),
+            Cmd::SetBasepath(basepath) => write!(f, "Cmd::SetBasepath({basepath:?})"),
+            Cmd::SetProtocol(protocol_type) => write!(f, "Cmd::SetProtocol({protocol_type:?})"),
+            Cmd::SetDeepFry(deep_fry) => write!(f, "Cmd::SetDeepFry({deep_fry})"),
+            Cmd::SetConfig(..) => write!(f, "Cmd::SetConfig(_, _)"),
         }
     }
 }
@@ -314,8 +669,22 @@ enum Event<'a> {
     Parsed(DocumentId, WidgetSource<'a>),
     ParseImage(DocumentId, SourceID, String, String, String),
     ParseHeader(DocumentId, SourceID, u8, String),
+    ParseDiagram(DocumentId, SourceID, Diagram, String),
+    Outline(DocumentId, SourceID, u8, String),
+    // A `<details>` summary and the last source id of its content.
+    Details(DocumentId, SourceID, SourceID),
+    // The marker line of a folded code block and the first source id of the hidden lines.
+    CodeFold(DocumentId, SourceID, SourceID),
     Update(DocumentId, Vec<WidgetSource<'a>>),
+    // The thumbnail of an inline image, by source id and column, always an `Image`.
+    Thumbnail(DocumentId, SourceID, u16, WidgetSourceData<'a>),
+    // A header or diagram that the worker does not render, it stays as it was parsed.
+    Unrendered(DocumentId, SourceID),
     FileChanged,
+    // Open another file, from `--listen`.
+    OpenFile(PathBuf),
+    OpenImage(PathBuf),
+    Error(String),
 }
 
 impl Display for Event<'_> {
@@ -346,7 +715,34 @@ impl Display for Event<'_> {
                 write!(f, "Event::ParseHeader({document_id}, {id}, {tier}, {text})")
             }
 
+            Event::ParseDiagram(document_id, id, diagram, _) => {
+                write!(f, "Event::ParseDiagram({document_id}, {id}, {diagram}, _)")
+            }
+
+            Event::Outline(document_id, id, tier, text) => {
+                write!(f, "Event::Outline({document_id}, {id}, {tier}, {text})")
+            }
+            Event::Details(document_id, id, last_id) => {
+                write!(f, "Event::Details({document_id}, {id}, {last_id})")
+            }
+            Event::CodeFold(document_id, id, first_id) => {
+                write!(f, "Event::CodeFold({document_id}, {id}, {first_id})")
+            }
+
+            Event::Thumbnail(document_id, id, column, _) => {
+                write!(f, "Event::Thumbnail({document_id}, {id}, {column}, _)")
+            }
+            Event::Unrendered(document_id, id) => {
+                write!(f, "Event::Unrendered({document_id}, {id})")
+            }
+
             Event::FileChanged => write!(f, "Event::FileChanged"),
+
+            Event::OpenFile(path) => write!(f, "Event::OpenFile({})", path.display()),
+
+            Event::OpenImage(path) => write!(f, "Event::OpenImage({})", path.display()),
+
+            Event::Error(msg) => write!(f, "Event::Error({msg})"),
         }
     }
 }
@@ -357,145 +753,314 @@ impl Display for Event<'_> {
 #[expect(clippy::too_many_lines)]
 fn run<'a>(
     terminal: &mut DefaultTerminal,
-    mut model: Model<'a, 'a>,
+    model: &mut Model<'a, 'a>,
+    watcher: &mut Option<FileWatcher>,
     ui_logger: &LoggerHandle,
 ) -> Result<(), Error> {
-    terminal.draw(|frame| view(&model, frame))?;
+    terminal.draw(|frame| view(model, frame))?;
     let mut screen_size = terminal.size()?;
+    let mut last_frame = Instant::now();
 
     loop {
         let page_scroll_count = model.inner_height(screen_size.height) as i16 - 2;
 
         let (had_events, _) = model.process_events(screen_size.width)?;
+        // `--listen` may have opened another file.
+        if had_events
+            && let Some(watcher) = watcher
+            && let Some(path) = model.file_path()
+        {
+            watcher.follow(path)?;
+        }
+        let had_expired = model.expire_status();
 
-        let mut had_input = false;
-        if event::poll(if had_events {
+        // Only wake up for the next frame while animating, and otherwise just to check for events
+        // of the worker and watcher.
+        let timeout = if had_events {
             Duration::ZERO
+        } else if model.is_animating() {
+            ANIMATION_FRAME.saturating_sub(last_frame.elapsed())
         } else {
-            Duration::from_millis(100)
-        })? {
+            model.idle_poll()
+        };
+        let mut had_input = false;
+        if event::poll(timeout)? {
             had_input = true;
             match event::read()? {
                 event::Event::Key(key) => {
                     if key.kind == KeyEventKind::Press {
-                        match model.cursor {
-                            Cursor::Search(ref mut mode, _) if !mode.accepted => match key.code {
-                                KeyCode::Char('/') if mode.accepted => {
-                                    *mode = SearchState::default();
-                                    model.add_searches(None);
+                        if let Some(menu) = &mut model.recent {
+                            match key.code {
+                                KeyCode::Char('j') | KeyCode::Down => menu.next(),
+                                KeyCode::Char('k') | KeyCode::Up => menu.prev(),
+                                KeyCode::Enter => {
+                                    model.open_recent(screen_size)?;
                                 }
-                                KeyCode::Char(c) => {
-                                    mode.needle.push(c);
-                                    let needle = mode.needle.clone();
-                                    model.add_searches(Some(needle));
+                                KeyCode::Esc | KeyCode::Char('q') => {
+                                    return Ok(());
+                                }
+                                KeyCode::Char('c')
+                                    if key.modifiers.contains(KeyModifiers::CONTROL) =>
+                                {
+                                    return Ok(());
+                                }
+                                _ => {}
+                            }
+                        } else if model.outline_input.is_some() {
+                            match key.code {
+                                KeyCode::Char(c) if c.is_ascii_digit() => {
+                                    model.outline_input_digit(c);
                                 }
                                 KeyCode::Backspace => {
-                                    mode.needle.pop();
-                                    let needle = mode.needle.clone();
-                                    model.add_searches(Some(needle));
+                                    if let Some(input) = &mut model.outline_input {
+                                        input.pop();
+                                    }
                                 }
-                                KeyCode::Esc => {
-                                    model.cursor = Cursor::None;
+                                KeyCode::Enter => {
+                                    model.outline_input_accept();
+                                }
+                                KeyCode::Esc | KeyCode::Char('o' | 'q') => {
+                                    model.outline_input = None;
+                                }
+                                _ => {}
+                            }
+                        } else if model.toc.is_some() {
+                            match key.code {
+                                KeyCode::Char('j') | KeyCode::Down => model.toc_next(),
+                                KeyCode::Char('k') | KeyCode::Up => model.toc_prev(),
+                                KeyCode::Enter => model.toc_accept(),
+                                KeyCode::Esc | KeyCode::Char('t' | 'q') => {
+                                    model.toc = None;
+                                }
+                                _ => {}
+                            }
+                        } else if let Some(input) = &mut model.goto_source_input {
+                            match key.code {
+                                KeyCode::Char(c) if c.is_ascii_digit() => input.push(c),
+                                KeyCode::Backspace => {
+                                    input.pop();
                                 }
                                 KeyCode::Enter => {
-                                    mode.accepted = true;
-                                    model.cursor_next();
+                                    model.goto_source_accept();
+                                }
+                                KeyCode::Esc => {
+                                    model.goto_source_input = None;
                                 }
                                 _ => {}
-                            },
-                            _ => {
-                                match key.code {
-                                    KeyCode::Char('q') => {
-                                        return Ok(());
+                            }
+                        } else {
+                            match model.cursor {
+                                Cursor::Search(ref mut mode, _) if !mode.accepted => match key.code
+                                {
+                                    KeyCode::Char('/') if mode.accepted => {
+                                        *mode = SearchState::default();
+                                        model.add_searches(None);
                                     }
-                                    KeyCode::Char('c')
+                                    KeyCode::Char('r')
                                         if key.modifiers.contains(KeyModifiers::CONTROL) =>
                                     {
-                                        return Ok(());
-                                    }
-                                    KeyCode::Char('r') => {
-                                        model.reload(screen_size)?;
-                                    }
-                                    KeyCode::Char('j') | KeyCode::Down => {
-                                        model.scroll_by(1);
-                                    }
-                                    KeyCode::Char('k') | KeyCode::Up => {
-                                        model.scroll_by(-1);
+                                        model.toggle_search_regex();
                                     }
-                                    KeyCode::Char('d') => {
-                                        model.scroll_by((page_scroll_count + 1) / 2);
+                                    KeyCode::Char(c) => {
+                                        mode.needle.push(c);
+                                        let needle = mode.needle.clone();
+                                        model.add_searches(Some(needle));
                                     }
-                                    KeyCode::Char('u') => {
-                                        model.scroll_by(-(page_scroll_count + 1) / 2);
+                                    KeyCode::Backspace => {
+                                        mode.needle.pop();
+                                        let needle = mode.needle.clone();
+                                        model.add_searches(Some(needle));
                                     }
-                                    KeyCode::Char('f' | ' ') | KeyCode::PageDown => {
-                                        model.scroll_by(page_scroll_count);
-                                    }
-                                    KeyCode::Char('b') | KeyCode::PageUp => {
-                                        model.scroll_by(-page_scroll_count);
-                                    }
-                                    KeyCode::Char('g') => {
-                                        model.scroll = 0;
-                                    }
-                                    KeyCode::Char('G') => {
-                                        model.scroll = model.total_lines().saturating_sub(
-                                            page_scroll_count as u16 + 1, // Why +1?
-                                        );
-                                    }
-                                    KeyCode::Char('/') => {
-                                        model.cursor = Cursor::Search(SearchState::default(), None);
+                                    KeyCode::Esc => {
+                                        model.cursor = Cursor::None;
                                     }
-                                    KeyCode::Char('n') => {
+                                    KeyCode::Enter => {
+                                        mode.accepted = true;
                                         model.cursor_next();
                                     }
-                                    KeyCode::Char('N') => {
-                                        model.cursor_prev();
-                                    }
-                                    KeyCode::F(11) => {
-                                        model.log_snapshot = match model.log_snapshot {
-                                            None => Some(flexi_logger::Snapshot::new()),
-                                            Some(_) => None,
-                                        };
+                                    _ => {}
+                                },
+                                _ => {
+                                    // A count or `g` only applies to the very next key.
+                                    let count = std::mem::take(&mut model.count_input);
+                                    // After `g`, any key but `x` goes to the start first.
+                                    let g_pending = std::mem::take(&mut model.g_pending);
+                                    if g_pending && key.code != KeyCode::Char('x') {
+                                        model.scroll = 0;
                                     }
-                                    KeyCode::Enter => {
-                                        if let Cursor::Links(CursorPointer { id, index }) =
-                                            model.cursor
+                                    match key.code {
+                                        KeyCode::Char(c)
+                                            if c.is_ascii_digit()
+                                                && (c != '0' || !count.is_empty()) =>
                                         {
-                                            let url = model.sources().find_map(|source| {
-                                                if source.id == id {
-                                                    let WidgetSourceData::Line(_, extras) =
-                                                        &source.data
-                                                    else {
-                                                        return None;
-                                                    };
-
-                                                    match extras.get(index) {
-                                                        Some(LineExtra::Link(url, _, _)) => {
-                                                            Some(url.clone())
-                                                        }
-                                                        _ => None,
-                                                    }
-                                                } else {
-                                                    None
-                                                }
-                                            });
-                                            if let Some(url) = url {
-                                                log::debug!("open link_cursor {url}");
-                                                model.open_link(url)?;
+                                            model.count_input = format!("{count}{c}");
+                                        }
+                                        KeyCode::Char('q') => {
+                                            return Ok(());
+                                        }
+                                        KeyCode::Char('c')
+                                            if key.modifiers.contains(KeyModifiers::CONTROL) =>
+                                        {
+                                            return Ok(());
+                                        }
+                                        KeyCode::Char('r') => {
+                                            model.reload(screen_size)?;
+                                        }
+                                        KeyCode::Char('R') => {
+                                            model.hard_reload(screen_size)?;
+                                        }
+                                        KeyCode::Char('j') | KeyCode::Down => {
+                                            model.scroll_by(1);
+                                        }
+                                        KeyCode::Char('k') | KeyCode::Up => {
+                                            model.scroll_by(-1);
+                                        }
+                                        KeyCode::Char('d') => {
+                                            model.scroll_page_by((page_scroll_count + 1) / 2);
+                                        }
+                                        KeyCode::Char('u') => {
+                                            model.scroll_page_by(-(page_scroll_count + 1) / 2);
+                                        }
+                                        KeyCode::Char('f' | ' ') | KeyCode::PageDown => {
+                                            model.scroll_page_by(page_scroll_count);
+                                        }
+                                        KeyCode::Char('b') | KeyCode::PageUp => {
+                                            model.scroll_page_by(-page_scroll_count);
+                                        }
+                                        KeyCode::Char('g') if !g_pending => {
+                                            model.count_input = count;
+                                            model.g_pending = true;
+                                        }
+                                        KeyCode::Char('g') => {}
+                                        KeyCode::Char('x') if g_pending => {
+                                            model.open_link_from_top(count.parse().unwrap_or(1))?;
+                                        }
+                                        KeyCode::Char('G') => {
+                                            model.scroll_to_bottom();
+                                        }
+                                        KeyCode::Char('/') => {
+                                            model.cursor =
+                                                Cursor::Search(SearchState::default(), None);
+                                        }
+                                        KeyCode::Char('o') => {
+                                            model.outline_input = Some(String::new());
+                                        }
+                                        KeyCode::Char('t') => {
+                                            model.open_toc();
+                                        }
+                                        KeyCode::Char('z') => {
+                                            model.set_all_details(false);
+                                        }
+                                        KeyCode::Char('Z') => {
+                                            model.set_all_details(true);
+                                        }
+                                        KeyCode::Backspace => {
+                                            model.go_back(screen_size)?;
+                                        }
+                                        KeyCode::Char('i') => {
+                                            model.open_image()?;
+                                        }
+                                        KeyCode::Char('s') => {
+                                            model.toggle_source(screen_size)?;
+                                        }
+                                        KeyCode::Char('S') => {
+                                            model.toggle_raw();
+                                        }
+                                        KeyCode::Char('F') => {
+                                            model.toggle_focus();
+                                        }
+                                        KeyCode::Char('P') => {
+                                            model.cycle_protocol(screen_size)?;
+                                        }
+                                        KeyCode::Char('D') => {
+                                            model.toggle_deep_fry(screen_size)?;
+                                        }
+                                        KeyCode::Char('A') => {
+                                            model.toggle_animations();
+                                        }
+                                        KeyCode::Char('C') => {
+                                            edit_config(terminal, model, screen_size)?;
+                                        }
+                                        KeyCode::Char('#')
+                                            if model.debug || model.log_snapshot.is_some() =>
+                                        {
+                                            model.goto_source_input = Some(String::new());
+                                        }
+                                        KeyCode::Char('y')
+                                            if matches!(model.cursor, Cursor::Links(_)) =>
+                                        {
+                                            if let Some(LineExtra::Link(url, _, _)) =
+                                                model.cursor_extra()
+                                            {
+                                                model.copy_to_clipboard(
+                                                    &url,
+                                                    format!("Copied {url}"),
+                                                );
                                             }
                                         }
-                                    }
-                                    KeyCode::Esc => {
-                                        if let Cursor::Search(SearchState { accepted, .. }, _) =
-                                            model.cursor
-                                            && accepted
+                                        KeyCode::Char('y') => {
+                                            model.copy_document(false);
+                                        }
+                                        KeyCode::Char('Y') => {
+                                            model.copy_document(true);
+                                        }
+                                        KeyCode::Char('U') => {
+                                            model.inline_link_urls = !model.inline_link_urls;
+                                        }
+                                        KeyCode::Char('n') => {
+                                            model.cursor_next();
+                                        }
+                                        KeyCode::Char('N') => {
+                                            model.cursor_prev();
+                                        }
+                                        // Like moving the focus in browsers, only between links.
+                                        KeyCode::Tab
+                                            if matches!(model.cursor, Cursor::Links(_)) =>
+                                        {
+                                            model.cursor_next();
+                                        }
+                                        KeyCode::BackTab
+                                            if matches!(model.cursor, Cursor::Links(_)) =>
                                         {
-                                            model.cursor = Cursor::None;
-                                        } else if let Cursor::Links(_) = model.cursor {
-                                            model.cursor = Cursor::None;
+                                            model.cursor_prev();
                                         }
+                                        KeyCode::Char('m') => {
+                                            model.keep_search();
+                                        }
+                                        KeyCode::Char('M') => {
+                                            model.clear_highlights();
+                                        }
+                                        KeyCode::F(11) => {
+                                            model.log_snapshot = match model.log_snapshot {
+                                                None => Some(flexi_logger::Snapshot::new()),
+                                                Some(_) => None,
+                                            };
+                                        }
+                                        KeyCode::Enter if model.cursor == Cursor::None => {
+                                            model.enter()?;
+                                        }
+                                        KeyCode::Enter => match model.cursor_extra() {
+                                            Some(LineExtra::Link(url, _, _)) => {
+                                                log::debug!("open link_cursor {url}");
+                                                model.open_link(url)?;
+                                            }
+                                            Some(LineExtra::Task(checked, offset)) => {
+                                                model.toggle_task(screen_size, checked, offset)?;
+                                            }
+                                            _ => {}
+                                        },
+                                        KeyCode::Esc => {
+                                            if let Cursor::Search(SearchState { accepted, .. }, _) =
+                                                model.cursor
+                                                && accepted
+                                            {
+                                                model.cursor = Cursor::None;
+                                            } else if let Cursor::Links(_) = model.cursor {
+                                                model.cursor = Cursor::None;
+                                            }
+                                        }
+                                        _ => {}
                                     }
-                                    _ => {}
                                 }
                             }
                         }
@@ -505,7 +1070,7 @@ fn run<'a>(
                     log::debug!("Resize {new_width},{new_height}");
                     if screen_size.width != new_width || screen_size.height != new_height {
                         screen_size = Size::new(new_width, new_height);
-                        model.reload(screen_size)?;
+                        model.resize(screen_size)?;
                     }
                 }
                 event::Event::Mouse(mouse) => match mouse.kind {
@@ -515,55 +1080,182 @@ fn run<'a>(
                     MouseEventKind::ScrollDown => {
                         model.scroll_by(2);
                     }
+                    MouseEventKind::Down(MouseButton::Left) => {
+                        if let Some(area) = model.minimap_area(screen_size)
+                            && area.contains(Position::new(mouse.column, mouse.row))
+                        {
+                            model.minimap_jump(mouse.row - area.y, area.height);
+                        }
+                    }
                     _ => {}
                 },
                 _ => {}
             }
         }
 
-        if had_events || had_input {
+        let mut had_frame = false;
+        if model.is_animating() && last_frame.elapsed() >= ANIMATION_FRAME {
+            model.animation_frame += 1;
+            model.advance_animations(last_frame.elapsed());
+            last_frame = Instant::now();
+            had_frame = true;
+        }
+
+        if had_events || had_input || had_expired || had_frame {
             if let Some(ref mut snapshot) = model.log_snapshot {
                 ui_logger.update_snapshot(snapshot)?;
             }
-            terminal.draw(|frame| view(&model, frame))?;
+            terminal.draw(|frame| view(model, frame))?;
         }
     }
 }
 
 fn view(model: &Model, frame: &mut Frame) {
     let frame_area = frame.area();
+    let outline_width = model.outline_panel_width(frame_area.width);
+    let source_width = model.source_pane_width(frame_area.width);
+    let minimap_width = model.minimap_width(frame_area.width);
+    let mut content_area = frame_area;
+    content_area.width -= outline_width + source_width + minimap_width;
+
     let mut block = Block::new();
-    let padding = model.block_padding(frame_area);
+    let padding = model.block_padding(content_area);
     block = block.padding(padding);
 
-    if let Some(bg) = model.bg {
+    if let Some(bg) = model.background() {
         block = block.style(Style::default().bg(bg.into()));
     }
 
-    let inner_area = if let Some(snapshot) = &model.log_snapshot {
+    // The area without the padding, for full width headers.
+    let (inner_area, unpadded_area) = if let Some(snapshot) = &model.log_snapshot {
         let area = debug::render_snapshot(snapshot, frame);
         let mut fixed_padding = padding;
         fixed_padding.right = 0;
         block = block.padding(fixed_padding);
-        block.inner(area)
+        (block.inner(area), area)
     } else {
-        block.inner(frame_area)
+        (block.inner(content_area), content_area)
     };
 
-    frame.render_widget(block, frame_area);
+    frame.render_widget(block, content_area);
+
+    // Beneath the content, which keeps the background unless it has its own.
+    if let Some(column) = model.column_guide()
+        && column < inner_area.width
+    {
+        let guide = Rect::new(inner_area.x + column, inner_area.y, 1, inner_area.height);
+        frame
+            .buffer_mut()
+            .set_style(guide, Style::default().bg(COLOR_COLUMN_GUIDE));
+    }
+
+    if let Some(area) = model.minimap_area(frame_area.as_size()) {
+        minimap::render(frame, area, &model.minimap_rows(area.height));
+    }
+
+    if source_width > 0 {
+        let mut source_area = frame_area;
+        source_area.x = content_area.width + minimap_width;
+        source_area.width = source_width;
+        source_area.height -= 1;
+        let source_block = Block::default()
+            .borders(Borders::LEFT)
+            .border_style(Color::Indexed(237))
+            .padding(Padding::horizontal(1));
+        let source = Paragraph::new(model.source())
+            .block(source_block)
+            .scroll((model.source_scroll(), 0));
+        frame.render_widget(source, source_area);
+    }
+
+    if outline_width > 0 {
+        let mut outline_area = frame_area;
+        outline_area.x = content_area.width + minimap_width + source_width;
+        outline_area.width = outline_width;
+        outline_area.height -= 1;
+        outline::render_panel(
+            frame,
+            outline_area,
+            model.outline(),
+            model.current_outline_index(),
+        );
+    }
+
+    if model.raw_view() {
+        // Do not render into the last line, like the sources.
+        let mut raw_area = inner_area;
+        raw_area.height = raw_area.height.saturating_sub(1);
+        let raw = Paragraph::new(raw_lines(model.source())).scroll((model.scroll, 0));
+        frame.render_widget(raw, raw_area);
+    }
 
     let mut cursor_positioned = None;
+    let focus_range = model.focus_range();
 
     let mut y: i16 = 0 - (model.scroll as i16);
     for source in model.sources() {
+        // The raw view replaces the rendered sources.
+        if model.raw_view() {
+            break;
+        }
         if y >= 0 {
+            let out_of_focus = focus_range
+                .is_some_and(|(from, to)| !(from..to).contains(&(y + model.scroll as i16)));
             let y: u16 = y as u16;
             match &source.data {
                 WidgetSourceData::Line(line, extras) => {
-                    let p = Paragraph::new(line.clone());
+                    let mut line = line.clone();
+                    if model.details_expanded(source.id) && model.is_code_fold(source.id) {
+                        line = Line::from(CODE_FOLD_EXPANDED).style(line.style);
+                    } else if model.details_expanded(source.id)
+                        && let Some(glyph) = line.spans.first_mut()
+                    {
+                        glyph.content = DETAILS_EXPANDED.into();
+                    }
+                    if out_of_focus {
+                        for span in &mut line.spans {
+                            span.style = span.style.fg(COLOR_OUT_OF_FOCUS);
+                        }
+                    }
+                    // Search matches are positioned by the original columns, so no urls then.
+                    let (p, extras) = if model.inline_link_urls
+                        && !matches!(model.cursor, Cursor::Search(..))
+                        && model.highlights().is_empty()
+                    {
+                        let (line, extras) = with_link_urls(&line, extras, inner_area.width);
+                        (Paragraph::new(line), Cow::Owned(extras))
+                    } else {
+                        (Paragraph::new(line), Cow::Borrowed(extras.as_slice()))
+                    };
 
                     render_widget(p, source.height, y, inner_area, frame);
 
+                    for extra in extras.iter() {
+                        if let LineExtra::Thumbnail(_, start, end) = extra
+                            && let Some(proto) = model.thumbnail(source.id, *start)
+                        {
+                            let x = frame_area.x + padding.left + *start;
+                            frame.render_widget(Image::new(proto), Rect::new(x, y, end - start, 1));
+                        }
+                    }
+
+                    // Kept highlights stay, also outside of the search mode.
+                    for extra in extras.iter() {
+                        if let LineExtra::SearchMatch(start, end, text, group) = extra
+                            && *group > 0
+                        {
+                            let x = frame_area.x + padding.left + (*start as u16);
+                            let width = *end as u16 - *start as u16;
+                            let color = COLORS_HIGHLIGHT[(group - 1) % COLORS_HIGHLIGHT.len()];
+                            frame.render_widget(
+                                Paragraph::new(text.clone())
+                                    .fg(model.search_colors().fg)
+                                    .bg(color),
+                                Rect::new(x, y, width, 1),
+                            );
+                        }
+                    }
+
                     match &model.cursor {
                         Cursor::Links(CursorPointer { id, index })
                             if *id == source.id && !extras.is_empty() =>
@@ -580,10 +1272,21 @@ fn view(model: &Model, frame: &mut Frame) {
                                 frame.render_widget(link_overlay_widget, area);
                                 cursor_positioned = Some((x, y));
                             }
+                            if let Some(LineExtra::Task(..)) = extras.get(*index)
+                                && let Some((start, glyph)) = task_glyph(&line)
+                            {
+                                let x = frame_area.x + padding.left + start;
+                                let task_overlay_widget = Paragraph::new(glyph)
+                                    .fg(Color::Indexed(15))
+                                    .bg(Color::Indexed(32));
+                                frame.render_widget(task_overlay_widget, Rect::new(x, y, 1, 1));
+                                cursor_positioned = Some((x, y));
+                            }
                         }
                         Cursor::Search(SearchState { .. }, pointer) => {
+                            let colors = model.search_colors();
                             for (i, extra) in extras.iter().enumerate() {
-                                if let LineExtra::SearchMatch(start, end, text) = extra {
+                                if let LineExtra::SearchMatch(start, end, text, 0) = extra {
                                     let x = frame_area.x + padding.left + (*start as u16);
                                     let width = *end as u16 - *start as u16;
                                     let area = Rect::new(x, y, width, 1);
@@ -593,9 +1296,9 @@ fn view(model: &Model, frame: &mut Frame) {
                                         && source.id == *id
                                         && i == *index
                                     {
-                                        link_overlay_widget.fg(Color::Black).bg(Color::Indexed(197))
+                                        link_overlay_widget.fg(colors.fg).bg(colors.current_bg)
                                     } else {
-                                        link_overlay_widget.fg(Color::Black).bg(Color::Indexed(148))
+                                        link_overlay_widget.fg(colors.fg).bg(colors.bg)
                                     };
                                     frame.render_widget(link_overlay_widget, area);
                                     cursor_positioned = Some((x, y));
@@ -605,9 +1308,22 @@ fn view(model: &Model, frame: &mut Frame) {
                         _ => {}
                     }
                 }
-                WidgetSourceData::Image(_, proto) => {
-                    let img = Image::new(proto);
-                    render_widget(img, source.height, y, inner_area, frame);
+                WidgetSourceData::Image(..)
+                | WidgetSourceData::AnimatedImage(..)
+                | WidgetSourceData::HeaderImage(..) => {
+                    if let Some(proto) = source.data.protocol() {
+                        let img = Image::new(proto);
+                        let area = if model.is_full_width_header(source.id) {
+                            Rect {
+                                y: inner_area.y,
+                                height: inner_area.height,
+                                ..unpadded_area
+                            }
+                        } else {
+                            inner_area
+                        };
+                        render_widget(img, source.height, y, area, frame);
+                    }
                 }
                 WidgetSourceData::BrokenImage(url, text) => {
                     let spans = vec![
@@ -620,7 +1336,7 @@ fn view(model: &Model, frame: &mut Frame) {
                     let p = Paragraph::new(text);
                     render_widget(p, height as u16, y, inner_area, frame);
                 }
-                WidgetSourceData::Header(text, tier) => {
+                WidgetSourceData::Header(text, tier, _) => {
                     let big_text = BigText::new(text, *tier);
                     render_widget(big_text, 2, y, inner_area, frame);
                 }
@@ -633,6 +1349,18 @@ fn view(model: &Model, frame: &mut Frame) {
         }
     }
 
+    let (more_above, more_below) = model.scroll_hints();
+    let hint_x = inner_area.right().saturating_sub(1);
+    if more_above {
+        let area = Rect::new(hint_x, inner_area.y, 1, 1);
+        frame.render_widget(Paragraph::new("▲").fg(COLOR_SCROLL_HINT), area);
+    }
+    if more_below && inner_area.height > 1 {
+        // The last line is not rendered into, so on the last line with content.
+        let area = Rect::new(hint_x, inner_area.bottom() - 2, 1, 1);
+        frame.render_widget(Paragraph::new("▼").fg(COLOR_SCROLL_HINT), area);
+    }
+
     match &model.cursor {
         Cursor::None => {
             frame.set_cursor_position((0, frame_area.height - 1));
@@ -649,22 +1377,158 @@ fn view(model: &Model, frame: &mut Frame) {
         }
         Cursor::Search(mode, _) => {
             let mut line = Line::default();
-            line.spans.push(Span::from("/").fg(Color::Indexed(148)));
+            let color = model.search_colors().bg;
+            if mode.regex {
+                line.spans.push(Span::from(".*").fg(COLOR_REGEX));
+            }
+            line.spans.push(Span::from("/").fg(color));
             let mut needle = Span::from(mode.needle.clone());
             if mode.accepted {
-                needle = needle.fg(Color::Indexed(148));
+                needle = needle.fg(color);
             }
             line.spans.push(needle);
-            let width = line.width() as u16;
+            let cursor_x = line.width() as u16;
+            if !mode.needle.is_empty()
+                && let Some((position, total)) = model.search_count()
+            {
+                let color = if total == 0 {
+                    Color::Red
+                } else {
+                    COLOR_SEARCH_COUNT
+                };
+                line.spans
+                    .push(Span::from(format!("  {position}/{total}")).fg(color));
+            }
+            if let Some((above, below)) = model.offscreen_matches() {
+                let hint = match (above, below) {
+                    (true, true) => "  matches above ↑ and below ↓",
+                    (true, false) => "  matches above ↑",
+                    _ => "  matches below ↓",
+                };
+                line.spans.push(Span::from(hint).fg(COLOR_SEARCH_COUNT));
+            }
+            let width = (line.width() as u16).min(frame_area.width);
             let searchbar = Paragraph::new(line);
             frame.render_widget(searchbar, Rect::new(0, frame_area.height - 1, width, 1));
             if !mode.accepted {
-                frame.set_cursor_position((width, frame_area.height - 1));
+                frame.set_cursor_position((cursor_x, frame_area.height - 1));
             } else if cursor_positioned.is_none() {
                 frame.set_cursor_position((0, frame_area.height - 1));
             }
         }
     }
+
+    if let Some(input) = &model.goto_source_input {
+        let line = Line::from(vec![
+            Span::from("#").fg(Color::Indexed(32)),
+            Span::from(input),
+        ]);
+        let width = line.width() as u16;
+        frame.render_widget(
+            Paragraph::new(line),
+            Rect::new(0, frame_area.height - 1, width, 1),
+        );
+        frame.set_cursor_position((width, frame_area.height - 1));
+    }
+
+    if let Some(input) = &model.outline_input {
+        outline::render_overlay(frame, model.outline(), model.current_outline_index(), input);
+    }
+
+    if let Some(selected) = model.toc {
+        outline::render_toc(frame, model.outline(), selected);
+    }
+
+    if let Some(menu) = &model.recent {
+        menu.render(frame);
+    }
+
+    if let Some(status) = model.status() {
+        // Right-aligned, so that it does not cover the search or links bar.
+        let line = match status {
+            Status::Info(msg) => Line::from(msg.as_str()).fg(COLOR_INFO),
+            Status::Error(msg) => Line::from(msg.as_str()).fg(Color::Red),
+        };
+        let width = (line.width() as u16).min(frame_area.width);
+        let area = Rect::new(frame_area.width - width, frame_area.height - 1, width, 1);
+        frame.render_widget(Paragraph::new(line), area);
+    } else if model.pending_renders() > 0 {
+        let line = Line::from(format!(
+            "{} Rendering {} elements",
+            SPINNER[model.animation_frame % SPINNER.len()],
+            model.pending_renders()
+        ))
+        .fg(COLOR_RENDERING);
+        let width = (line.width() as u16).min(frame_area.width);
+        let area = Rect::new(frame_area.width - width, frame_area.height - 1, width, 1);
+        frame.render_widget(Paragraph::new(line), area);
+    }
+}
+
+// Edit the config file in `$VISUAL` or `$EDITOR`, suspending the UI meanwhile, and apply it once the
+// editor exits. Without an editor, the directory of the config file is opened instead.
+fn edit_config(
+    terminal: &mut DefaultTerminal,
+    model: &mut Model,
+    screen_size: Size,
+) -> Result<(), Error> {
+    let path = match config::ensure_file() {
+        Ok(path) => path,
+        Err(err) => {
+            model.show_error(format!("Failed to create the config file: {err}"));
+            return Ok(());
+        }
+    };
+    let Some(editor) = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+    else {
+        model.open_config_dir(&path);
+        return Ok(());
+    };
+
+    let mouse_capture = model.mouse_capture();
+    if mouse_capture {
+        ratatui::crossterm::execute!(io::stderr(), DisableMouseCapture)?;
+    }
+    ratatui::crossterm::terminal::disable_raw_mode()?;
+    // Like `open_command`, the editor may come with arguments.
+    let mut args = editor.split_whitespace();
+    let status = std::process::Command::new(args.next().unwrap_or_default())
+        .args(args)
+        .arg(&path)
+        .status();
+    ratatui::crossterm::terminal::enable_raw_mode()?;
+    if mouse_capture {
+        ratatui::crossterm::execute!(io::stderr(), EnableMouseCapture)?;
+    }
+    terminal.clear()?;
+
+    match status {
+        Ok(status) if status.success() => match config::reload() {
+            Ok(user_config) => model.apply_config(screen_size, Config::from(user_config))?,
+            Err(err) => model.show_error(err.to_string()),
+        },
+        Ok(status) => model.show_error(format!("{editor} exited with {status}")),
+        Err(err) => model.show_error(format!("Failed to run {editor}: {err}")),
+    }
+    Ok(())
+}
+
+// The column and the checkbox glyph of a task list item's line.
+fn task_glyph(line: &Line) -> Option<(u16, &'static str)> {
+    let mut column = 0;
+    for span in &line.spans {
+        for glyph in [TASK_CHECKED, TASK_UNCHECKED] {
+            if let Some(i) = span.content.find(glyph) {
+                let before = span.content.get(..i).unwrap_or_default().width();
+                return Some((u16::try_from(column + before).ok()?, glyph));
+            }
+        }
+        column += span.width();
+    }
+    None
 }
 
 fn render_widget<W: Widget>(widget: W, source_height: u16, y: u16, area: Rect, f: &mut Frame) {
@@ -679,22 +1543,34 @@ fn render_widget<W: Widget>(widget: W, source_height: u16, y: u16, area: Rect, f
 #[cfg(test)]
 #[expect(clippy::unwrap_used)]
 mod tests {
-    use std::{sync::mpsc, thread::JoinHandle};
+    use std::{fs, path::PathBuf, sync::mpsc, thread::JoinHandle};
 
+    use image::DynamicImage;
     use insta::assert_snapshot;
-    use ratatui::{Terminal, backend::TestBackend, layout::Size};
+    use ratatui::{Terminal, backend::TestBackend, layout::Size, style::Color};
     use ratatui_image::picker::{Picker, ProtocolType};
 
     use crate::{
-        Cmd, Event,
+        COLOR_COLUMN_GUIDE, Cmd, Event, check_conflicts, cli,
         config::{Config, UserConfig},
+        cursor::{Cursor, SearchState},
         error::Error,
+        log_level,
+        markdown::ParseOptions,
         model::Model,
-        view,
+        read_input, view,
+        widget_sources::WidgetSourceData,
         worker::worker_thread,
     };
 
     fn setup(config: Config) -> (Model<'static, 'static>, JoinHandle<Result<(), Error>>, Size) {
+        setup_with_basepath(config, None)
+    }
+
+    fn setup_with_basepath(
+        config: Config,
+        basepath: Option<PathBuf>,
+    ) -> (Model<'static, 'static>, JoinHandle<Result<(), Error>>, Size) {
         #[expect(clippy::let_underscore_untyped)]
         let _ = flexi_logger::Logger::try_with_env()
             .unwrap()
@@ -707,16 +1583,18 @@ mod tests {
         let picker = Picker::halfblocks();
         assert_eq!(picker.protocol_type(), ProtocolType::Halfblocks);
         let worker = worker_thread(
-            None,
+            basepath,
             picker,
             None,
             config.theme.skin.clone(),
             None,
             true,
             false,
+            false,
             cmd_rx,
             event_tx,
             config.max_image_height,
+            ParseOptions::from(&config),
         );
 
         let screen_size = (80, 20).into();
@@ -744,7 +1622,7 @@ mod tests {
 
     // Poll until parsed and no pending images.
     fn poll_done(model: &mut Model<'static, 'static>, screen_size: &Size) {
-        while model.pending_image_count > 0 {
+        while model.pending_renders() > 0 {
             model.process_events(screen_size.width).unwrap();
         }
         log::debug!("poll_done completed");
@@ -888,6 +1766,39 @@ Goodbye."#,
         teardown(model, worker);
     }
 
+    #[test]
+    fn hero_image() {
+        let config = UserConfig {
+            max_image_height: Some(4),
+            hero_image: Some(true),
+            ..Default::default()
+        }
+        .into();
+        let (mut model, worker, screen_size) = setup(config);
+
+        model
+            .open(
+                screen_size,
+                String::from(
+                    r#"![image](./assets/NixOS.png)
+Hello
+![image](./assets/NixOS.png)"#,
+                ),
+            )
+            .unwrap();
+        poll_parsed(&mut model, &screen_size);
+        poll_done(&mut model, &screen_size);
+        let heights: Vec<u16> = model
+            .sources()
+            .filter(|source| matches!(source.data, WidgetSourceData::Image(..)))
+            .map(|source| source.height)
+            .collect();
+        assert_eq!(heights.len(), 2);
+        assert!(heights[0] > heights[1], "hero is taller: {heights:?}");
+        assert!(heights[1] <= 4);
+        teardown(model, worker);
+    }
+
     #[test]
     fn duplicate_image() {
         let config = UserConfig {
@@ -932,4 +1843,175 @@ Goodbye.
         assert_snapshot!("duplicate image done", terminal.backend());
         teardown(model, worker);
     }
+
+    #[test]
+    fn conflicting_flags() {
+        let check = |args: &[&str]| {
+            let matches = cli()
+                .try_get_matches_from(std::iter::once("mdfried").chain(args.iter().copied()))
+                .unwrap();
+            check_conflicts(&matches)
+        };
+        check(&["a.md"]).unwrap();
+        check(&["--watch", "a.md"]).unwrap();
+        check(&["--stdin-timeout", "3", "-"]).unwrap();
+        check(&["--print-config"]).unwrap();
+        check(&["-t", "# hi"]).unwrap();
+
+        for args in [
+            &["--print-config", "a.md"][..],
+            &["--setup", "--no-cap-checks", "a.md"],
+            &["--watch"],
+            &["--watch", "-"],
+            &["--stdin-max-bytes", "100", "a.md"],
+            &["--export-html", "a.html", "--watch", "a.md"],
+            &["--text", "# hi", "a.md"],
+            &["--text", "# hi", "-"],
+            &["--text", "# hi", "--watch"],
+            &["--text", "# hi", "--stdin-timeout", "3"],
+            &["--log-level", "warn", "-v", "a.md"],
+        ] {
+            assert!(
+                matches!(check(args), Err(Error::Usage(Some(_)))),
+                "{args:?} should conflict"
+            );
+        }
+    }
+
+    #[test]
+    fn log_levels() {
+        let level = |args: &[&str]| {
+            let matches = cli()
+                .try_get_matches_from(std::iter::once("mdfried").chain(args.iter().copied()))
+                .unwrap();
+            log_level(&matches).map(String::from)
+        };
+        assert_eq!(level(&["a.md"]), None);
+        assert_eq!(level(&["--log-level", "warn"]).as_deref(), Some("warn"));
+        assert_eq!(level(&["-v"]).as_deref(), Some("debug"));
+        assert_eq!(level(&["-vv"]).as_deref(), Some("trace"));
+        assert_eq!(level(&["-vvv"]).as_deref(), Some("trace"));
+        cli()
+            .try_get_matches_from(["mdfried", "--log-level", "loud"])
+            .unwrap_err();
+    }
+
+    #[test]
+    fn search_colors() {
+        let config = UserConfig {
+            search_match_fg: Some(Color::White),
+            search_match_bg: Some(Color::Blue),
+            search_current_bg: Some(Color::Red),
+            ..Default::default()
+        }
+        .into();
+        let (mut model, worker, screen_size) = setup(config);
+        let mut terminal =
+            Terminal::new(TestBackend::new(screen_size.width, screen_size.height)).unwrap();
+
+        model
+            .open(screen_size, String::from("find me\n\nand me"))
+            .unwrap();
+        poll_parsed(&mut model, &screen_size);
+        model.cursor = Cursor::Search(
+            SearchState {
+                needle: String::from("me"),
+                accepted: true,
+                regex: false,
+            },
+            None,
+        );
+        model.add_searches(Some(String::from("me")));
+        model.cursor_next();
+        terminal.draw(|frame| view(&model, frame)).unwrap();
+
+        let buffer = terminal.backend().buffer();
+        assert_eq!(buffer[(5, 0)].fg, Color::White);
+        assert_eq!(buffer[(5, 0)].bg, Color::Red, "current match");
+        assert_eq!(buffer[(4, 2)].fg, Color::White);
+        assert_eq!(buffer[(4, 2)].bg, Color::Blue, "other match");
+
+        teardown(model, worker);
+    }
+
+    #[test]
+    fn column_guide() {
+        let config = UserConfig {
+            column_guide: Some(10),
+            ..Default::default()
+        }
+        .into();
+        let (mut model, worker, screen_size) = setup(config);
+        let mut terminal =
+            Terminal::new(TestBackend::new(screen_size.width, screen_size.height)).unwrap();
+
+        model
+            .open(screen_size, String::from("0123456789abcdef"))
+            .unwrap();
+        poll_parsed(&mut model, &screen_size);
+        terminal.draw(|frame| view(&model, frame)).unwrap();
+
+        let buffer = terminal.backend().buffer();
+        assert_eq!(buffer[(10, 0)].symbol(), "a");
+        assert_eq!(buffer[(10, 0)].bg, COLOR_COLUMN_GUIDE, "beneath the text");
+        assert_eq!(buffer[(10, 5)].bg, COLOR_COLUMN_GUIDE, "below the text");
+        assert_ne!(buffer[(9, 0)].bg, COLOR_COLUMN_GUIDE);
+
+        teardown(model, worker);
+    }
+
+    #[test]
+    fn basepath_argument() {
+        let dir = std::env::temp_dir().join(format!("mdfried_basepath_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        DynamicImage::new_rgb8(8, 8)
+            .save(dir.join("pic.png"))
+            .unwrap();
+        let matches = cli()
+            .try_get_matches_from([
+                "mdfried",
+                "--text",
+                "![pic](pic.png)",
+                "--basepath",
+                dir.to_str().unwrap(),
+            ])
+            .unwrap();
+        let (text, basepath) = read_input(&matches).unwrap();
+        assert_eq!(basepath, Some(dir.clone()));
+
+        let (mut model, worker, screen_size) =
+            setup_with_basepath(UserConfig::default().into(), basepath);
+        model.open(screen_size, text).unwrap();
+        poll_parsed(&mut model, &screen_size);
+        poll_done(&mut model, &screen_size);
+        assert!(
+            model
+                .sources()
+                .any(|source| matches!(source.data, WidgetSourceData::Image(..))),
+            "the image is found in the basepath"
+        );
+
+        teardown(model, worker);
+        fs::remove_dir_all(dir).expect("remove_dir_all");
+    }
+
+    #[test]
+    fn text_argument() {
+        let matches = cli()
+            .try_get_matches_from(["mdfried", "--text", "# Hello\n\nworld"])
+            .unwrap();
+        let (text, basepath) = read_input(&matches).unwrap();
+        assert_eq!(text, "# Hello\n\nworld");
+        assert_eq!(basepath, None);
+
+        let (mut model, worker, screen_size) = setup(UserConfig::default().into());
+        model.open(screen_size, text).unwrap();
+        poll_parsed(&mut model, &screen_size);
+        assert_eq!(
+            model.outline().first().map(|entry| entry.text.as_str()),
+            Some("Hello")
+        );
+        assert!(model.sources().any(|source| source.to_string() == "world"));
+        teardown(model, worker);
+    }
 }
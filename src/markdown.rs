@@ -1,57 +1,368 @@
 mod blocks;
+mod header;
+mod html;
 mod links;
+mod list;
+mod long_lines;
+mod raw;
+mod table;
 
-use ratatui::text::Line;
+pub use blocks::{
+    Block, ListItem, limit_quote_depth, split_headers_and_images, strip_html_comments, task_offsets,
+};
+pub use header::{header_spans, tier_styles};
+pub use list::{TASK_CHECKED, TASK_UNCHECKED};
+pub use raw::raw_lines;
+
+use std::{borrow::Cow, time::Duration};
+
+use itertools::Itertools as _;
+use minimad::{CompositeStyle, Line as MadLine, Text};
+use ratatui::{
+    style::{Color, Stylize as _},
+    text::{Line, Span},
+};
 use ratskin::RatSkin;
 
 use crate::{
     DocumentId, Event, WidgetSource,
-    markdown::blocks::{Block, split_headers_and_images},
-    widget_sources::{BigText, WidgetSourceData},
+    config::{Config, ImageWidth, LinkWrap, LongLineMode, UserConfig},
+    diagram::{Diagram, command_available},
+    image_cache::ImageCache,
+    markdown::{header::strip_markers, html::InlineHtml, long_lines::truncate_long_words},
+    setup::BgColor,
+    widget_sources::{BigText, LineExtra, SourceID, WidgetSourceData},
 };
 
+const COLOR_DETAILS: Color = Color::Indexed(63);
+
+// The parts of `Config` that affect parsing.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    pub render_html: bool,
+    pub show_html_comments: bool,
+    pub show_link_definitions: bool,
+    // Deeper quotes and `<details>` are rendered flat.
+    pub max_nesting_depth: usize,
+    pub link_underline: bool,
+    pub autolink_bare_urls: bool,
+    pub header_emphasis: bool,
+    pub long_line_mode: LongLineMode,
+    pub link_wrap: LinkWrap,
+    pub render_diagrams: bool,
+    pub mermaid_command: String,
+    pub dot_command: String,
+    pub inline_image_thumbnails: bool,
+    pub max_header_lines: Option<usize>,
+    pub code_fold_threshold: Option<usize>,
+    pub table_min_column_width: usize,
+    pub table_max_column_width: Option<usize>,
+    pub image_margin: u16,
+    // Blank lines before and after headers, instead of the one between other blocks.
+    pub header_space_before: u16,
+    pub header_space_after: u16,
+    // Composited behind transparent images, by the worker.
+    pub image_background: Option<BgColor>,
+    // The first image is rendered at the full width, and twice the height, by the worker.
+    pub hero_image: bool,
+    // Bigger images are downscaled before rendering, by the worker.
+    pub max_image_pixels: u64,
+    // Images are rendered at most this wide, by the worker.
+    pub max_image_width: Option<ImageWidth>,
+    // Bigger `data:` uri images are not decoded, by the worker.
+    pub max_data_uri_bytes: usize,
+    // Animated images keep all their frames, by the worker.
+    pub animate_images: bool,
+    // For the http client of the worker.
+    pub image_follow_redirects: bool,
+    pub image_max_redirects: usize,
+    pub quote_gutter_color: Color,
+    // The url color of links to image files, like other links if `None`.
+    pub image_link_color: Option<Color>,
+    // Remote images are fetched through this, see `resized_image_url`.
+    pub image_resize_url_template: Option<String>,
+    pub image_resize_hosts: Vec<String>,
+    // Remote images are looked up here before fetching them, by the worker.
+    pub image_cache: Option<ImageCache>,
+}
+
+impl From<&Config> for ParseOptions {
+    fn from(config: &Config) -> Self {
+        ParseOptions {
+            render_html: config.render_html,
+            show_html_comments: config.show_html_comments,
+            show_link_definitions: config.show_link_definitions,
+            max_nesting_depth: config.max_nesting_depth,
+            link_underline: config.link_underline,
+            autolink_bare_urls: config.autolink_bare_urls,
+            header_emphasis: config.header_emphasis,
+            long_line_mode: config.long_line_mode,
+            link_wrap: config.link_wrap,
+            render_diagrams: config.render_diagrams,
+            mermaid_command: config.mermaid_command.clone(),
+            dot_command: config.dot_command.clone(),
+            inline_image_thumbnails: config.inline_image_thumbnails,
+            max_header_lines: config.max_header_lines,
+            code_fold_threshold: config.code_fold_threshold,
+            table_min_column_width: config.table_min_column_width,
+            table_max_column_width: config.table_max_column_width,
+            image_margin: config.image_margin,
+            header_space_before: config.header_space_before,
+            header_space_after: config.header_space_after,
+            image_background: config.image_background.bg_color(),
+            hero_image: config.hero_image,
+            max_image_pixels: config.max_image_pixels,
+            max_image_width: config.max_image_width,
+            max_data_uri_bytes: config.max_data_uri_bytes,
+            animate_images: config.animate_images,
+            image_follow_redirects: config.image_follow_redirects,
+            image_max_redirects: config.image_max_redirects,
+            quote_gutter_color: config.quote_gutter_color,
+            image_link_color: config
+                .distinct_image_links
+                .then_some(config.image_link_color),
+            image_resize_url_template: config.image_resize_url_template.clone(),
+            image_resize_hosts: config.image_resize_hosts.clone(),
+            image_cache: config
+                .image_cache
+                .then(|| {
+                    config
+                        .image_cache_dir
+                        .clone()
+                        .or_else(ImageCache::default_dir)
+                })
+                .flatten()
+                .map(|dir| ImageCache {
+                    dir,
+                    ttl: config.image_cache_ttl_seconds.map(Duration::from_secs),
+                    max_bytes: config.image_cache_max_megabytes.saturating_mul(1024 * 1024),
+                }),
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions::from(&Config::from(UserConfig::default()))
+    }
+}
+
+impl ParseOptions {
+    // The command that renders `diagram`, if enabled and installed.
+    pub fn diagram_command(&self, diagram: Diagram) -> Option<&str> {
+        let command = match diagram {
+            Diagram::Mermaid => &self.mermaid_command,
+            Diagram::Dot => &self.dot_command,
+        };
+        (self.render_diagrams && command_available(command)).then_some(command.as_str())
+    }
+
+    // The url to fetch a remote image with, to get it at about `width` cells from servers that can
+    // resize. `None` without a template, or if the host is not one of `image_resize_hosts`.
+    pub fn resized_image_url(&self, url: &str, width: u16, font_width: u16) -> Option<String> {
+        let template = self.image_resize_url_template.as_ref()?;
+        let authority = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))?
+            .split(['/', '?', '#'])
+            .next()?;
+        let host = authority.rsplit('@').next().unwrap_or(authority);
+        let host = host.split(':').next().unwrap_or(host);
+        if !self.image_resize_hosts.is_empty()
+            && !self
+                .image_resize_hosts
+                .iter()
+                .any(|resize_host| resize_host.eq_ignore_ascii_case(host))
+        {
+            return None;
+        }
+        let separator = if url.contains('?') { "&" } else { "?" };
+        let pixels = u32::from(width) * u32::from(font_width);
+        // The url last, so that it's not searched for placeholders.
+        Some(
+            template
+                .replace("{width}", &pixels.to_string())
+                .replace("{sep}", separator)
+                .replace("{url}", url),
+        )
+    }
+}
+
+// Glyphs in front of a `<details>` summary.
+pub const DETAILS_COLLAPSED: &str = "▶ ";
+pub const DETAILS_EXPANDED: &str = "▼ ";
+
+// How many lines of a folded code block are shown, see `Config::code_fold_threshold`.
+const CODE_FOLD_PREVIEW_LINES: usize = 5;
+// The marker line of an expanded code fold, replacing the collapsed marker.
+pub const CODE_FOLD_EXPANDED: &str = "▲ press Enter to fold";
+
 pub fn parse<'a>(
     text: &str,
     skin: &RatSkin,
     document_id: DocumentId,
     width: u16,
     has_text_size_protocol: bool,
+    options: &ParseOptions,
 ) -> impl Iterator<Item = Event<'a>> {
-    let mut id = 0;
+    // Tasks point to their marker in the original text, for toggling them in the file. The text is
+    // changed before it is split into blocks, like by resolving link references, and stripping
+    // comments, with the tasks in them.
+    let mut task_offsets = task_offsets(text, !options.show_html_comments).into_iter();
+    parse_from(
+        text,
+        skin,
+        document_id,
+        width,
+        has_text_size_protocol,
+        options,
+        0,
+        0,
+    )
+    .map(move |mut event| {
+        if let Event::Parsed(
+            _,
+            WidgetSource {
+                data: WidgetSourceData::Line(_, extras),
+                ..
+            },
+        ) = &mut event
+        {
+            extras.retain_mut(|extra| match extra {
+                LineExtra::Task(_, offset) => {
+                    task_offsets.next().map(|next| *offset = next).is_some()
+                }
+                _ => true,
+            });
+        }
+        event
+    })
+}
+
+// Parse with source ids starting at `first_id`, for nested content like `<details>`, which is
+// `depth` levels deep.
+#[expect(clippy::too_many_arguments)]
+fn parse_from<'a>(
+    text: &str,
+    skin: &RatSkin,
+    document_id: DocumentId,
+    width: u16,
+    has_text_size_protocol: bool,
+    options: &ParseOptions,
+    first_id: SourceID,
+    depth: usize,
+) -> impl Iterator<Item = Event<'a>> {
+    let mut id = first_id;
 
-    let blocks = split_headers_and_images(text);
+    let mut text = Cow::Borrowed(text);
+    if !options.show_html_comments && text.contains("<!--") {
+        text = Cow::Owned(strip_html_comments(&text));
+    }
+    if let Some(limited) = limit_quote_depth(&text, options.max_nesting_depth) {
+        log::warn!(
+            "quotes nested deeper than {} levels are rendered flat",
+            options.max_nesting_depth
+        );
+        text = Cow::Owned(limited);
+    }
+    if text.contains("]:") {
+        let (resolved, definitions) = links::resolve_link_references(&text);
+        text = Cow::Owned(resolved);
+        // Only for the whole document, not again for nested content.
+        if options.show_link_definitions && depth == 0 && !definitions.is_empty() {
+            text.to_mut()
+                .push_str(&links::link_definitions_section(&definitions));
+        }
+    }
+    let blocks = split_headers_and_images(&text, options.render_html);
+    let inline_html =
+        (options.render_html || options.show_html_comments).then(|| InlineHtml::new(skin));
 
     let mut needs_space = false;
+    let mut after_header = false;
+    let mut link_references = 0;
 
     blocks.into_iter().flat_map(move |block| {
         let mut events = Vec::new();
-        if needs_space {
-            // Send a newline after things like Markdowns and Images, but not after the last block.
-            events = vec![send_parsed(
+
+        // Without the tool, the diagram is shown as the code block that it is.
+        let block = match block {
+            Block::Diagram(diagram, source) if options.diagram_command(diagram).is_none() => {
+                Block::Markdown(format!("```{diagram}\n{source}\n```"))
+            }
+            // Too deeply nested `<details>` are shown as their raw content.
+            Block::Details(summary, content) if depth >= options.max_nesting_depth => {
+                log::warn!(
+                    "details nested deeper than {} levels are rendered flat",
+                    options.max_nesting_depth
+                );
+                Block::Markdown(format!("{summary}\n\n{content}"))
+            }
+            block => block,
+        };
+
+        // Send newlines between blocks, but not before the first one. Headers have their own
+        // spacing, see `Config::header_space_before`.
+        let is_header = matches!(block, Block::Header(..));
+        let space = if after_header {
+            options.header_space_after
+        } else if !needs_space {
+            0
+        } else if is_header {
+            options.header_space_before
+        } else {
+            1
+        };
+        after_header = is_header;
+        for _ in 0..space {
+            events.push(send_parsed(
                 document_id,
                 &mut id,
                 WidgetSourceData::Line(Line::default(), Vec::new()),
                 1,
-            )];
+            ));
         }
 
         match block {
             Block::Header(tier, text) => {
                 needs_space = false;
+                // Does not take up an id, it's just the first id of the header.
+                events.push(Event::Outline(document_id, id, tier, strip_markers(&text)));
                 if has_text_size_protocol {
-                    let (n, d) = BigText::size_ratio(tier);
-                    let scaled_with = width / 2 * u16::from(d) / u16::from(n);
-
-                    // Leverage ratskin/termimad's line-wrapping feature.
-                    // TODO: this is probably inefficient, find something else that simply
-                    // word-wraps.
-                    let madtext = RatSkin::parse_text(&text);
-                    for line in skin.parse(madtext, scaled_with) {
-                        let text = line.to_string();
+                    let wrap = |tier: u8| {
+                        let (n, d) = BigText::size_ratio(tier);
+                        let scaled_with = width / 2 * u16::from(d) / u16::from(n);
+
+                        // Leverage ratskin/termimad's line-wrapping feature.
+                        // TODO: this is probably inefficient, find something else that simply
+                        // word-wraps.
+                        let madtext = RatSkin::parse_text(&text);
+                        skin.parse(madtext, scaled_with)
+                            .into_iter()
+                            .map(|line| line.to_string())
+                            .collect::<Vec<_>>()
+                    };
+                    let mut tier = tier;
+                    let mut lines = wrap(tier);
+                    if let Some(max_lines) = options.max_header_lines {
+                        // Demote to smaller tiers, and cut off if even the smallest is too long.
+                        while tier < 6 && lines.len() > max_lines {
+                            tier += 1;
+                            lines = wrap(tier);
+                        }
+                        if lines.len() > max_lines {
+                            lines.truncate(max_lines);
+                            if let Some(last) = lines.last_mut() {
+                                last.truncate(last.trim_end().len());
+                                last.push('…');
+                            }
+                        }
+                    }
+                    for text in lines {
                         events.push(send_parsed(
                             document_id,
                             &mut id,
-                            WidgetSourceData::Header(text, tier),
+                            WidgetSourceData::Header(text, tier, Vec::new()),
                             2,
                         ));
                     }
@@ -60,18 +371,130 @@ pub fn parse<'a>(
                     events.push(send_event(&mut id, event));
                 }
             }
-            Block::Image(alt, url) => {
+            Block::Image(alt, url, title) => {
                 needs_space = true;
-                let event = Event::ParseImage(document_id, id, url, alt, String::new());
+                image_margin(&mut events, document_id, &mut id, options);
+                let caption = (!title.is_empty()).then(|| Line::from(title.clone()).dim());
+                let event = Event::ParseImage(document_id, id, url, alt, title);
                 events.push(send_event(&mut id, event));
+                if let Some(caption) = caption {
+                    events.push(send_parsed(
+                        document_id,
+                        &mut id,
+                        WidgetSourceData::Line(caption, Vec::new()),
+                        1,
+                    ));
+                }
+                image_margin(&mut events, document_id, &mut id, options);
             }
-            Block::Markdown(text) => {
+            Block::Diagram(diagram, source) => {
                 needs_space = true;
-                let madtext = RatSkin::parse_text(&text);
+                image_margin(&mut events, document_id, &mut id, options);
+                let event = Event::ParseDiagram(document_id, id, diagram, source);
+                events.push(send_event(&mut id, event));
+                image_margin(&mut events, document_id, &mut id, options);
+            }
+            Block::Markdown(mut text) => {
+                needs_space = true;
+                if options.long_line_mode == LongLineMode::Truncate {
+                    text = truncate_long_words(&text, width);
+                }
+                if options.link_wrap == LinkWrap::Reference {
+                    text = links::reference_links(&text, &mut link_references);
+                }
+                let madtext = table::fit_columns(RatSkin::parse_text(&text), width, options);
+                let mut in_comment = false;
+
+                // To fold code blocks, they are formatted on their own to know their lines.
+                let runs: Vec<(bool, Vec<Line>)> = match options.code_fold_threshold {
+                    Some(_) => madtext
+                        .lines
+                        .into_iter()
+                        .chunk_by(is_code_line)
+                        .into_iter()
+                        .map(|(code, lines)| {
+                            let run = Text {
+                                lines: lines.collect(),
+                            };
+                            (code, skin.parse(run, width))
+                        })
+                        .collect(),
+                    None => vec![(false, skin.parse(madtext, width))],
+                };
 
-                for line in skin.parse(madtext, width) {
-                    let (line, links) = links::capture_line(line, &text, width);
+                for (code, lines) in runs {
+                    let line_count = lines.len();
+                    let preview = options
+                        .code_fold_threshold
+                        .filter(|threshold| code && line_count > *threshold)
+                        .map(|threshold| threshold.min(CODE_FOLD_PREVIEW_LINES));
+                    let mut first_hidden_id = None;
+                    for (i, mut line) in lines.into_iter().enumerate() {
+                        if preview == Some(i) {
+                            first_hidden_id = Some(id);
+                        }
+                        if let Some(inline_html) = &inline_html {
+                            if options.render_html {
+                                line = inline_html.capture_kbd(line);
+                            }
+                            if options.show_html_comments {
+                                line = inline_html.dim_comments(line, &mut in_comment);
+                            }
+                        }
+                        let (line, links) = links::capture_line(line, &text, width, options);
 
+                        events.push(send_parsed(
+                            document_id,
+                            &mut id,
+                            WidgetSourceData::Line(line, links),
+                            1,
+                        ));
+                    }
+                    if let (Some(preview), Some(first_hidden_id)) = (preview, first_hidden_id) {
+                        let marker_id = id;
+                        let marker = Line::from(format!(
+                            "... ({} more lines, press Enter to expand)",
+                            line_count - preview
+                        ))
+                        .fg(COLOR_DETAILS);
+                        events.push(send_parsed(
+                            document_id,
+                            &mut id,
+                            WidgetSourceData::Line(marker, Vec::new()),
+                            1,
+                        ));
+                        events.push(Event::CodeFold(document_id, marker_id, first_hidden_id));
+                    }
+                }
+            }
+            Block::Table(rows, alignments) => {
+                needs_space = true;
+                // For urls that get cut off, like the text of a markdown block.
+                let text = rows.iter().flatten().join(" ");
+                for line in table::table_lines(&rows, &alignments, width, skin, options) {
+                    let (line, links) = links::capture_line(line, &text, width, options);
+                    events.push(send_parsed(
+                        document_id,
+                        &mut id,
+                        WidgetSourceData::Line(line, links),
+                        1,
+                    ));
+                }
+            }
+            Block::List(items, loose) => {
+                needs_space = true;
+                let text = items.iter().map(|item| item.text.as_str()).join("\n");
+                for (mut line, task) in list::list_lines(&items, loose, width, skin) {
+                    if options.render_html
+                        && let Some(inline_html) = &inline_html
+                    {
+                        line = inline_html.capture_kbd(line);
+                    }
+                    let (line, mut links) = links::capture_line(line, &text, width, options);
+                    // The offset is set once all blocks are parsed, see `parse`.
+                    if let Some(checked) = task {
+                        links.push(LineExtra::Task(checked, 0));
+                    }
                     events.push(send_parsed(
                         document_id,
                         &mut id,
@@ -80,11 +503,86 @@ pub fn parse<'a>(
                     ));
                 }
             }
+            Block::Quote(depth, text) => {
+                needs_space = true;
+                let gutter = "│ ".repeat(usize::from(depth));
+                let inner_width = width.saturating_sub(2 * u16::from(depth)).max(1);
+                let madtext = RatSkin::parse_text(&text);
+                for line in skin.parse(madtext, inner_width) {
+                    let mut spans = vec![Span::from(gutter.clone()).fg(options.quote_gutter_color)];
+                    spans.extend(line.spans.into_iter().map(|span| span.dim()));
+                    let (line, links) =
+                        links::capture_line(Line::from(spans), &text, width, options);
+                    events.push(send_parsed(
+                        document_id,
+                        &mut id,
+                        WidgetSourceData::Line(line, links),
+                        1,
+                    ));
+                }
+            }
+            Block::Details(summary, content) => {
+                needs_space = true;
+                let summary_id = id;
+                let madtext = RatSkin::parse_text(&summary);
+                let summary_line = skin
+                    .parse(madtext, width.saturating_sub(2))
+                    .into_iter()
+                    .next()
+                    .unwrap_or_default();
+                let mut spans = vec![Span::from(DETAILS_COLLAPSED).fg(COLOR_DETAILS)];
+                spans.extend(summary_line.spans);
+                events.push(send_parsed(
+                    document_id,
+                    &mut id,
+                    WidgetSourceData::Line(Line::from(spans), Vec::new()),
+                    1,
+                ));
+
+                // The content is always parsed, even if collapsed, so that ids stay the same.
+                let content_events: Vec<Event> = parse_from(
+                    &content,
+                    skin,
+                    document_id,
+                    width,
+                    has_text_size_protocol,
+                    options,
+                    id,
+                    depth + 1,
+                )
+                .collect();
+                if let Some(last_id) = content_events.iter().filter_map(source_id).max() {
+                    id = last_id + 1;
+                }
+                events.extend(content_events);
+                events.push(Event::Details(document_id, summary_id, id - 1));
+            }
         }
         events
     })
 }
 
+// Blank lines above or below an image, see `Config::image_margin`.
+fn image_margin(
+    events: &mut Vec<Event>,
+    document_id: DocumentId,
+    id: &mut SourceID,
+    options: &ParseOptions,
+) {
+    for _ in 0..options.image_margin {
+        events.push(send_parsed(
+            document_id,
+            id,
+            WidgetSourceData::Line(Line::default(), Vec::new()),
+            1,
+        ));
+    }
+}
+
+fn is_code_line(line: &MadLine) -> bool {
+    matches!(line, MadLine::Normal(composite) if composite.style == CompositeStyle::Code)
+}
+
 fn send_parsed<'a>(
     document_id: DocumentId,
     id: &mut usize,
@@ -104,6 +602,17 @@ fn send_parsed<'a>(
     )
 }
 
+// The id of the source that an event creates, if any.
+fn source_id(event: &Event) -> Option<SourceID> {
+    match event {
+        Event::Parsed(_, source) => Some(source.id),
+        Event::ParseImage(_, id, ..)
+        | Event::ParseHeader(_, id, ..)
+        | Event::ParseDiagram(_, id, ..) => Some(*id),
+        _ => None,
+    }
+}
+
 fn send_event<'a>(id: &mut usize, ev: Event<'a>) -> Event<'a> {
     *id += 1;
     ev
@@ -112,7 +621,9 @@ fn send_event<'a>(id: &mut usize, ev: Event<'a>) -> Event<'a> {
 #[cfg(test)]
 mod tests {
     use crate::{
+        config::{LinkWrap, LongLineMode},
         markdown::{
+            ParseOptions,
             links::{COLOR_DECOR, COLOR_LINK, COLOR_TEXT},
             parse,
         },
@@ -120,6 +631,7 @@ mod tests {
     };
     use pretty_assertions::assert_eq;
     use ratskin::RatSkin;
+    use unicode_width::UnicodeWidthStr as _;
 
     #[test]
     fn parse_one_basic_line() {
@@ -129,6 +641,7 @@ mod tests {
             DocumentId::default(),
             80,
             true,
+            &ParseOptions::default(),
         )
         .collect();
         let expected = vec![Event::Parsed(
@@ -153,6 +666,7 @@ mod tests {
             DocumentId::default(),
             80,
             true,
+            &ParseOptions::default(),
         )
         .collect();
         let expected = vec![Event::Parsed(
@@ -184,6 +698,7 @@ mod tests {
             DocumentId::default(),
             30,
             true,
+            &ParseOptions::default(),
         )
         .collect();
         let expected = vec![
@@ -233,6 +748,7 @@ mod tests {
             DocumentId::default(),
             30,
             true,
+            &ParseOptions::default(),
         )
         .collect();
 
@@ -337,6 +853,59 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_long_link_as_reference() {
+        let options = ParseOptions {
+            link_wrap: LinkWrap::Reference,
+            ..ParseOptions::default()
+        };
+        let events: Vec<Event> = parse(
+            "[a b](http://link.com/veeeeeeeeeeeeeeeeery/long/tail) and more\n\nnext",
+            &RatSkin::default(),
+            DocumentId::default(),
+            30,
+            true,
+            &options,
+        )
+        .collect();
+        let lines: Vec<String> = events
+            .iter()
+            .filter_map(|ev| match ev {
+                Event::Parsed(_, source) => Some(source.to_string()),
+                _ => None,
+            })
+            .collect();
+        let urls: Vec<&str> = events
+            .iter()
+            .filter_map(|ev| match ev {
+                Event::Parsed(
+                    _,
+                    WidgetSource {
+                        data: WidgetSourceData::Line(_, links),
+                        ..
+                    },
+                ) => links.first(),
+                _ => None,
+            })
+            .filter_map(|extra| match extra {
+                LineExtra::Link(url, _, _) => Some(url.as_str()),
+                LineExtra::SearchMatch(..) | LineExtra::Thumbnail(..) | LineExtra::Task(..) => None,
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "[a b][1] and more",
+                "",
+                "[1]:\u{a0}http://link.com/veeeeeeee",
+                "eeeeeeeeeery/long/tail",
+                "",
+                "next",
+            ],
+            lines
+        );
+        assert_eq!(vec!["http://link.com/veeeeeeeeeeeeeeeeery/long/tail"], urls);
+    }
+
     #[test]
     fn parse_multiple_links_same_line() {
         let events: Vec<Event> = parse(
@@ -345,6 +914,7 @@ mod tests {
             DocumentId::default(),
             80,
             true,
+            &ParseOptions::default(),
         )
         .collect();
 
@@ -377,6 +947,563 @@ mod tests {
         assert_eq!(vec!["http://a.com", "http://b.com"], urls, "finds all URLs");
     }
 
+    fn parsed_lines(text: &str, width: u16, options: &ParseOptions) -> Vec<String> {
+        parse(
+            text,
+            &RatSkin::default(),
+            DocumentId::default(),
+            width,
+            false,
+            options,
+        )
+        .filter_map(|event| match event {
+            Event::Parsed(
+                _,
+                WidgetSource {
+                    data: WidgetSourceData::Line(line, _),
+                    ..
+                },
+            ) => Some(line.to_string()),
+            _ => None,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn long_word_wraps() {
+        let lines = parsed_lines(&"a".repeat(200), 24, &ParseOptions::default());
+        assert_eq!(9, lines.len());
+        assert_eq!(200, lines.iter().map(String::len).sum::<usize>());
+
+        let lines = parsed_lines(&"漢".repeat(200), 24, &ParseOptions::default());
+        assert!(lines.iter().all(|line| line.width() <= 24));
+    }
+
+    #[test]
+    fn long_word_truncates() {
+        let options = ParseOptions {
+            long_line_mode: LongLineMode::Truncate,
+            ..ParseOptions::default()
+        };
+        let lines = parsed_lines(&format!("x {} y", "a".repeat(200)), 24, &options);
+        assert_eq!(
+            vec![
+                String::from("x "),
+                format!("{}…", "a".repeat(23)),
+                String::from("y")
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn diagram_without_tool() {
+        let options = ParseOptions {
+            render_diagrams: true,
+            mermaid_command: String::from("mdfried-no-such-command"),
+            ..ParseOptions::default()
+        };
+        let lines = parsed_lines("```mermaid\ngraph TD\n```", 80, &options);
+        assert_eq!(
+            vec!["graph TD"],
+            lines.iter().map(|l| l.trim_end()).collect::<Vec<_>>()
+        );
+
+        let options = ParseOptions {
+            render_diagrams: false,
+            // Installed, but not enabled.
+            mermaid_command: String::from("sh"),
+            ..ParseOptions::default()
+        };
+        let lines = parsed_lines("```mermaid\ngraph TD\n```", 80, &options);
+        assert_eq!(
+            vec!["graph TD"],
+            lines.iter().map(|l| l.trim_end()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_image_margin() {
+        let options = ParseOptions {
+            image_margin: 1,
+            ..ParseOptions::default()
+        };
+        let events: Vec<String> = parse(
+            "text\n![alt](image.png)\ntext",
+            &RatSkin::default(),
+            DocumentId::default(),
+            80,
+            false,
+            &options,
+        )
+        .map(|event| match event {
+            Event::Parsed(_, source) => format!("{}: {source}", source.id),
+            event => event.to_string(),
+        })
+        .collect();
+        assert_eq!(
+            vec![
+                String::from("0: text"),
+                String::from("1: "),
+                String::from("2: "),
+                String::from("Event::ParseImage(D0.0, 3, image.png, _, _)"),
+                String::from("4: "),
+                String::from("5: "),
+                String::from("6: text"),
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn parse_header_space() {
+        let events = |text: &str, options: &ParseOptions| -> Vec<String> {
+            parse(
+                text,
+                &RatSkin::default(),
+                DocumentId::default(),
+                80,
+                false,
+                options,
+            )
+            .map(|event| match event {
+                Event::Parsed(_, source) => format!("{}: {source}", source.id),
+                event => event.to_string(),
+            })
+            .collect()
+        };
+        assert_eq!(
+            events("text\n# Header\ntext", &ParseOptions::default()),
+            vec![
+                String::from("0: text"),
+                String::from("1: "),
+                String::from("Event::Outline(D0.0, 2, 1, Header)"),
+                String::from("Event::ParseHeader(D0.0, 2, 1, Header)"),
+                String::from("3: text"),
+            ],
+            "one line before, none after by default"
+        );
+
+        let options = ParseOptions {
+            header_space_before: 2,
+            header_space_after: 1,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            events("text\n# Header\ntext", &options),
+            vec![
+                String::from("0: text"),
+                String::from("1: "),
+                String::from("2: "),
+                String::from("Event::Outline(D0.0, 3, 1, Header)"),
+                String::from("Event::ParseHeader(D0.0, 3, 1, Header)"),
+                String::from("4: "),
+                String::from("5: text"),
+            ]
+        );
+        assert_eq!(
+            events("# Header\n# Header", &options),
+            vec![
+                String::from("Event::Outline(D0.0, 0, 1, Header)"),
+                String::from("Event::ParseHeader(D0.0, 0, 1, Header)"),
+                String::from("1: "),
+                String::from("Event::Outline(D0.0, 2, 1, Header)"),
+                String::from("Event::ParseHeader(D0.0, 2, 1, Header)"),
+            ],
+            "nothing before the first header, and only the space after between headers"
+        );
+    }
+
+    #[test]
+    fn parse_image_caption() {
+        let events: Vec<String> = parse(
+            "![alt](image.png \"A caption\")\ntext",
+            &RatSkin::default(),
+            DocumentId::default(),
+            80,
+            false,
+            &ParseOptions::default(),
+        )
+        .map(|event| match event {
+            Event::Parsed(_, source) => format!("{}: {source}", source.id),
+            event => event.to_string(),
+        })
+        .collect();
+        assert_eq!(
+            vec![
+                String::from("Event::ParseImage(D0.0, 0, image.png, _, _)"),
+                String::from("1: A caption"),
+                String::from("2: "),
+                String::from("3: text"),
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn parse_details() {
+        let events: Vec<Event> = parse(
+            "<details><summary>Sum</summary>\n\nhidden\n</details>\nafter",
+            &RatSkin::default(),
+            DocumentId::default(),
+            80,
+            false,
+            &ParseOptions::default(),
+        )
+        .collect();
+        let summary: Vec<String> = events
+            .iter()
+            .map(|event| match event {
+                Event::Parsed(_, source) => format!("{}: {source}", source.id),
+                event => event.to_string(),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                String::from("0: ▶ Sum"),
+                String::from("1: hidden"),
+                String::from("Event::Details(D0.0, 0, 1)"),
+                String::from("2: "),
+                String::from("3: after"),
+            ],
+            summary
+        );
+    }
+
+    #[test]
+    fn parse_html_comments() {
+        let text = "before <!-- inline --> after\n<!-- TODO:\nmore notes\n-->\nend\n```\n<!-- code -->\n```";
+        assert_eq!(
+            parsed_lines(text, 80, &ParseOptions::default())
+                .iter()
+                .map(|line| line.trim_end())
+                .collect::<Vec<_>>(),
+            vec!["before  after", "end", "<!-- code -->"],
+        );
+
+        let options = ParseOptions {
+            show_html_comments: true,
+            ..ParseOptions::default()
+        };
+        let lines = parsed_lines(text, 80, &options);
+        assert!(
+            lines.iter().any(|line| line.contains("more notes")),
+            "comment not shown: {lines:#?}"
+        );
+    }
+
+    #[test]
+    fn parse_link_definitions() {
+        let text = "A [link][1].\n[1]: http://a";
+        let lines = parsed_lines(text, 80, &ParseOptions::default());
+        assert_eq!(
+            lines.iter().map(|line| line.trim_end()).collect::<Vec<_>>(),
+            vec!["A [link](http://a)."],
+        );
+
+        let options = ParseOptions {
+            show_link_definitions: true,
+            ..ParseOptions::default()
+        };
+        let events: Vec<Event> = parse(
+            text,
+            &RatSkin::default(),
+            DocumentId::default(),
+            80,
+            false,
+            &options,
+        )
+        .collect();
+        assert!(
+            events.iter().any(
+                |event| matches!(event, Event::ParseHeader(_, _, 2, text) if text == "References")
+            ),
+            "no References header"
+        );
+        let lines = parsed_lines(text, 80, &options);
+        assert!(
+            lines.iter().any(|line| line.contains("[1](http://a)")),
+            "definition not listed: {lines:#?}"
+        );
+    }
+
+    #[test]
+    fn parse_deep_nesting() {
+        let options = ParseOptions::default();
+        let text = format!("{}deep", "> ".repeat(100));
+        let lines = parsed_lines(&text, 80, &options);
+        assert_eq!(1, lines.len(), "not wrapped: {lines:#?}");
+        assert_eq!(
+            options.max_nesting_depth,
+            lines[0].matches('│').count(),
+            "one gutter per level: {lines:#?}"
+        );
+
+        let text = format!(
+            "{}deep{}",
+            "<details>\n".repeat(100),
+            "\n</details>".repeat(100)
+        );
+        let events: Vec<Event> = parse(
+            &text,
+            &RatSkin::default(),
+            DocumentId::default(),
+            80,
+            false,
+            &options,
+        )
+        .collect();
+        let details = events
+            .iter()
+            .filter(|event| matches!(event, Event::Details(..)))
+            .count();
+        assert_eq!(options.max_nesting_depth, details);
+    }
+
+    #[test]
+    fn resized_image_urls() {
+        let mut options = ParseOptions::default();
+        assert_eq!(
+            options.resized_image_url("https://cdn.x/a.png", 40, 10),
+            None
+        );
+
+        options.image_resize_url_template = Some(String::from("{url}{sep}w={width}"));
+        assert_eq!(
+            options.resized_image_url("https://cdn.x/a.png", 40, 10),
+            Some(String::from("https://cdn.x/a.png?w=400"))
+        );
+        assert_eq!(
+            options.resized_image_url("https://cdn.x/a.png?v=2", 40, 10),
+            Some(String::from("https://cdn.x/a.png?v=2&w=400"))
+        );
+        assert_eq!(options.resized_image_url("./a.png", 40, 10), None);
+
+        options.image_resize_hosts = vec![String::from("CDN.x")];
+        assert_eq!(
+            options.resized_image_url("http://user@cdn.x:8080/a.png", 2, 10),
+            Some(String::from("http://user@cdn.x:8080/a.png?w=20"))
+        );
+        assert_eq!(
+            options.resized_image_url("https://other.x/a.png", 40, 10),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_lists() {
+        let text = "- one two three four five six\n  - nested\n1. x\n\n7. y";
+        assert_eq!(
+            parsed_lines(text, 16, &ParseOptions::default())
+                .iter()
+                .map(|line| line.trim_end())
+                .collect::<Vec<_>>(),
+            vec![
+                "• one two three",
+                "  four five six",
+                "  • nested",
+                "",
+                "1. x",
+                "",
+                "2. y",
+            ],
+        );
+    }
+
+    #[test]
+    fn parse_tasks() {
+        let text = "[ref]: http://ref\n\n- [ ] a [link][ref]\n- [x] b";
+        assert_eq!(
+            parsed_lines(text, 80, &ParseOptions::default())
+                .iter()
+                .map(|line| line.trim_end())
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>(),
+            vec!["☐ a link", "☑ b"],
+        );
+        let tasks: Vec<LineExtra> = parse(
+            text,
+            &RatSkin::default(),
+            DocumentId::default(),
+            80,
+            false,
+            &ParseOptions::default(),
+        )
+        .filter_map(|event| match event {
+            Event::Parsed(
+                _,
+                WidgetSource {
+                    data: WidgetSourceData::Line(_, extras),
+                    ..
+                },
+            ) => extras.last().cloned(),
+            _ => None,
+        })
+        .collect();
+        assert_eq!(
+            tasks,
+            vec![LineExtra::Task(false, 21), LineExtra::Task(true, 41)],
+            "offsets in the original text"
+        );
+    }
+
+    #[test]
+    fn parse_quotes() {
+        let options = ParseOptions::default();
+        let text = "> quoted text that wraps\n>> nested\n> ```\n> code\n> ```";
+        let lines: Vec<Line> = parse(
+            text,
+            &RatSkin::default(),
+            DocumentId::default(),
+            20,
+            false,
+            &options,
+        )
+        .filter_map(|event| match event {
+            Event::Parsed(
+                _,
+                WidgetSource {
+                    data: WidgetSourceData::Line(line, _),
+                    ..
+                },
+            ) => Some(line),
+            _ => None,
+        })
+        .collect();
+        assert_eq!(
+            lines
+                .iter()
+                .map(|line| line.to_string().trim_end().to_owned())
+                .collect::<Vec<_>>(),
+            vec![
+                "│ quoted text that",
+                "│ wraps",
+                "",
+                "│ │ nested",
+                "",
+                "│ code",
+            ],
+        );
+        assert_eq!(lines[0].spans[0].style.fg, Some(options.quote_gutter_color));
+        assert!(
+            lines[0].spans[1]
+                .style
+                .add_modifier
+                .contains(ratatui::style::Modifier::DIM),
+            "quoted text is dimmed"
+        );
+    }
+
+    #[test]
+    fn parse_code_fold() {
+        let options = ParseOptions {
+            code_fold_threshold: Some(6),
+            ..ParseOptions::default()
+        };
+        let events: Vec<Event> = parse(
+            "```\n1\n2\n3\n4\n5\n6\n7\n8\n```\nafter\n```\nshort\n```",
+            &RatSkin::default(),
+            DocumentId::default(),
+            80,
+            false,
+            &options,
+        )
+        .collect();
+        let summary: Vec<String> = events
+            .iter()
+            .map(|event| match event {
+                Event::Parsed(_, source) => {
+                    format!("{}: {source}", source.id).trim_end().to_owned()
+                }
+                event => event.to_string(),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                String::from("0: 1"),
+                String::from("1: 2"),
+                String::from("2: 3"),
+                String::from("3: 4"),
+                String::from("4: 5"),
+                String::from("5: 6"),
+                String::from("6: 7"),
+                String::from("7: 8"),
+                String::from("8: ... (3 more lines, press Enter to expand)"),
+                String::from("Event::CodeFold(D0.0, 8, 5)"),
+                String::from("9: after"),
+                String::from("10: short"),
+            ],
+            summary
+        );
+    }
+
+    #[test]
+    fn parse_header_strips_emphasis_markers() {
+        let events: Vec<Event> = parse(
+            "# Hello **world**",
+            &RatSkin::default(),
+            DocumentId::default(),
+            80,
+            true,
+            &ParseOptions::default(),
+        )
+        .collect();
+        assert_eq!(
+            Event::Outline(DocumentId::default(), 0, 1, String::from("Hello world")),
+            events[0]
+        );
+        let Event::Parsed(
+            _,
+            WidgetSource {
+                data: WidgetSourceData::Header(text, _, _),
+                ..
+            },
+        ) = &events[1]
+        else {
+            panic!("expected Header");
+        };
+        assert_eq!("Hello world", text);
+    }
+
+    #[test]
+    fn parse_header_max_lines() {
+        let options = ParseOptions {
+            max_header_lines: Some(1),
+            ..ParseOptions::default()
+        };
+        let headers = |text: &str| -> Vec<(String, u8)> {
+            parse(
+                text,
+                &RatSkin::default(),
+                DocumentId::default(),
+                10,
+                true,
+                &options,
+            )
+            .filter_map(|event| match event {
+                Event::Parsed(
+                    _,
+                    WidgetSource {
+                        data: WidgetSourceData::Header(text, tier, _),
+                        ..
+                    },
+                ) => Some((text, tier)),
+                _ => None,
+            })
+            .collect()
+        };
+        assert_eq!(
+            vec![(String::from("1234567890"), 6)],
+            headers("# 1234567890"),
+            "demoted to fit"
+        );
+        assert_eq!(
+            vec![(String::from("1234567890…"), 6)],
+            headers("# 1234567890 1234567890"),
+            "cut off"
+        );
+    }
+
     #[test]
     fn parse_header_wrapping_tier_1() {
         let events: Vec<Event> = parse(
@@ -385,17 +1512,23 @@ mod tests {
             DocumentId::default(),
             10,
             true,
+            &ParseOptions::default(),
         )
         .collect();
-        assert_eq!(2, events.len());
+        assert_eq!(3, events.len());
+
+        assert_eq!(
+            Event::Outline(DocumentId::default(), 0, 1, String::from("1234567890")),
+            events[0]
+        );
 
         let Event::Parsed(
             _,
             WidgetSource {
-                data: WidgetSourceData::Header(text, tier),
+                data: WidgetSourceData::Header(text, tier, _),
                 ..
             },
-        ) = &events[0]
+        ) = &events[1]
         else {
             panic!("expected Header");
         };
@@ -405,10 +1538,10 @@ mod tests {
         let Event::Parsed(
             _,
             WidgetSource {
-                data: WidgetSourceData::Header(text, tier),
+                data: WidgetSourceData::Header(text, tier, _),
                 ..
             },
-        ) = &events[1]
+        ) = &events[2]
         else {
             panic!("expected Header");
         };
@@ -424,17 +1557,23 @@ mod tests {
             DocumentId::default(),
             10,
             true,
+            &ParseOptions::default(),
         )
         .collect();
-        assert_eq!(2, events.len());
+        assert_eq!(3, events.len());
+
+        assert_eq!(
+            Event::Outline(DocumentId::default(), 0, 4, String::from("1234567890")),
+            events[0]
+        );
 
         let Event::Parsed(
             _,
             WidgetSource {
-                data: WidgetSourceData::Header(text, tier),
+                data: WidgetSourceData::Header(text, tier, _),
                 ..
             },
-        ) = &events[0]
+        ) = &events[1]
         else {
             panic!("expected Header");
         };
@@ -444,10 +1583,10 @@ mod tests {
         let Event::Parsed(
             _,
             WidgetSource {
-                data: WidgetSourceData::Header(text, tier),
+                data: WidgetSourceData::Header(text, tier, _),
                 ..
             },
-        ) = &events[1]
+        ) = &events[2]
         else {
             panic!("expected Header");
         };
@@ -1,5 +1,8 @@
+use ratatui::layout::Alignment;
 use regex::Regex;
 
+use crate::diagram::Diagram;
+
 // Crude "pre-parsing" of markdown by lines.
 // Headers are always on a line of their own.
 // Images are only processed if it appears on a line by itself, to avoid having to deal with text
@@ -7,24 +10,250 @@ use regex::Regex;
 #[derive(Debug, PartialEq)]
 pub enum Block {
     Header(u8, String),
-    Image(String, String),
+    // The alt text, url, and title, which is empty if there is none.
+    Image(String, String, String),
     Markdown(String),
+    // A collapsible `<details>` section, with the summary and the inner markdown.
+    Details(String, String),
+    // A fenced diagram, without the fences.
+    Diagram(Diagram, String),
+    // A pipe table, the first row is the header. All rows have as many cells as the header.
+    Table(Vec<Vec<String>>, Vec<Alignment>),
+    // A list, which is loose if its items are separated by blank lines.
+    List(Vec<ListItem>, bool),
+    // Consecutive lines of a quote of the same depth, without the `>` markers.
+    Quote(u8, String),
+}
+
+// An item of a `Block::List`, with its continuation lines joined.
+#[derive(Debug, PartialEq)]
+pub struct ListItem {
+    pub depth: u8,
+    // The number to show for ordered items, counting up from the first item of the same level.
+    pub number: Option<u64>,
+    // Whether a task list item, like `- [ ] task`, is checked.
+    pub task: Option<bool>,
+    pub text: String,
+}
+
+// The list that is being split.
+#[derive(Default)]
+struct ListState {
+    items: Vec<ListItem>,
+    // The indentation of the current item of each depth.
+    indents: Vec<usize>,
+    // The next number of ordered items of each depth, and their `.` or `)` delimiter.
+    numbers: Vec<Option<(u64, char)>>,
+    // The delimiter of the top level items, if they are numbered.
+    delimiter: Option<char>,
+    loose: bool,
+    // A blank line came after the last item.
+    blank: bool,
+}
+
+impl ListState {
+    // Whether an item belongs to this list, top level items with another kind of marker, or
+    // another delimiter like `1)` after `1.`, start a new list.
+    fn continues(&self, indent: usize, number: Option<(u64, char)>) -> bool {
+        self.indents.first().is_none_or(|first| indent > *first)
+            || self.delimiter == number.map(|(_, delimiter)| delimiter)
+    }
+
+    fn push(&mut self, indent: usize, number: Option<(u64, char)>, text: &str) {
+        if self.items.is_empty() {
+            self.delimiter = number.map(|(_, delimiter)| delimiter);
+        }
+        while self.indents.last().is_some_and(|last| indent < *last) {
+            self.indents.pop();
+        }
+        if self.indents.last().is_none_or(|last| indent > *last) {
+            self.indents.push(indent);
+        }
+        let depth = self.indents.len() - 1;
+        self.numbers.resize(depth + 1, None);
+        // Nested items with another delimiter are another list too, numbered from their start.
+        let number = number.map(|(number, delimiter)| {
+            let number = self.numbers[depth]
+                .filter(|(_, previous)| *previous == delimiter)
+                .map_or(number, |(next, _)| next);
+            self.numbers[depth] = Some((number + 1, delimiter));
+            number
+        });
+        if number.is_none() {
+            self.numbers[depth] = None;
+        }
+        self.loose |= self.blank && !self.items.is_empty();
+        self.blank = false;
+        let (task, text) = task_marker(text);
+        self.items.push(ListItem {
+            depth: u8::try_from(depth).unwrap_or(u8::MAX),
+            number,
+            task,
+            text: text.trim_end().to_owned(),
+        });
+    }
+
+    fn continue_item(&mut self, line: &str) {
+        self.loose |= self.blank;
+        self.blank = false;
+        if let Some(item) = self.items.last_mut() {
+            item.text.push(' ');
+            item.text.push_str(line.trim());
+        }
+    }
 }
 
-pub fn split_headers_and_images(text: &str) -> Vec<Block> {
+pub fn split_headers_and_images(text: &str, html: bool) -> Vec<Block> {
     // Regex to match lines starting with 1-6 `#` characters
     let header_re = Regex::new(r"^(#+)\s*(.*)").expect("regex");
+    // Regex to match the optional closing sequence of a header, like `## Title ##`
+    let header_closing_re = Regex::new(r"(^|\s+)#+\s*$").expect("regex");
     // Regex to match standalone image lines: ![alt](url)
     let image_re = Regex::new(r"^!\[(.*?)\]\((.*?)\)$").expect("regex");
     // Regex to match beginning or end of code fence
-    let codefence_re = Regex::new(r"^ {0,3}(`{3,}|~{3,})").expect("regex");
+    let codefence_re = Regex::new(r"^ {0,3}(`{3,}|~{3,})(.*)").expect("regex");
+    // Regex to match the start of a `<details>` section
+    let details_re = Regex::new(r"^ {0,3}<details[\s>]").expect("regex");
+    // Regex to match list items, see `list_item`
+    let item_re = Regex::new(ITEM_PATTERN).expect("regex");
+    // Regex to match horizontal rules like `---` or `* * *`
+    let rule_re = Regex::new(RULE_PATTERN).expect("regex");
+    // Regex to match the delimiter row below a table header, like `| --- | :-: |`
+    let table_rule_re =
+        Regex::new(r"^ {0,3}\|?\s*:?-+:?\s*(\|\s*:?-+:?\s*)*\|?\s*$").expect("regex");
 
     let mut blocks = Vec::new();
     let mut current_block = String::new();
     let mut current_codefence: Option<String> = None;
+    // The diagram and its source, if the current codefence is one.
+    let mut current_diagram: Option<(Diagram, String)> = None;
+    // The raw `<details>` section, and how deeply nested the current line is.
+    let mut current_details = String::new();
+    let mut details_depth = 0;
+    // Where the header starts in `current_block`, the rows, and the alignments of the current table.
+    let mut current_table: Option<(usize, Vec<Vec<String>>, Vec<Alignment>)> = None;
+    let mut current_list: Option<ListState> = None;
+    // The depth and text of the current quote, and its code fence if one is open.
+    let mut current_quote: Option<(usize, String, Option<String>)> = None;
 
-    for line in text.lines() {
-        if let Some(codefence_str) = &current_codefence {
+    for mut line in text.lines() {
+        if let Some((start, rows, _)) = &mut current_table {
+            if line.contains('|') && !header_re.is_match(line) {
+                if rows.len() == 1 {
+                    // The first row confirms the table, the header and rule are not text.
+                    current_block.truncate(*start);
+                    if current_block.ends_with('\n') {
+                        current_block.pop();
+                    }
+                    if !current_block.is_empty() {
+                        blocks.push(Block::Markdown(current_block.clone()));
+                        current_block.clear();
+                    }
+                }
+                let mut cells = table_cells(line);
+                cells.resize(rows[0].len(), String::new());
+                rows.push(cells);
+                continue;
+            }
+            if let Some((_, rows, alignments)) = current_table.take()
+                && rows.len() > 1
+            {
+                blocks.push(Block::Table(rows, alignments));
+            }
+        }
+
+        if let Some((depth, text, fence)) = &mut current_quote
+            && let Some((line_depth, rest)) = quote_markers(line, usize::MAX)
+        {
+            // Inside a code fence, further markers are code.
+            let rest = if fence.is_some() {
+                quote_markers(line, *depth).map_or(rest, |(_, rest)| rest)
+            } else {
+                rest
+            };
+            if fence.is_some() || line_depth == *depth {
+                text.push('\n');
+                text.push_str(rest);
+                toggle_fence(fence, rest, &codefence_re);
+                continue;
+            }
+            if let Some((depth, text, _)) = current_quote.take() {
+                blocks.push(quote_block(depth, text));
+            }
+            let mut fence = None;
+            toggle_fence(&mut fence, rest, &codefence_re);
+            current_quote = Some((line_depth, rest.to_owned(), fence));
+            continue;
+        }
+        if let Some((depth, text, _)) = current_quote.take() {
+            blocks.push(quote_block(depth, text));
+        }
+
+        if let Some(list) = &mut current_list {
+            let item = list_item(line, &item_re, &rule_re);
+            if let Some((indent, number, text)) = item
+                && list.continues(indent, number)
+            {
+                list.push(indent, number, text);
+                continue;
+            }
+            if line.trim().is_empty() {
+                list.blank = true;
+                continue;
+            }
+            // Indented lines continue the item, and so do unindented ones right below it. Code
+            // fences, and paragraphs after a blank line, are blocks of their own.
+            let fence = codefence_re.is_match(line.trim_start());
+            let starts_block = list.blank
+                || item.is_some()
+                || fence
+                || rule_re.is_match(line)
+                || quote_markers(line, 1).is_some()
+                || header_re.is_match(line)
+                || image_re.is_match(line)
+                || (html && details_re.is_match(line));
+            if !starts_block || (line.starts_with([' ', '\t']) && !list.blank && !fence) {
+                list.continue_item(line);
+                continue;
+            }
+            if list.blank && item.is_none() && !fence {
+                line = line.trim_start();
+            }
+            if let Some(list) = current_list.take() {
+                blocks.push(Block::List(list.items, list.loose));
+            }
+        }
+
+        if details_depth > 0 || (html && details_re.is_match(line)) {
+            if details_depth == 0 && !current_block.is_empty() {
+                blocks.push(Block::Markdown(current_block.clone()));
+                current_block.clear();
+            }
+            details_depth += line.matches("<details").count();
+            details_depth = details_depth.saturating_sub(line.matches("</details>").count());
+            current_details.push_str(line);
+            current_details.push('\n');
+            if details_depth == 0 {
+                blocks.push(details_block(&current_details));
+                current_details.clear();
+            }
+        } else if let Some(codefence_str) = &current_codefence
+            && let Some((diagram, source)) = &mut current_diagram
+        {
+            match codefence_re.captures(line) {
+                Some(captures) if captures[1].starts_with(codefence_str.as_str()) => {
+                    blocks.push(Block::Diagram(*diagram, std::mem::take(source)));
+                    current_diagram = None;
+                    current_codefence = None;
+                }
+                _ => {
+                    if !source.is_empty() {
+                        source.push('\n');
+                    }
+                    source.push_str(line);
+                }
+            }
+        } else if let Some(codefence_str) = &current_codefence {
             if !current_block.is_empty() {
                 current_block.push('\n');
             }
@@ -43,7 +272,10 @@ pub fn split_headers_and_images(text: &str) -> Vec<Block> {
             }
             // Push the header as (level, text)
             let level = captures[1].len().min(6) as u8;
-            let text = captures[2].to_string();
+            let text = header_closing_re
+                .replace(&captures[2], "")
+                .trim_end()
+                .to_string();
             blocks.push(Block::Header(level, text));
         } else if let Some(captures) = image_re.captures(line) {
             // If there's an ongoing block, push it as a plain text block
@@ -51,16 +283,48 @@ pub fn split_headers_and_images(text: &str) -> Vec<Block> {
                 blocks.push(Block::Markdown(current_block.clone()));
                 current_block.clear();
             }
-            // Push the image as (alt_text, url)
+            // Push the image as (alt_text, url, title)
             let alt_text = captures[1].to_string();
-            let url = captures[2].to_string();
-            blocks.push(Block::Image(alt_text, url));
+            let (url, title) = split_image_title(&captures[2]);
+            blocks.push(Block::Image(alt_text, url, title));
         } else if let Some(captures) = codefence_re.captures(line) {
+            if let Some(diagram) = Diagram::from_info(&captures[2]) {
+                if !current_block.is_empty() {
+                    blocks.push(Block::Markdown(current_block.clone()));
+                    current_block.clear();
+                }
+                current_codefence = Some(captures[1].to_string());
+                current_diagram = Some((diagram, String::new()));
+                continue;
+            }
             if !current_block.is_empty() {
                 current_block.push('\n');
             }
             current_block.push_str(line);
             current_codefence = Some(captures[1].to_string());
+        } else if let Some((start, header, alignments)) =
+            table_header(&current_block, line, &table_rule_re)
+        {
+            // Kept as text until the first row, a table needs at least one.
+            current_table = Some((start, vec![header], alignments));
+            current_block.push('\n');
+            current_block.push_str(line);
+        } else if let Some((depth, rest)) = quote_markers(line, usize::MAX) {
+            if !current_block.is_empty() {
+                blocks.push(Block::Markdown(current_block.clone()));
+                current_block.clear();
+            }
+            let mut fence = None;
+            toggle_fence(&mut fence, rest, &codefence_re);
+            current_quote = Some((depth, rest.to_owned(), fence));
+        } else if let Some((indent, number, text)) = list_item(line, &item_re, &rule_re) {
+            if !current_block.is_empty() {
+                blocks.push(Block::Markdown(current_block.clone()));
+                current_block.clear();
+            }
+            let mut list = ListState::default();
+            list.push(indent, number, text);
+            current_list = Some(list);
         } else {
             // Accumulate lines that are neither headers nor images
             if !current_block.is_empty() {
@@ -70,18 +334,388 @@ pub fn split_headers_and_images(text: &str) -> Vec<Block> {
         }
     }
 
-    // Push the final block if there's remaining content
+    if let Some((_, rows, alignments)) = current_table
+        && rows.len() > 1
+    {
+        blocks.push(Block::Table(rows, alignments));
+    }
+    if let Some(list) = current_list {
+        blocks.push(Block::List(list.items, list.loose));
+    }
+    if let Some((depth, text, _)) = current_quote {
+        blocks.push(quote_block(depth, text));
+    }
+    // An unclosed diagram extends to the end
+    if let Some((diagram, source)) = current_diagram {
+        blocks.push(Block::Diagram(diagram, source));
+    }
+    // Push the final block if there's remaining content, without the blank lines at the end, which
+    // only some documents have.
+    let current_block = current_block.trim_end_matches('\n');
     if !current_block.is_empty() {
-        blocks.push(Block::Markdown(current_block));
+        blocks.push(Block::Markdown(current_block.to_owned()));
+    }
+    // An unclosed `<details>` extends to the end
+    if !current_details.is_empty() {
+        blocks.push(details_block(&current_details));
     }
 
     blocks
 }
 
+// Cut off the markers of quotes nested deeper than `max_depth`, except in code blocks, so that
+// they don't take up the whole width. `None` if there are none.
+pub fn limit_quote_depth(text: &str, max_depth: usize) -> Option<String> {
+    if text.matches('>').count() <= max_depth {
+        return None;
+    }
+    let codefence_re = Regex::new(r"^ {0,3}(`{3,}|~{3,})").expect("regex");
+    let quote_re = Regex::new(r"^ {0,3}((?:> ?)+)").expect("regex");
+
+    let mut result = String::with_capacity(text.len());
+    let mut current_codefence: Option<String> = None;
+    let mut limited = false;
+    for line in text.lines() {
+        if let Some(codefence_str) = &current_codefence {
+            if let Some(captures) = codefence_re.captures(line)
+                && captures[1].starts_with(codefence_str.as_str())
+            {
+                current_codefence = None;
+            }
+        } else if let Some(captures) = codefence_re.captures(line) {
+            current_codefence = Some(captures[1].to_string());
+        } else if let Some(markers) = quote_re.find(line)
+            && markers.as_str().matches('>').count() > max_depth
+        {
+            limited = true;
+            result.push_str(&"> ".repeat(max_depth));
+            result.push_str(line.get(markers.end()..).unwrap_or_default());
+            result.push('\n');
+            continue;
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    limited.then_some(result)
+}
+
+// The depth of a quote line, up to `max`, and the text after that many `>` markers.
+fn quote_markers(line: &str, max: usize) -> Option<(usize, &str)> {
+    let mut depth = 0;
+    let mut rest = line;
+    while depth < max {
+        let Some(after) = rest.trim_start_matches(' ').strip_prefix('>') else {
+            break;
+        };
+        rest = after.strip_prefix(' ').unwrap_or(after);
+        depth += 1;
+    }
+    (depth > 0).then_some((depth, rest))
+}
+
+fn quote_block(depth: usize, text: String) -> Block {
+    Block::Quote(u8::try_from(depth).unwrap_or(u8::MAX), text)
+}
+
+// Open or close the code fence of a quote, on a fence line.
+fn toggle_fence(fence: &mut Option<String>, line: &str, codefence_re: &Regex) {
+    let Some(captures) = codefence_re.captures(line) else {
+        return;
+    };
+    match fence {
+        Some(open) if captures[1].starts_with(open.as_str()) => *fence = None,
+        Some(_) => {}
+        None => *fence = Some(captures[1].to_string()),
+    }
+}
+
+// A `-`, `*`, `+`, `1.` or `1)` list item.
+const ITEM_PATTERN: &str = r"^( *)(?:[-*+]|(\d{1,9})([.)])) +(\S.*)$";
+// A horizontal rule like `---` or `* * *`, which is not a list item.
+const RULE_PATTERN: &str = r"^ {0,3}(?:(?:- *){3,}|(?:\* *){3,}|(?:_ *){3,})$";
+
+// The indentation, the number and delimiter if ordered, and the text of a `-`, `*`, `+`, `1.` or
+// `1)` item.
+fn list_item<'a>(
+    line: &'a str,
+    item_re: &Regex,
+    rule_re: &Regex,
+) -> Option<(usize, Option<(u64, char)>, &'a str)> {
+    if rule_re.is_match(line) {
+        return None;
+    }
+    let captures = item_re.captures(line)?;
+    let number = captures
+        .get(2)
+        .and_then(|number| number.as_str().parse().ok())
+        .zip(
+            captures
+                .get(3)
+                .and_then(|delimiter| delimiter.as_str().chars().next()),
+        );
+    Some((captures[1].len(), number, captures.get(4)?.as_str()))
+}
+
+// Whether the text of a list item starts with a checked or unchecked task marker, and the text
+// after it.
+fn task_marker(text: &str) -> (Option<bool>, &str) {
+    let checked = match text.get(..3) {
+        Some("[ ]") => false,
+        Some("[x]" | "[X]") => true,
+        _ => return (None, text),
+    };
+    match text.get(3..) {
+        Some(rest) if rest.is_empty() || rest.starts_with(' ') => {
+            (Some(checked), rest.trim_start())
+        }
+        _ => (None, text),
+    }
+}
+
+// The byte offsets of the `[ ]` or `[x]` markers of task list items, outside of code fences, in the
+// order that they are rendered in. With `skip_comments`, tasks in `<!-- ... -->` are skipped, like
+// `strip_html_comments` removes them.
+pub fn task_offsets(text: &str, skip_comments: bool) -> Vec<usize> {
+    let codefence_re = Regex::new(r"^ {0,3}(`{3,}|~{3,})(.*)").expect("regex");
+    let item_re = Regex::new(ITEM_PATTERN).expect("regex");
+    let rule_re = Regex::new(RULE_PATTERN).expect("regex");
+    let mut fence = None;
+    let mut in_comment = false;
+    let mut offsets = Vec::new();
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        let line_start = start;
+        start += line.len();
+        let line = line.trim_end_matches(['\n', '\r']);
+        if skip_comments && (in_comment || (fence.is_none() && !codefence_re.is_match(line))) {
+            let was_in_comment = in_comment;
+            strip_line_comments(line, &mut in_comment);
+            if was_in_comment {
+                continue;
+            }
+        }
+        if codefence_re.is_match(line) {
+            toggle_fence(&mut fence, line, &codefence_re);
+            continue;
+        }
+        if fence.is_none()
+            && let Some((_, _, item)) = list_item(line, &item_re, &rule_re)
+            && task_marker(item).0.is_some()
+        {
+            // The item text runs to the end of the line.
+            offsets.push(line_start + line.len() - item.len());
+        }
+    }
+    offsets
+}
+
+// If `line` is the rule below a table header, which is the last line of `block`, where the header
+// starts in `block`, its cells, and the alignments of the columns.
+fn table_header(
+    block: &str,
+    line: &str,
+    rule_re: &Regex,
+) -> Option<(usize, Vec<String>, Vec<Alignment>)> {
+    if !line.contains('|') || !rule_re.is_match(line) {
+        return None;
+    }
+    let (start, header) = match block.rsplit_once('\n') {
+        Some((before, header)) => (before.len() + 1, header),
+        None => (0, block),
+    };
+    if !header.contains('|') {
+        return None;
+    }
+    let header = table_cells(header);
+    let alignments: Vec<Alignment> = table_cells(line)
+        .iter()
+        .map(|cell| match (cell.starts_with(':'), cell.ends_with(':')) {
+            (true, true) => Alignment::Center,
+            (false, true) => Alignment::Right,
+            _ => Alignment::Left,
+        })
+        .collect();
+    (header.len() == alignments.len()).then_some((start, header, alignments))
+}
+
+// The trimmed cells of a table row, without the outer pipes. `\|` is a literal pipe.
+fn table_cells(row: &str) -> Vec<String> {
+    let row = row.trim();
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let row = match row.strip_suffix('|') {
+        Some(stripped) if !stripped.ends_with('\\') => stripped,
+        _ => row,
+    };
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            }
+            '|' => cells.push(std::mem::take(&mut cell)),
+            c => cell.push(c),
+        }
+    }
+    cells.push(cell);
+    cells
+        .into_iter()
+        .map(|cell| cell.trim().to_owned())
+        .collect()
+}
+
+// Remove `<!-- ... -->` comments, which may span several lines, except in code blocks.
+// Lines that had nothing but a comment are removed entirely, instead of leaving a gap.
+pub fn strip_html_comments(text: &str) -> String {
+    let codefence_re = Regex::new(r"^ {0,3}(`{3,}|~{3,})").expect("regex");
+
+    let mut result = String::with_capacity(text.len());
+    let mut current_codefence: Option<String> = None;
+    let mut in_comment = false;
+    for line in text.lines() {
+        if !in_comment {
+            if let Some(codefence_str) = &current_codefence {
+                if let Some(captures) = codefence_re.captures(line)
+                    && captures[1].starts_with(codefence_str.as_str())
+                {
+                    current_codefence = None;
+                }
+                result.push_str(line);
+                result.push('\n');
+                continue;
+            }
+            if let Some(captures) = codefence_re.captures(line) {
+                current_codefence = Some(captures[1].to_string());
+                result.push_str(line);
+                result.push('\n');
+                continue;
+            }
+        }
+
+        let (kept, had_comment) = strip_line_comments(line, &mut in_comment);
+        if had_comment && kept.trim().is_empty() {
+            continue;
+        }
+        result.push_str(&kept);
+        result.push('\n');
+    }
+    result
+}
+
+// The text of `line` outside of comments, and whether it had any, where `in_comment` is whether a
+// comment is still open from the lines before, and is updated for the lines after. Inline code
+// spans are kept as they are, a `<!--` in them starts no comment.
+fn strip_line_comments(line: &str, in_comment: &mut bool) -> (String, bool) {
+    let mut kept = String::new();
+    let mut had_comment = *in_comment;
+    let mut rest = line;
+    loop {
+        if *in_comment {
+            let Some((_, after)) = rest.split_once("-->") else {
+                break;
+            };
+            *in_comment = false;
+            rest = after;
+            continue;
+        }
+        let comment = rest.find("<!--");
+        match rest.find('`') {
+            Some(code) if comment.is_none_or(|comment| code < comment) => {
+                let (before, from_code) = rest.split_at(code);
+                let (span, after) = from_code.split_at(code_span_len(from_code));
+                kept.push_str(before);
+                kept.push_str(span);
+                rest = after;
+            }
+            _ => {
+                let Some((before, after)) = rest.split_once("<!--") else {
+                    kept.push_str(rest);
+                    break;
+                };
+                kept.push_str(before);
+                had_comment = true;
+                *in_comment = true;
+                rest = after;
+            }
+        }
+    }
+    (kept, had_comment)
+}
+
+// The byte length of the inline code span at the start of `text`, up to the closing backticks of
+// the same count, or only of the opening backticks if the span isn't closed on the line.
+fn code_span_len(text: &str) -> usize {
+    let ticks_at = |from: usize| {
+        text.get(from..)
+            .map_or(0, |rest| rest.len() - rest.trim_start_matches('`').len())
+    };
+    let ticks = ticks_at(0);
+    let mut offset = ticks;
+    while let Some(found) = text.get(offset..).and_then(|rest| rest.find('`')) {
+        let start = offset + found;
+        let run = ticks_at(start);
+        if run == ticks {
+            return start + run;
+        }
+        offset = start + run;
+    }
+    ticks
+}
+
+// Split a raw `<details>` section into its summary and inner markdown.
+fn details_block(raw: &str) -> Block {
+    let open_re = Regex::new(r"^\s*<details[^>]*>").expect("regex");
+    let summary_re = Regex::new(r"(?s)^\s*<summary>(.*?)</summary>").expect("regex");
+    let close_re = Regex::new(r"</details>\s*$").expect("regex");
+
+    let inner = open_re.replace(raw, "");
+    let inner = close_re.replace(&inner, "");
+    let (summary, inner) = match summary_re.captures(&inner) {
+        Some(captures) => (
+            captures[1].trim().to_owned(),
+            summary_re.replace(&inner, "").into_owned(),
+        ),
+        None => (String::from("Details"), inner.into_owned()),
+    };
+    Block::Details(summary, inner.trim_matches('\n').to_owned())
+}
+
+// Split the url from an optional title, like `url "Title"`, `url 'Title'` or `url (Title)`. Urls
+// with spaces are enclosed in `<>`. Anything else is all url, like before titles were supported.
+fn split_image_title(destination: &str) -> (String, String) {
+    let destination = destination.trim();
+    let (url, rest) = if let Some(enclosed) = destination.strip_prefix('<')
+        && let Some((url, rest)) = enclosed.split_once('>')
+    {
+        (url, rest)
+    } else {
+        destination
+            .split_once(char::is_whitespace)
+            .unwrap_or((destination, ""))
+    };
+    let rest = rest.trim();
+    if rest.is_empty() {
+        return (url.to_owned(), String::new());
+    }
+    let title = [('"', '"'), ('\'', '\''), ('(', ')')]
+        .into_iter()
+        .find_map(|(open, close)| {
+            rest.strip_prefix(open)
+                .and_then(|title| title.strip_suffix(close))
+        });
+    match title {
+        Some(title) => (url.to_owned(), title.to_owned()),
+        None => (destination.to_owned(), String::new()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::*;
+    use crate::{diagram::Diagram, *};
     use pretty_assertions::assert_eq;
+    use ratatui::layout::Alignment;
 
     #[test]
     fn split_headers_and_images() {
@@ -104,6 +738,7 @@ paragraph
 
 # header
 "#,
+            true,
         );
         assert_eq!(
             blocks,
@@ -130,6 +765,7 @@ paragraph
 paragraph
 # header
 "#,
+            true,
         );
         assert_eq!(6, blocks.len());
         assert_eq!(
@@ -145,6 +781,26 @@ paragraph
         );
     }
 
+    #[test]
+    fn closed_headers() {
+        let blocks = markdown::split_headers_and_images(
+            "## Title ##\n# Title #####   \n## C# ##\n## C#\n### ###\n## Title ## after\n# Title#",
+            true,
+        );
+        assert_eq!(
+            blocks,
+            vec![
+                markdown::Block::Header(2, "Title".to_owned()),
+                markdown::Block::Header(1, "Title".to_owned()),
+                markdown::Block::Header(2, "C#".to_owned()),
+                markdown::Block::Header(2, "C#".to_owned()),
+                markdown::Block::Header(3, String::new()),
+                markdown::Block::Header(2, "Title ## after".to_owned()),
+                markdown::Block::Header(1, "Title#".to_owned()),
+            ]
+        );
+    }
+
     #[test]
     fn codefence() {
         let blocks = markdown::split_headers_and_images(
@@ -173,6 +829,7 @@ paragraph
 
 paragraph
 "#,
+            true,
         );
         assert_eq!(
             blocks,
@@ -204,4 +861,295 @@ paragraph
             ]
         );
     }
+
+    #[test]
+    fn details() {
+        let blocks = markdown::split_headers_and_images(
+            r#"before
+<details>
+<summary>Click *me*</summary>
+
+hidden **text**
+
+<details><summary>Inner</summary>
+nested
+</details>
+</details>
+after"#,
+            true,
+        );
+        assert_eq!(
+            blocks,
+            vec![
+                markdown::Block::Markdown("before".to_owned()),
+                markdown::Block::Details(
+                    "Click *me*".to_owned(),
+                    "hidden **text**\n\n<details><summary>Inner</summary>\nnested\n</details>"
+                        .to_owned()
+                ),
+                markdown::Block::Markdown("after".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn details_without_html() {
+        let blocks = markdown::split_headers_and_images("<details>\nx\n</details>", false);
+        assert_eq!(
+            blocks,
+            vec![markdown::Block::Markdown(
+                "<details>\nx\n</details>".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn diagrams() {
+        let blocks = markdown::split_headers_and_images(
+            "before\n```mermaid\ngraph TD\n  A --> B\n```\n~~~rust\nfn x() {}\n~~~\n```dot",
+            true,
+        );
+        assert_eq!(
+            blocks,
+            vec![
+                markdown::Block::Markdown("before".to_owned()),
+                markdown::Block::Diagram(Diagram::Mermaid, "graph TD\n  A --> B".to_owned()),
+                markdown::Block::Markdown("~~~rust\nfn x() {}\n~~~".to_owned()),
+                markdown::Block::Diagram(Diagram::Dot, String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tables() {
+        let blocks = markdown::split_headers_and_images(
+            "before\n| a | b \\| c | d |\n|:--|:-:|--:|\n| 1 | 2 |\n|x|y|z|extra|\nafter\n\nno | rows\n--|--",
+            true,
+        );
+        assert_eq!(
+            blocks,
+            vec![
+                markdown::Block::Markdown("before".to_owned()),
+                markdown::Block::Table(
+                    vec![
+                        vec!["a".to_owned(), "b | c".to_owned(), "d".to_owned()],
+                        vec!["1".to_owned(), "2".to_owned(), String::new()],
+                        vec!["x".to_owned(), "y".to_owned(), "z".to_owned()],
+                    ],
+                    vec![Alignment::Left, Alignment::Center, Alignment::Right],
+                ),
+                markdown::Block::Markdown("after\n\nno | rows\n--|--".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lists() {
+        let blocks = markdown::split_headers_and_images(
+            "intro\n- a\n  - b\n    wrapped\n- c\n\n1. one\n1. two\n\n1. three\n   more\n\nafter\n* * *",
+            true,
+        );
+        let item = |depth, number, text: &str| markdown::ListItem {
+            depth,
+            number,
+            task: None,
+            text: text.to_owned(),
+        };
+        assert_eq!(
+            blocks,
+            vec![
+                markdown::Block::Markdown("intro".to_owned()),
+                markdown::Block::List(
+                    vec![
+                        item(0, None, "a"),
+                        item(1, None, "b wrapped"),
+                        item(0, None, "c"),
+                    ],
+                    false,
+                ),
+                markdown::Block::List(
+                    vec![
+                        item(0, Some(1), "one"),
+                        item(0, Some(2), "two"),
+                        item(0, Some(3), "three more"),
+                    ],
+                    true,
+                ),
+                markdown::Block::Markdown("after\n* * *".to_owned()),
+            ]
+        );
+
+        let blocks = markdown::split_headers_and_images(
+            "- a\n  ```rust\n  let a = 1;\n  ```\n- b\n\n  more of b\n  and more",
+            true,
+        );
+        assert_eq!(
+            blocks,
+            vec![
+                markdown::Block::List(vec![item(0, None, "a")], false),
+                markdown::Block::Markdown("  ```rust\n  let a = 1;\n  ```".to_owned()),
+                markdown::Block::List(vec![item(0, None, "b")], false),
+                markdown::Block::Markdown("more of b\n  and more".to_owned()),
+            ],
+            "code blocks and paragraphs in items are not flattened into the item"
+        );
+    }
+
+    #[test]
+    fn ordered_list_numbers() {
+        let blocks = markdown::split_headers_and_images(
+            "3. first\n4. second\n\ntext\n\n1. again\n```\ncode\n```\n1. after code\n1) other\n   1. a\n   1) b\n   1) c",
+            true,
+        );
+        let item = |depth, number, text: &str| markdown::ListItem {
+            depth,
+            number,
+            task: None,
+            text: text.to_owned(),
+        };
+        assert_eq!(
+            blocks,
+            vec![
+                markdown::Block::List(
+                    vec![item(0, Some(3), "first"), item(0, Some(4), "second")],
+                    false,
+                ),
+                markdown::Block::Markdown("text\n".to_owned()),
+                markdown::Block::List(vec![item(0, Some(1), "again")], false),
+                markdown::Block::Markdown("```\ncode\n```".to_owned()),
+                markdown::Block::List(vec![item(0, Some(1), "after code")], false),
+                markdown::Block::List(
+                    vec![
+                        item(0, Some(1), "other"),
+                        item(1, Some(1), "a"),
+                        item(1, Some(1), "b"),
+                        item(1, Some(2), "c"),
+                    ],
+                    false,
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn tasks() {
+        let text = "- [ ] open\n  - [x] done\n- [] not a task\n```\n- [ ] code\n```\n1. [X]";
+        let blocks = markdown::split_headers_and_images(text, true);
+        let tasks: Vec<(Option<bool>, &str)> = blocks
+            .iter()
+            .flat_map(|block| match block {
+                markdown::Block::List(items, _) => items.as_slice(),
+                _ => &[],
+            })
+            .map(|item| (item.task, item.text.as_str()))
+            .collect();
+        assert_eq!(
+            tasks,
+            vec![
+                (Some(false), "open"),
+                (Some(true), "done"),
+                (None, "[] not a task"),
+                (Some(true), ""),
+            ]
+        );
+
+        let offsets = markdown::task_offsets(text, true);
+        assert_eq!(offsets, vec![2, 15, 62]);
+        for offset in offsets {
+            assert_eq!(
+                text.get(offset..=offset),
+                Some("["),
+                "offset {offset} points to the marker"
+            );
+        }
+
+        let text = "<!--\n- [ ] hidden\n-->\n- [ ] a <!-- x -->\n<!-- - [ ] y -->\n- [x] b";
+        assert_eq!(
+            markdown::task_offsets(text, true),
+            vec![24, 60],
+            "tasks in comments are skipped"
+        );
+        assert_eq!(
+            markdown::task_offsets(text, false),
+            vec![7, 24, 60],
+            "unless comments are shown"
+        );
+    }
+
+    #[test]
+    fn comments_in_code_spans() {
+        assert_eq!(
+            markdown::strip_html_comments(
+                "`<!--` stays, ``a ` <!-- b`` too <!-- gone -->\n``<!-- open\n-->after"
+            ),
+            "`<!--` stays, ``a ` <!-- b`` too \n``\nafter\n",
+            "an unclosed span is no code span"
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        let blocks = markdown::split_headers_and_images(
+            "> a\n> b\n>> c\n> ```\n> > code\n> ```\nafter",
+            true,
+        );
+        assert_eq!(
+            blocks,
+            vec![
+                markdown::Block::Quote(1, "a\nb".to_owned()),
+                markdown::Block::Quote(2, "c".to_owned()),
+                markdown::Block::Quote(1, "```\n> code\n```".to_owned()),
+                markdown::Block::Markdown("after".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn image_titles() {
+        let blocks = markdown::split_headers_and_images(
+            "![a](a.png)\n![b](b.png \"Title\")\n![c](<my c.png> 'Title')\n![d](<my d.png>)\n![e](e.png (Title))\n![f](f.png not a title)",
+            true,
+        );
+        let image = |alt: &str, url: &str, title: &str| {
+            markdown::Block::Image(alt.to_owned(), url.to_owned(), title.to_owned())
+        };
+        assert_eq!(
+            blocks,
+            vec![
+                image("a", "a.png", ""),
+                image("b", "b.png", "Title"),
+                image("c", "my c.png", "Title"),
+                image("d", "my d.png", ""),
+                image("e", "e.png", "Title"),
+                image("f", "f.png not a title", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn without_trailing_newline() {
+        for text in [
+            "paragraph\n\n# Title",
+            "paragraph\n![alt](image.png)",
+            "paragraph\n\n```rust\nfn main() {}\n```",
+            "```mermaid\ngraph TD\n```",
+            "paragraph\n\n- one\n- two",
+            "> quote",
+            "a | b\n--|--\n1 | 2",
+            "<details>\nhidden\n</details>",
+            "# Title\n\nparagraph",
+        ] {
+            let blocks = markdown::split_headers_and_images(text, true);
+            assert_eq!(
+                markdown::split_headers_and_images(&format!("{text}\n"), true),
+                blocks,
+                "{text:?} with a trailing newline"
+            );
+            assert_eq!(
+                markdown::split_headers_and_images(&format!("{text}\n\n"), true),
+                blocks,
+                "{text:?} with a trailing blank line"
+            );
+        }
+    }
 }
@@ -0,0 +1,201 @@
+// Header text is rendered outside of termimad (as images, or in the outline), so the inline
+// emphasis markers are parsed here.
+
+use ratatui::style::Style;
+use ratskin::RatSkin;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderSpan {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Emphasis {
+    Bold,
+    Italic,
+}
+
+#[derive(Debug)]
+enum Token {
+    Text(String),
+    Marker(Emphasis, String),
+}
+
+// The styles of the header tiers 1 to 6 from the skin, for headers rendered as plain lines.
+pub fn tier_styles(skin: &RatSkin) -> Vec<Style> {
+    (1..=6)
+        .map(|tier| {
+            let sample = skin.parse(RatSkin::parse_text(&format!("{} x", "#".repeat(tier))), 3);
+            sample
+                .iter()
+                .flat_map(|line| line.spans.iter())
+                .find(|span| span.content.trim() == "x")
+                .map_or_else(Style::default, |span| span.style)
+        })
+        .collect()
+}
+
+// Split header text into spans of the same emphasis, without the markers.
+// Markers that are not closed are kept as literal text.
+pub fn header_spans(text: &str) -> Vec<HeaderSpan> {
+    let mut tokens = tokenize(text);
+    for emphasis in [Emphasis::Bold, Emphasis::Italic] {
+        let count = tokens
+            .iter()
+            .filter(|token| matches!(token, Token::Marker(e, _) if *e == emphasis))
+            .count();
+        if count % 2 == 1
+            && let Some(token) = tokens
+                .iter_mut()
+                .rev()
+                .find(|token| matches!(token, Token::Marker(e, _) if *e == emphasis))
+            && let Token::Marker(_, raw) = token
+        {
+            *token = Token::Text(std::mem::take(raw));
+        }
+    }
+
+    let mut spans: Vec<HeaderSpan> = Vec::new();
+    let (mut bold, mut italic) = (false, false);
+    for token in tokens {
+        match token {
+            Token::Marker(Emphasis::Bold, _) => bold = !bold,
+            Token::Marker(Emphasis::Italic, _) => italic = !italic,
+            Token::Text(text) => match spans.last_mut() {
+                Some(last) if last.bold == bold && last.italic == italic => {
+                    last.text.push_str(&text);
+                }
+                _ => spans.push(HeaderSpan { text, bold, italic }),
+            },
+        }
+    }
+    spans
+}
+
+// The header text without any emphasis markers.
+pub fn strip_markers(text: &str) -> String {
+    header_spans(text)
+        .into_iter()
+        .map(|span| span.text)
+        .collect()
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut i = 0;
+    while let Some(&c) = chars.get(i) {
+        match c {
+            // Inline code is kept verbatim, only the backticks are removed.
+            '`' => {
+                if let Some(len) = chars.iter().skip(i + 1).position(|&c| c == '`') {
+                    literal.extend(chars.iter().skip(i + 1).take(len));
+                    i += len + 2;
+                    continue;
+                }
+            }
+            '*' | '_' => {
+                let run = chars.iter().skip(i).take_while(|&&r| r == c).count();
+                let before = i.checked_sub(1).and_then(|p| chars.get(p));
+                let after = chars.get(i + run);
+                // `snake_case` is not emphasis.
+                let intraword = c == '_'
+                    && before.is_some_and(|c| c.is_alphanumeric())
+                    && after.is_some_and(|c| c.is_alphanumeric());
+                let markers: &[Emphasis] = match run {
+                    1 => &[Emphasis::Italic],
+                    2 => &[Emphasis::Bold],
+                    3 => &[Emphasis::Bold, Emphasis::Italic],
+                    _ => &[],
+                };
+                if !intraword && !markers.is_empty() {
+                    if !literal.is_empty() {
+                        tokens.push(Token::Text(std::mem::take(&mut literal)));
+                    }
+                    for emphasis in markers {
+                        let len = if *emphasis == Emphasis::Bold { 2 } else { 1 };
+                        tokens.push(Token::Marker(*emphasis, c.to_string().repeat(len)));
+                    }
+                    i += run;
+                    continue;
+                }
+                literal.extend(chars.iter().skip(i).take(run));
+                i += run;
+                continue;
+            }
+            _ => {}
+        }
+        literal.push(c);
+        i += 1;
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Text(literal));
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use ratatui::style::{Color, Modifier, Style};
+    use ratskin::RatSkin;
+
+    use super::{HeaderSpan, header_spans, strip_markers, tier_styles};
+    use crate::config::Theme;
+
+    fn span(text: &str, bold: bool, italic: bool) -> HeaderSpan {
+        HeaderSpan {
+            text: String::from(text),
+            bold,
+            italic,
+        }
+    }
+
+    #[test]
+    fn emphasis() {
+        assert_eq!(
+            header_spans("Hello **world** and *you*"),
+            vec![
+                span("Hello ", false, false),
+                span("world", true, false),
+                span(" and ", false, false),
+                span("you", false, true),
+            ]
+        );
+        assert_eq!(header_spans("***x***"), vec![span("x", true, true)]);
+    }
+
+    #[test]
+    fn markers_are_stripped() {
+        assert_eq!(strip_markers("**x**"), "x");
+        assert_eq!(strip_markers("__bold__ _it_ `code`"), "bold it code");
+    }
+
+    #[test]
+    fn literal_markers() {
+        assert_eq!(strip_markers("snake_case_name"), "snake_case_name");
+        assert_eq!(strip_markers("2 * 3"), "2 * 3");
+        assert_eq!(strip_markers("`**not bold**`"), "**not bold**");
+        assert_eq!(strip_markers("**a** **b"), "a **b");
+    }
+
+    #[test]
+    fn tier_styles_from_skin() {
+        let skin = RatSkin {
+            skin: Theme::default().skin,
+        };
+        let styles = tier_styles(&skin);
+        assert_eq!(styles.len(), 6);
+        assert_eq!(
+            styles[0],
+            Style::default()
+                .fg(Color::Indexed(75))
+                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+        );
+        assert_eq!(styles[5], Style::default().fg(Color::Indexed(244)));
+    }
+}
@@ -0,0 +1,185 @@
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+use ratskin::RatSkin;
+
+pub const COLOR_KBD_FG: Color = Color::Indexed(252);
+pub const COLOR_KBD_BG: Color = Color::Indexed(238);
+const COLOR_COMMENT: Color = Color::Indexed(242);
+
+const KBD_OPEN: &str = "<kbd>";
+const KBD_CLOSE: &str = "</kbd>";
+const COMMENT_OPEN: &str = "<!--";
+const COMMENT_CLOSE: &str = "-->";
+
+// Minimal inline HTML rendering, on top of the already styled (and wrapped) lines.
+pub struct InlineHtml {
+    // Styles of inline code and code blocks, where HTML must be left alone.
+    code_styles: Vec<Style>,
+}
+
+impl InlineHtml {
+    pub fn new(skin: &RatSkin) -> Self {
+        let sample = skin.parse(RatSkin::parse_text("`x`\n```\nx\n```"), 1);
+        let code_styles = sample
+            .iter()
+            .flat_map(|line| line.spans.iter())
+            .filter(|span| span.content.trim() == "x")
+            .map(|span| span.style)
+            .collect();
+        InlineHtml { code_styles }
+    }
+
+    // Replace `<kbd>...</kbd>` with a keycap-like styled span.
+    // The content may be split into several spans, e.g. `<kbd>**Ctrl**</kbd>`.
+    pub fn capture_kbd<'a>(&self, line: Line<'a>) -> Line<'a> {
+        if !line
+            .spans
+            .iter()
+            .any(|span| span.content.contains(KBD_OPEN))
+        {
+            return line;
+        }
+
+        let kbd_style = Style::default().fg(COLOR_KBD_FG).bg(COLOR_KBD_BG);
+        let mut spans = Vec::with_capacity(line.spans.len());
+        let mut in_kbd = false;
+        for span in line.spans {
+            if self.code_styles.contains(&span.style) {
+                spans.push(span);
+                continue;
+            }
+            let style = span.style;
+            let mut rest: &str = &span.content;
+            loop {
+                let tag = if in_kbd { KBD_CLOSE } else { KBD_OPEN };
+                let (before, after) = match rest.split_once(tag) {
+                    Some((before, after)) => (before, Some(after)),
+                    None => (rest, None),
+                };
+                if !before.is_empty() {
+                    let segment_style = if in_kbd {
+                        style.patch(kbd_style)
+                    } else {
+                        style
+                    };
+                    spans.push(Span::styled(before.to_owned(), segment_style));
+                }
+                let Some(after) = after else {
+                    break;
+                };
+                // Pad the keycap on both sides.
+                spans.push(Span::styled(" ", kbd_style));
+                in_kbd = !in_kbd;
+                rest = after;
+            }
+        }
+        Line::from(spans)
+    }
+
+    // Dim `<!-- ... -->` comments, see `Config::show_html_comments`.
+    // `in_comment` carries over to the next line, for comments spanning several lines.
+    pub fn dim_comments<'a>(&self, line: Line<'a>, in_comment: &mut bool) -> Line<'a> {
+        if !*in_comment
+            && !line
+                .spans
+                .iter()
+                .any(|span| span.content.contains(COMMENT_OPEN))
+        {
+            return line;
+        }
+
+        let comment_style = Style::default().fg(COLOR_COMMENT);
+        let mut spans = Vec::with_capacity(line.spans.len());
+        for span in line.spans {
+            if self.code_styles.contains(&span.style) {
+                spans.push(span);
+                continue;
+            }
+            let style = span.style;
+            let mut rest: &str = &span.content;
+            loop {
+                let tag = if *in_comment {
+                    COMMENT_CLOSE
+                } else {
+                    COMMENT_OPEN
+                };
+                let Some((before, after)) = rest.split_once(tag) else {
+                    if !rest.is_empty() {
+                        let rest_style = if *in_comment {
+                            style.patch(comment_style)
+                        } else {
+                            style
+                        };
+                        spans.push(Span::styled(rest.to_owned(), rest_style));
+                    }
+                    break;
+                };
+                if *in_comment {
+                    spans.push(Span::styled(
+                        format!("{before}{COMMENT_CLOSE}"),
+                        style.patch(comment_style),
+                    ));
+                } else {
+                    if !before.is_empty() {
+                        spans.push(Span::styled(before.to_owned(), style));
+                    }
+                    spans.push(Span::styled(COMMENT_OPEN, style.patch(comment_style)));
+                }
+                *in_comment = !*in_comment;
+                rest = after;
+            }
+        }
+        Line::from(spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use ratatui::{
+        style::Stylize as _,
+        text::{Line, Span},
+    };
+    use ratskin::RatSkin;
+
+    use crate::markdown::html::{COLOR_KBD_BG, COLOR_KBD_FG, InlineHtml};
+
+    #[test]
+    fn kbd() {
+        let skin = RatSkin::default();
+        let html = InlineHtml::new(&skin);
+        let line = skin
+            .parse(
+                RatSkin::parse_text("Press <kbd>Ctrl</kbd>+<kbd>**C**</kbd>"),
+                80,
+            )
+            .remove(0);
+
+        assert_eq!(
+            Line::from(vec![
+                Span::from("Press "),
+                Span::from(" ").fg(COLOR_KBD_FG).bg(COLOR_KBD_BG),
+                Span::from("Ctrl").fg(COLOR_KBD_FG).bg(COLOR_KBD_BG),
+                Span::from(" ").fg(COLOR_KBD_FG).bg(COLOR_KBD_BG),
+                Span::from("+"),
+                Span::from(" ").fg(COLOR_KBD_FG).bg(COLOR_KBD_BG),
+                Span::from("C").bold().fg(COLOR_KBD_FG).bg(COLOR_KBD_BG),
+                Span::from(" ").fg(COLOR_KBD_FG).bg(COLOR_KBD_BG),
+            ]),
+            html.capture_kbd(line),
+        );
+    }
+
+    #[test]
+    fn kbd_in_code() {
+        let skin = RatSkin::default();
+        let html = InlineHtml::new(&skin);
+        let line = skin
+            .parse(RatSkin::parse_text("Use `<kbd>` tags"), 80)
+            .remove(0);
+
+        assert_eq!(line.clone(), html.capture_kbd(line));
+    }
+}
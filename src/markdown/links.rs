@@ -1,32 +1,262 @@
 #![allow(clippy::string_slice)] // TODO fix ASAP
+use std::fmt::Write as _;
+
+use itertools::Itertools as _;
 use ratatui::{
-    style::{Color, Stylize as _},
+    style::{Color, Style, Stylize as _},
     text::{Line, Span},
 };
 use regex::Regex;
+use unicode_width::UnicodeWidthStr as _;
 
-use crate::widget_sources::LineExtra;
+use crate::{
+    markdown::ParseOptions,
+    widget_sources::{LineExtra, THUMBNAIL_WIDTH},
+};
 
 pub const COLOR_DECOR: Color = Color::Indexed(237);
 pub const COLOR_TEXT: Color = Color::Indexed(4);
 pub const COLOR_LINK: Color = Color::Indexed(32);
 
-pub fn capture_line<'a>(line: Line<'a>, text: &str, width: u16) -> (Line<'a>, Vec<LineExtra>) {
+pub fn capture_line<'a>(
+    line: Line<'a>,
+    text: &str,
+    width: u16,
+    options: &ParseOptions,
+) -> (Line<'a>, Vec<LineExtra>) {
     let mut links = Vec::new();
 
     let mut new_spans = Vec::new();
     let mut offset = 0;
     for span in line.spans {
-        capture_links(span, &mut offset, text, width, &mut new_spans, &mut links);
+        capture_links(
+            span,
+            &mut offset,
+            text,
+            width,
+            options,
+            &mut new_spans,
+            &mut links,
+        );
     }
     (Line::from(new_spans), links)
 }
 
+// Replace inline links with numbered references, listing the urls after each paragraph, so that
+// long urls don't get split between the link text and the next lines.
+pub fn reference_links(text: &str, count: &mut usize) -> String {
+    let md_link_regex = Regex::new(r"\[([^\]]+)\]\(([^)\s]+)\)").expect("regex");
+    let mut result = String::with_capacity(text.len());
+    let mut references = Vec::new();
+    let mut in_code_block = false;
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            result.push('\n');
+        }
+        let trimmed = line.trim_start();
+        let fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+        if !in_code_block && (fence || trimmed.is_empty()) {
+            push_references(&mut result, &mut references);
+        }
+        if fence {
+            in_code_block = !in_code_block;
+        }
+        if in_code_block || fence || trimmed.is_empty() {
+            result.push_str(line);
+        } else {
+            // Odd segments are inline code.
+            for (i, segment) in line.split('`').enumerate() {
+                if i > 0 {
+                    result.push('`');
+                }
+                if i % 2 == 1 {
+                    result.push_str(segment);
+                    continue;
+                }
+                let replaced = md_link_regex.replace_all(segment, |captures: &regex::Captures| {
+                    *count += 1;
+                    // A no-break space, so that the url isn't wrapped away from its number.
+                    references.push(format!("[{count}]:\u{a0}{}", &captures[2]));
+                    format!("[{}][{count}]", &captures[1])
+                });
+                result.push_str(&replaced);
+            }
+        }
+    }
+    if !references.is_empty() {
+        result.push('\n');
+        push_references(&mut result, &mut references);
+        result.pop();
+    }
+    result
+}
+
+// A `[label]: url "title"` definition of reference-style links, see `resolve_link_references`.
+#[derive(Debug, PartialEq)]
+pub struct LinkDefinition {
+    pub label: String,
+    pub url: String,
+    pub title: Option<String>,
+}
+
+// Replace reference-style links (`[text][label]`, `[label][]` and `[label]`) with inline links,
+// and remove the definition lines, which are not rendered. Returns the definitions in order.
+pub fn resolve_link_references(text: &str) -> (String, Vec<LinkDefinition>) {
+    let definition_regex =
+        Regex::new(r#"^ {0,3}\[([^\]^][^\]]*)\]:\s*<?([^\s>]+)>?(?:\s+["'(](.*)["')])?\s*$"#)
+            .expect("regex");
+    let mut definitions: Vec<LinkDefinition> = Vec::new();
+    let mut kept = Vec::new();
+    let mut in_code_block = false;
+    for line in text.split('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code_block = !in_code_block;
+        } else if !in_code_block && let Some(captures) = definition_regex.captures(line) {
+            // The label is shown as written, and only normalized for matching.
+            let label = captures[1].trim();
+            // The first definition of a label wins.
+            if !definitions
+                .iter()
+                .any(|definition| normalize_label(&definition.label) == normalize_label(label))
+            {
+                definitions.push(LinkDefinition {
+                    label: label.to_owned(),
+                    url: captures[2].to_owned(),
+                    title: captures.get(3).map(|title| title.as_str().to_owned()),
+                });
+            }
+            continue;
+        }
+        kept.push(line);
+    }
+    if definitions.is_empty() {
+        return (text.to_owned(), definitions);
+    }
+
+    let link_regex = Regex::new(r"\[([^\]]+)\](?:\[([^\]]*)\])?").expect("regex");
+    let task_regex = Regex::new(r"^\s*(?:[-*+]|\d+[.)])\s+$").expect("regex");
+    let mut result = String::with_capacity(text.len());
+    in_code_block = false;
+    for (i, line) in kept.into_iter().enumerate() {
+        if i > 0 {
+            result.push('\n');
+        }
+        let trimmed = line.trim_start();
+        let fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+        if fence {
+            in_code_block = !in_code_block;
+        }
+        if in_code_block || fence {
+            result.push_str(line);
+            continue;
+        }
+        // Odd segments are inline code.
+        for (i, segment) in line.split('`').enumerate() {
+            if i > 0 {
+                result.push('`');
+            }
+            if i % 2 == 1 {
+                result.push_str(segment);
+                continue;
+            }
+            let mut last_end = 0;
+            for captures in link_regex.captures_iter(segment) {
+                let Some(full_match) = captures.get(0) else {
+                    continue;
+                };
+                // Inline links, and shortcuts that are followed by something else.
+                let rest = &segment[full_match.end()..];
+                if captures.get(2).is_none() && (rest.starts_with('(') || rest.starts_with('[')) {
+                    continue;
+                }
+                // Task markers like `- [x] done`.
+                if i == 0
+                    && captures.get(2).is_none()
+                    && matches!(&captures[1], " " | "x" | "X")
+                    && task_regex.is_match(&segment[..full_match.start()])
+                {
+                    continue;
+                }
+                let label = match captures.get(2) {
+                    Some(label) if !label.as_str().is_empty() => label.as_str(),
+                    _ => &captures[1],
+                };
+                let label = normalize_label(label);
+                let Some(definition) = definitions
+                    .iter()
+                    .find(|definition| normalize_label(&definition.label) == label)
+                else {
+                    continue;
+                };
+                result.push_str(&segment[last_end..full_match.start()]);
+                write!(result, "[{}]({})", &captures[1], definition.url).expect("write to string");
+                last_end = full_match.end();
+            }
+            result.push_str(&segment[last_end..]);
+        }
+    }
+    (result, definitions)
+}
+
+// Labels match case-insensitively, and regardless of whitespace.
+fn normalize_label(label: &str) -> String {
+    label.split_whitespace().join(" ").to_lowercase()
+}
+
+// A "References" section listing the definitions, see `Config::show_link_definitions`.
+pub fn link_definitions_section(definitions: &[LinkDefinition]) -> String {
+    let mut section = String::from("\n\n## References\n\n");
+    for definition in definitions {
+        write!(section, "* [{}]({})", definition.label, definition.url).expect("write to string");
+        if let Some(title) = &definition.title {
+            write!(section, " {title}").expect("write to string");
+        }
+        section.push('\n');
+    }
+    section
+}
+
+fn push_references(result: &mut String, references: &mut Vec<String>) {
+    if !references.is_empty() {
+        result.push('\n');
+        result.push_str(&references.join("\n"));
+        result.push('\n');
+        references.clear();
+    }
+}
+
+// Whether a url points to an image file, by its extension.
+pub fn is_image_url(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit_once('.').is_some_and(|(_, extension)| {
+        [
+            "png", "jpg", "jpeg", "gif", "webp", "bmp", "svg", "avif", "tif", "tiff",
+        ]
+        .iter()
+        .any(|image| extension.eq_ignore_ascii_case(image))
+    })
+}
+
+fn url_span<'a>(url: &str, parent_style: Style, options: &ParseOptions) -> Span<'a> {
+    let color = options
+        .image_link_color
+        .filter(|_| is_image_url(url))
+        .unwrap_or(COLOR_LINK);
+    let span = Span::from(url.to_owned()).style(parent_style).fg(color);
+    if options.link_underline {
+        span.underlined()
+    } else {
+        span
+    }
+}
+
 pub fn capture_links<'a>(
     span: Span<'a>,
     offset: &mut u16,
     text: &str,
     width: u16,
+    options: &ParseOptions,
     new_spans: &mut Vec<Span<'a>>,
     links: &mut Vec<LineExtra>,
 ) {
@@ -40,6 +270,8 @@ pub fn capture_links<'a>(
     let parent_style = span.style;
 
     let mut found_link = false;
+    // Columns taken out of the line by thumbnails, which are narrower than their markdown.
+    let mut removed: u16 = 0;
     for cap in md_link_regex.captures_iter(span_content) {
         let Some(full_match) = cap.get(0) else {
             continue;
@@ -49,6 +281,30 @@ pub fn capture_links<'a>(
         let match_start = full_match.start();
         let match_end = full_match.end();
 
+        if options.inline_image_thumbnails
+            && match_start > last_end
+            && span_content[..match_start].ends_with('!')
+            && full_match.as_str().ends_with(')')
+            && let Some(url) = cap.get(2)
+        {
+            if match_start - 1 > last_end {
+                spans.push(
+                    Span::from(span_content[last_end..match_start - 1].to_string())
+                        .style(parent_style),
+                );
+            }
+            let start = *offset + spans.iter().map(|span| span.width() as u16).sum::<u16>();
+            spans.push(Span::from(" ".repeat(usize::from(THUMBNAIL_WIDTH))).style(parent_style));
+            links.push(LineExtra::Thumbnail(
+                url.as_str().to_owned(),
+                start,
+                start + THUMBNAIL_WIDTH,
+            ));
+            removed += columns(&span_content[match_start - 1..match_end]) - THUMBNAIL_WIDTH;
+            last_end = match_end;
+            continue;
+        }
+
         // Add any non-link text before this match
         if match_start > last_end {
             spans.push(
@@ -68,12 +324,7 @@ pub fn capture_links<'a>(
             );
             spans.push(Span::from("]").style(decor_style));
             spans.push(Span::from("(").style(decor_style));
-            spans.push(
-                Span::from(url_str.to_owned())
-                    .style(parent_style)
-                    .fg(COLOR_LINK)
-                    .underlined(),
-            );
+            spans.push(url_span(url_str, parent_style, options));
             if full_match.as_str().ends_with(')') {
                 spans.push(Span::from(")").style(decor_style));
             }
@@ -83,7 +334,7 @@ pub fn capture_links<'a>(
             // Now try to find the full url in the original text again, might have
             // been split up and we don't want to open cut-off URLs.
             // This is code block is pretty ugly, but it works for now.
-            if match_end as u16 == width
+            if *offset + columns(&span_content[..match_end]) - removed == width
                 && let Some(pos) = text.find(url_str)
             {
                 let line_end = text[pos..].find('\n').map_or(text.len(), |n| pos + n);
@@ -95,8 +346,8 @@ pub fn capture_links<'a>(
 
             links.push(LineExtra::Link(
                 url_str.to_owned(),
-                *offset + url.start() as u16,
-                *offset + url.end() as u16,
+                *offset + columns(&span_content[..url.start()]) - removed,
+                *offset + columns(&span_content[..url.end()]) - removed,
             ));
         }
     }
@@ -109,16 +360,33 @@ pub fn capture_links<'a>(
             *offset += span.width() as u16;
         }
         new_spans.append(&mut spans);
+    } else if options.autolink_bare_urls || is_reference_definition(span_content) {
+        capture_urls(span, offset, text, width, options, new_spans, links);
     } else {
-        capture_urls(span, offset, text, width, new_spans, links);
+        *offset += span.width() as u16;
+        new_spans.push(span);
     }
 }
 
+// Link positions are display columns, where wide characters like CJK and emoji take two, and
+// combining marks none.
+fn columns(text: &str) -> u16 {
+    u16::try_from(text.width()).unwrap_or(u16::MAX)
+}
+
+// Like the `[1]: http://a` lines of `LinkWrap::Reference`, explicit links even without autolinks.
+fn is_reference_definition(content: &str) -> bool {
+    Regex::new(r"^\s*\[[^\]]+\]:[ \u{a0}]\S+\s*$")
+        .expect("regex")
+        .is_match(content)
+}
+
 pub fn capture_urls<'a>(
     span: Span<'a>,
     offset: &mut u16,
     text: &str,
     width: u16,
+    options: &ParseOptions,
     new_spans: &mut Vec<Span<'a>>,
     links: &mut Vec<LineExtra>,
 ) {
@@ -146,19 +414,14 @@ pub fn capture_urls<'a>(
         }
 
         let mut url_str = cap.as_str();
-        spans.push(
-            Span::from(url_str.to_owned())
-                .style(parent_style)
-                .fg(COLOR_LINK)
-                .underlined(),
-        );
+        spans.push(url_span(url_str, parent_style, options));
 
         last_end = match_end;
 
         // Now try to find the full url in the original text again, might have
         // been split up and we don't want to open cut-off URLs.
         // This is code block is pretty ugly, but it works for now.
-        if match_end as u16 == width
+        if *offset + columns(&span_content[..match_end]) == width
             && let Some(pos) = text.find(url_str)
         {
             let line_end = text[pos..].find('\n').map_or(text.len(), |n| pos + n);
@@ -170,8 +433,8 @@ pub fn capture_urls<'a>(
 
         links.push(LineExtra::Link(
             url_str.to_owned(),
-            *offset + cap.start() as u16,
-            *offset + cap.end() as u16,
+            *offset + columns(&span_content[..cap.start()]),
+            *offset + columns(&span_content[..cap.end()]),
         ));
     }
     if found_link {
@@ -193,10 +456,20 @@ pub fn capture_urls<'a>(
 #[cfg(test)]
 mod tests {
     use pretty_assertions::assert_eq;
-    use ratatui::{style::Stylize as _, text::Span};
+    use ratatui::{
+        style::{Modifier, Stylize as _},
+        text::{Line, Span},
+    };
+    use unicode_width::UnicodeWidthStr as _;
 
     use crate::{
-        markdown::links::{COLOR_DECOR, COLOR_LINK, COLOR_TEXT, capture_links},
+        markdown::{
+            ParseOptions,
+            links::{
+                COLOR_DECOR, COLOR_LINK, COLOR_TEXT, LinkDefinition, capture_line, capture_links,
+                is_image_url, link_definitions_section, reference_links, resolve_link_references,
+            },
+        },
         widget_sources::LineExtra,
     };
 
@@ -209,7 +482,15 @@ mod tests {
         let mut new_spans = Vec::new();
         let mut links = Vec::new();
         let mut offset = preceding_span.width() as u16;
-        capture_links(span, &mut offset, text, width, &mut new_spans, &mut links);
+        capture_links(
+            span,
+            &mut offset,
+            text,
+            width,
+            &ParseOptions::default(),
+            &mut new_spans,
+            &mut links,
+        );
 
         assert_eq!(
             vec![
@@ -237,7 +518,15 @@ mod tests {
         let width = 80;
         let mut new_spans = Vec::new();
         let mut links = Vec::new();
-        capture_links(span, &mut 0, text, width, &mut new_spans, &mut links);
+        capture_links(
+            span,
+            &mut 0,
+            text,
+            width,
+            &ParseOptions::default(),
+            &mut new_spans,
+            &mut links,
+        );
 
         assert_eq!(
             vec![
@@ -266,4 +555,255 @@ mod tests {
             links,
         );
     }
+
+    #[test]
+    fn wide_and_combining_columns() {
+        for (text, start, end) in [
+            // Two columns per CJK character.
+            ("日本語 [語](http://a)", 12, 20),
+            // Emoji are wide too.
+            ("🦀🦀 [a](http://a)", 9, 17),
+            // Combining marks take no column.
+            ("e\u{301}e\u{301} [a](http://a)", 7, 15),
+        ] {
+            let mut new_spans = Vec::new();
+            let mut links = Vec::new();
+            capture_links(
+                Span::from(text),
+                &mut 0,
+                text,
+                80,
+                &ParseOptions::default(),
+                &mut new_spans,
+                &mut links,
+            );
+            let line = Line::from(new_spans).to_string();
+            assert_eq!(
+                links,
+                vec![LineExtra::Link("http://a".to_owned(), start, end)],
+                "{text}"
+            );
+            assert_eq!(
+                line.width(),
+                usize::from(end) + 1,
+                "the url ends before the `)`: {text}"
+            );
+        }
+
+        let text = "語 http://a 語";
+        let mut new_spans = Vec::new();
+        let mut links = Vec::new();
+        capture_links(
+            Span::from(text),
+            &mut 0,
+            text,
+            80,
+            &ParseOptions::default(),
+            &mut new_spans,
+            &mut links,
+        );
+        assert_eq!(links, vec![LineExtra::Link("http://a".to_owned(), 3, 11)]);
+    }
+
+    #[test]
+    fn thumbnails() {
+        let text = "x ![i](a.png) [b](http://b)";
+        let span = Span::from(text);
+        let options = ParseOptions {
+            inline_image_thumbnails: true,
+            ..Default::default()
+        };
+        let mut new_spans = Vec::new();
+        let mut links = Vec::new();
+        capture_links(span, &mut 0, text, 80, &options, &mut new_spans, &mut links);
+
+        assert_eq!("x    [b](http://b)", Line::from(new_spans).to_string());
+        assert_eq!(
+            vec![
+                LineExtra::Thumbnail("a.png".to_owned(), 2, 4),
+                LineExtra::Link("http://b".to_owned(), 9, 17),
+            ],
+            links,
+        );
+    }
+
+    #[test]
+    fn without_underline() {
+        let text = "[a](http://a) http://b";
+        let span = Span::from("[a](http://a) http://b");
+        let options = ParseOptions {
+            link_underline: false,
+            ..Default::default()
+        };
+        let mut new_spans = Vec::new();
+        let mut links = Vec::new();
+        capture_links(span, &mut 0, text, 80, &options, &mut new_spans, &mut links);
+
+        assert_eq!(
+            vec![
+                Span::from("[").style(COLOR_DECOR),
+                Span::from("a").fg(COLOR_TEXT),
+                Span::from("]").style(COLOR_DECOR),
+                Span::from("(").style(COLOR_DECOR),
+                Span::from("http://a").fg(COLOR_LINK),
+                Span::from(")").style(COLOR_DECOR),
+                Span::from(" http://b"),
+            ],
+            new_spans
+        );
+        for span in new_spans {
+            assert!(
+                !span.style.add_modifier.contains(Modifier::UNDERLINED),
+                "{span:?} should not be underlined"
+            );
+        }
+    }
+
+    #[test]
+    fn image_links() {
+        assert!(is_image_url("./shots/a.PNG"));
+        assert!(is_image_url("http://a/b.jpg?size=2#top"));
+        assert!(!is_image_url("http://a/png"));
+        assert!(!is_image_url("./other.md"));
+
+        let text = "[a](a.png) [b](b.md)";
+        let url_colors = |options: &ParseOptions| {
+            let mut new_spans = Vec::new();
+            let mut links = Vec::new();
+            capture_links(
+                Span::from(text),
+                &mut 0,
+                text,
+                80,
+                options,
+                &mut new_spans,
+                &mut links,
+            );
+            new_spans
+                .into_iter()
+                .filter(|span| span.content.contains('.'))
+                .map(|span| span.style.fg)
+                .collect::<Vec<_>>()
+        };
+        let options = ParseOptions {
+            image_link_color: Some(Color::Indexed(141)),
+            ..Default::default()
+        };
+        assert_eq!(
+            url_colors(&options),
+            [Some(Color::Indexed(141)), Some(COLOR_LINK)]
+        );
+        let options = ParseOptions {
+            image_link_color: None,
+            ..Default::default()
+        };
+        assert_eq!(
+            url_colors(&options),
+            [Some(COLOR_LINK), Some(COLOR_LINK)],
+            "not distinct"
+        );
+    }
+
+    #[test]
+    fn bare_urls() {
+        let text = "see http://localhost:8080 or [docs](http://docs)";
+        let line = || {
+            Line::from(vec![
+                Span::from("see http://localhost:8080 or "),
+                Span::from("[docs](http://docs)"),
+            ])
+        };
+
+        let (_, links) = capture_line(line(), text, 80, &ParseOptions::default());
+        assert_eq!(
+            vec![
+                LineExtra::Link("http://localhost:8080".to_owned(), 4, 25),
+                LineExtra::Link("http://docs".to_owned(), 36, 47),
+            ],
+            links,
+        );
+
+        let options = ParseOptions {
+            autolink_bare_urls: false,
+            ..Default::default()
+        };
+        let (new_line, links) = capture_line(line(), text, 80, &options);
+        assert_eq!(
+            vec![LineExtra::Link("http://docs".to_owned(), 36, 47)],
+            links,
+            "only the explicit link"
+        );
+        assert_eq!(
+            new_line.spans[0],
+            Span::from("see http://localhost:8080 or ")
+        );
+
+        let (_, links) = capture_line(Line::from("[1]: http://a"), "[1]: http://a", 80, &options);
+        assert_eq!(
+            vec![LineExtra::Link("http://a".to_owned(), 5, 13)],
+            links,
+            "reference definitions are explicit"
+        );
+    }
+
+    #[test]
+    fn references() {
+        let mut count = 0;
+        let text = "[a](http://a) `[b](c)`\n```\n[d](e)\n```\n\n[f](http://f)";
+        assert_eq!(
+            "[a][1] `[b](c)`\n\n[1]:\u{a0}http://a\n```\n[d](e)\n```\n\n[f][2]\n\n[2]:\u{a0}http://f",
+            reference_links(text, &mut count)
+        );
+        assert_eq!(2, count);
+
+        let references = reference_links("[a](http://a/long/url)", &mut count);
+        assert!(
+            references
+                .lines()
+                .any(|line| line == "[3]:\u{a0}http://a/long/url"),
+            "the url stays on the line of its number: {references:?}"
+        );
+    }
+
+    #[test]
+    fn link_references() {
+        let text = "See [the docs][Docs], [Docs][] or [docs], not `[docs]`.\n\n[Docs]: http://docs \"The docs\"\n[other]:  <http://other>\n```\n[code]: http://code\n```";
+        let (resolved, definitions) = resolve_link_references(text);
+        assert_eq!(
+            "See [the docs](http://docs), [Docs](http://docs) or [docs](http://docs), not `[docs]`.\n\n```\n[code]: http://code\n```",
+            resolved
+        );
+        assert_eq!(
+            vec![
+                LinkDefinition {
+                    label: "Docs".to_owned(),
+                    url: "http://docs".to_owned(),
+                    title: Some("The docs".to_owned()),
+                },
+                LinkDefinition {
+                    label: "other".to_owned(),
+                    url: "http://other".to_owned(),
+                    title: None,
+                },
+            ],
+            definitions
+        );
+        assert_eq!(
+            "\n\n## References\n\n* [Docs](http://docs) The docs\n* [other](http://other)\n",
+            link_definitions_section(&definitions)
+        );
+
+        let text = "- [x] done\n- [ ] todo, [x]\n\n[x]: http://x";
+        assert_eq!(
+            "- [x] done\n- [ ] todo, [x](http://x)\n",
+            resolve_link_references(text).0,
+            "task markers are not links"
+        );
+
+        let text = "[x] is not defined, [a](http://a) is inline\n\n[y]: http://y";
+        assert_eq!(
+            "[x] is not defined, [a](http://a) is inline\n",
+            resolve_link_references(text).0
+        );
+    }
 }
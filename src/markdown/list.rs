@@ -0,0 +1,71 @@
+use minimad::{Composite, Line as MadLine, Text};
+use ratatui::{
+    style::{Color, Stylize as _},
+    text::{Line, Span},
+};
+use ratskin::RatSkin;
+use unicode_width::UnicodeWidthStr as _;
+
+use crate::markdown::ListItem;
+
+// Like the bullets of the default theme, see `Theme::default`.
+const COLOR_BULLET: Color = Color::Indexed(63);
+
+pub const TASK_UNCHECKED: &str = "☐";
+pub const TASK_CHECKED: &str = "☑";
+
+// Render a `Block::List`, with the wrapped lines of each item indented below its text, and nested
+// items indented by two columns per level. Loose lists have a blank line between items.
+// The first line of a task item comes with whether it is checked.
+pub fn list_lines(
+    items: &[ListItem],
+    loose: bool,
+    width: u16,
+    skin: &RatSkin,
+) -> Vec<(Line<'static>, Option<bool>)> {
+    let mut lines = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        if loose && i > 0 {
+            lines.push((Line::default(), None));
+        }
+        let indent = 2 * usize::from(item.depth);
+        let task = item.task.map(|checked| {
+            if checked {
+                TASK_CHECKED
+            } else {
+                TASK_UNCHECKED
+            }
+        });
+        let marker = match (item.number, task) {
+            (Some(number), Some(task)) => format!("{number}. {task} "),
+            (Some(number), None) => format!("{number}. "),
+            (None, Some(task)) => format!("{task} "),
+            (None, None) => String::from("• "),
+        };
+        let hanging = indent + marker.width();
+        let text_width = u16::try_from(usize::from(width).saturating_sub(hanging))
+            .unwrap_or(width)
+            .max(1);
+        let text = Text {
+            lines: vec![MadLine::Normal(Composite::from_inline(&item.text))],
+        };
+        for (j, line) in skin.parse(text, text_width).into_iter().enumerate() {
+            let mut spans = Vec::with_capacity(line.spans.len() + 2);
+            if j == 0 {
+                if indent > 0 {
+                    spans.push(Span::from(" ".repeat(indent)));
+                }
+                spans.push(Span::from(marker.clone()).fg(COLOR_BULLET));
+            } else {
+                spans.push(Span::from(" ".repeat(hanging)));
+            }
+            spans.extend(
+                line.spans
+                    .into_iter()
+                    .map(|span| Span::styled(span.content.into_owned(), span.style)),
+            );
+            lines.push((Line::from(spans), item.task.filter(|_| j == 0)));
+        }
+    }
+    lines
+}
@@ -0,0 +1,37 @@
+use unicode_width::UnicodeWidthChar as _;
+
+// Cut off words that would not fit on a line of `width`, with an ellipsis.
+// Markdown links are left alone, they render shorter than their source.
+pub fn truncate_long_words(text: &str, width: u16) -> String {
+    let max_width = usize::from(width.saturating_sub(1));
+    let mut result = String::with_capacity(text.len());
+    let mut word = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() {
+            push_word(&mut result, &word, max_width);
+            word.clear();
+            result.push(c);
+        } else {
+            word.push(c);
+        }
+    }
+    push_word(&mut result, &word, max_width);
+    result
+}
+
+fn push_word(result: &mut String, word: &str, max_width: usize) {
+    let word_width: usize = word.chars().map(|c| c.width().unwrap_or_default()).sum();
+    if word_width <= max_width + 1 || word.contains("](") {
+        result.push_str(word);
+        return;
+    }
+    let mut used = 0;
+    for c in word.chars() {
+        used += c.width().unwrap_or_default();
+        if used > max_width {
+            break;
+        }
+        result.push(c);
+    }
+    result.push('…');
+}
@@ -0,0 +1,279 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+use crate::markdown::links::{COLOR_DECOR, COLOR_LINK};
+
+// Like the bullets and details markers.
+const COLOR_MARKER: Color = Color::Indexed(63);
+const COLOR_HEADER: Color = Color::Indexed(75);
+const COLOR_CODE: Color = Color::Indexed(179);
+
+// Highlight the markdown source itself, for the raw view: the markers of headers, lists, quotes
+// and emphasis stay visible, as do code and links. Lines are not wrapped, so that there is one per
+// line of the source.
+pub fn raw_lines(text: &str) -> Vec<Line<'static>> {
+    let mut fence: Option<String> = None;
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        if let Some(marker) = &fence {
+            let closes = fence_marker(line).is_some_and(|(run, rest)| {
+                run.starts_with(marker.as_str()) && rest.trim().is_empty()
+            });
+            let color = if closes { COLOR_MARKER } else { COLOR_CODE };
+            if closes {
+                fence = None;
+            }
+            lines.push(Line::styled(line.to_owned(), Style::new().fg(color)));
+        } else if let Some((run, _)) = fence_marker(line) {
+            fence = Some(run);
+            lines.push(Line::styled(line.to_owned(), Style::new().fg(COLOR_MARKER)));
+        } else {
+            lines.push(markdown_line(line));
+        }
+    }
+    lines
+}
+
+// The run of at least three backticks or tildes that opens or closes a code fence, and the rest
+// of the line.
+fn fence_marker(line: &str) -> Option<(String, &str)> {
+    let trimmed = line.trim_start();
+    let c = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = trimmed.chars().take_while(|x| *x == c).count();
+    (len >= 3).then(|| (c.to_string().repeat(len), trimmed.split_at(len).1))
+}
+
+fn markdown_line(line: &str) -> Line<'static> {
+    let marker = Style::new().fg(COLOR_MARKER);
+    let mut spans = Vec::new();
+
+    // Indentation and quote markers.
+    let prefix = line
+        .char_indices()
+        .find(|(_, c)| !matches!(c, ' ' | '\t' | '>'))
+        .map_or(line.len(), |(i, _)| i);
+    let (prefix, rest) = line.split_at(prefix);
+    if !prefix.is_empty() {
+        spans.push(Span::styled(prefix.to_owned(), marker));
+    }
+
+    if is_rule(rest) {
+        spans.push(Span::styled(rest.to_owned(), marker));
+        return Line::from(spans);
+    }
+
+    let hashes = rest.chars().take_while(|c| *c == '#').count();
+    let (hashes, title) = rest.split_at(hashes);
+    if (1..=6).contains(&hashes.len()) && (title.is_empty() || title.starts_with(' ')) {
+        spans.push(Span::styled(hashes.to_owned(), marker));
+        spans.push(Span::styled(
+            title.to_owned(),
+            Style::new().fg(COLOR_HEADER).add_modifier(Modifier::BOLD),
+        ));
+        return Line::from(spans);
+    }
+
+    let rest = if let Some(len) = list_marker_len(rest) {
+        let (bullet, rest) = rest.split_at(len);
+        spans.push(Span::styled(bullet.to_owned(), marker));
+        rest
+    } else {
+        rest
+    };
+    inline_spans(rest, &mut spans);
+    Line::from(spans)
+}
+
+// Thematic breaks, and setext header underlines.
+fn is_rule(text: &str) -> bool {
+    let mut chars = text.chars().filter(|c| !c.is_whitespace());
+    let Some(first) = chars.next().filter(|c| matches!(c, '-' | '*' | '_' | '=')) else {
+        return false;
+    };
+    let mut len = 1;
+    for c in chars {
+        if c != first {
+            return false;
+        }
+        len += 1;
+    }
+    len >= 3 || first == '='
+}
+
+// The length of a `- `, `* `, `+ `, `1. ` or `1) ` list item marker, including the space.
+fn list_marker_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    let len = if digits == 0 {
+        bytes
+            .first()
+            .filter(|b| matches!(b, b'-' | b'*' | b'+'))
+            .map(|_| 1)?
+    } else if digits <= 9 && matches!(bytes.get(digits), Some(b'.' | b')')) {
+        digits + 1
+    } else {
+        return None;
+    };
+    matches!(bytes.get(len), Some(b' ' | b'\t')).then_some(len + 1)
+}
+
+// Inline code, emphasis markers, and the brackets and urls of links and images.
+fn inline_spans(text: &str, spans: &mut Vec<Span<'static>>) {
+    let marker = Style::new().fg(COLOR_MARKER);
+    let decor = Style::new().fg(COLOR_DECOR);
+    let mut plain = String::new();
+    let mut chars = text.char_indices().peekable();
+    let flush = |plain: &mut String, spans: &mut Vec<Span<'static>>| {
+        if !plain.is_empty() {
+            spans.push(Span::from(std::mem::take(plain)));
+        }
+    };
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                plain.push(c);
+                if let Some((_, escaped)) = chars.next() {
+                    plain.push(escaped);
+                }
+            }
+            '`' => {
+                let run = 1 + chars.clone().take_while(|(_, x)| *x == '`').count();
+                let ticks = "`".repeat(run);
+                let after = i + run;
+                while chars.next_if(|(j, _)| *j < after).is_some() {}
+                let end = text
+                    .get(after..)
+                    .and_then(|rest| rest.find(&ticks))
+                    .map(|end| after + end + run);
+                if let Some(end) = end {
+                    flush(&mut plain, spans);
+                    let code = text.get(i..end).unwrap_or_default();
+                    spans.push(Span::styled(code.to_owned(), Style::new().fg(COLOR_CODE)));
+                    while chars.next_if(|(j, _)| *j < end).is_some() {}
+                } else {
+                    plain.push_str(&ticks);
+                }
+            }
+            '*' | '~' | '_' => {
+                let before = text.get(..i).and_then(|s| s.chars().next_back());
+                let after = chars.peek().map(|(_, x)| *x);
+                // Not in the middle of `snake_case` words.
+                if c == '_'
+                    && before.is_some_and(char::is_alphanumeric)
+                    && after.is_some_and(char::is_alphanumeric)
+                {
+                    plain.push(c);
+                    continue;
+                }
+                flush(&mut plain, spans);
+                let mut run = String::from(c);
+                while let Some((_, x)) = chars.next_if(|(_, x)| *x == c) {
+                    run.push(x);
+                }
+                spans.push(Span::styled(run, marker));
+            }
+            '!' if chars.peek().is_some_and(|(_, x)| *x == '[') => {
+                flush(&mut plain, spans);
+                if let Some((_, bracket)) = chars.next() {
+                    spans.push(Span::styled(format!("{c}{bracket}"), decor));
+                }
+            }
+            '[' => {
+                flush(&mut plain, spans);
+                spans.push(Span::styled(String::from(c), decor));
+            }
+            ']' if chars.peek().is_some_and(|(_, x)| *x == '(') => {
+                let close = text
+                    .get(i..)
+                    .and_then(|rest| rest.find(')'))
+                    .map(|close| i + close);
+                let Some(close) = close else {
+                    plain.push(c);
+                    continue;
+                };
+                flush(&mut plain, spans);
+                spans.push(Span::styled(String::from("]("), decor));
+                let url = text.get(i + 2..close).unwrap_or_default();
+                spans.push(Span::styled(url.to_owned(), Style::new().fg(COLOR_LINK)));
+                spans.push(Span::styled(String::from(")"), decor));
+                while chars.next_if(|(j, _)| *j <= close).is_some() {}
+            }
+            ']' => {
+                flush(&mut plain, spans);
+                spans.push(Span::styled(String::from(c), decor));
+            }
+            _ => plain.push(c),
+        }
+    }
+    flush(&mut plain, spans);
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use ratatui::{
+        style::{Modifier, Style},
+        text::{Line, Span},
+    };
+
+    use super::{COLOR_CODE, COLOR_HEADER, COLOR_MARKER, raw_lines};
+    use crate::markdown::links::{COLOR_DECOR, COLOR_LINK};
+
+    #[test]
+    fn highlight() {
+        let lines =
+            raw_lines("## Title\n> - a **b** [c](d) snake_case\n```\n# code\n```\n1. `x` ---");
+        let texts: Vec<String> = lines.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            texts,
+            [
+                "## Title",
+                "> - a **b** [c](d) snake_case",
+                "```",
+                "# code",
+                "```",
+                "1. `x` ---"
+            ],
+            "the text is unchanged"
+        );
+
+        let marker = Style::new().fg(COLOR_MARKER);
+        let decor = Style::new().fg(COLOR_DECOR);
+        assert_eq!(
+            lines[0],
+            Line::from(vec![
+                Span::styled("##", marker),
+                Span::styled(
+                    " Title",
+                    Style::new().fg(COLOR_HEADER).add_modifier(Modifier::BOLD)
+                ),
+            ])
+        );
+        assert_eq!(
+            lines[1],
+            Line::from(vec![
+                Span::styled("> ", marker),
+                Span::styled("- ", marker),
+                Span::from("a "),
+                Span::styled("**", marker),
+                Span::from("b"),
+                Span::styled("**", marker),
+                Span::from(" "),
+                Span::styled("[", decor),
+                Span::from("c"),
+                Span::styled("](", decor),
+                Span::styled("d", Style::new().fg(COLOR_LINK)),
+                Span::styled(")", decor),
+                Span::from(" snake_case"),
+            ])
+        );
+        assert_eq!(lines[3].style.fg, Some(COLOR_CODE), "not a header in code");
+        assert_eq!(
+            lines[5].spans[1],
+            Span::styled("`x`", Style::new().fg(COLOR_CODE))
+        );
+    }
+}
@@ -0,0 +1,527 @@
+use itertools::Itertools as _;
+use minimad::{Composite, Line as MadLine, TableRow, Text};
+use ratatui::{
+    layout::Alignment,
+    style::Stylize as _,
+    text::{Line, Span},
+};
+use ratskin::RatSkin;
+use unicode_width::{UnicodeWidthChar as _, UnicodeWidthStr as _};
+
+use crate::markdown::{ParseOptions, links::COLOR_DECOR};
+
+// Termimad never renders a column narrower than this.
+const MIN_COLUMN_WIDTH: usize = 3;
+
+// Termimad shrinks all columns of a table that doesn't fit alike, so wide text columns squeeze
+// numeric ones. Instead, pre-wrap the cells to our own column widths, which termimad then keeps
+// because the table fits.
+pub fn fit_columns<'a>(text: Text<'a>, width: u16, options: &ParseOptions) -> Text<'a> {
+    let mut lines = Vec::with_capacity(text.lines.len());
+    for (is_table, run) in &text.lines.into_iter().chunk_by(is_table_line) {
+        if is_table {
+            lines.extend(fit_table(run.collect(), usize::from(width), options));
+        } else {
+            lines.extend(run);
+        }
+    }
+    Text { lines }
+}
+
+fn fit_table<'a>(
+    lines: Vec<MadLine<'a>>,
+    width: usize,
+    options: &ParseOptions,
+) -> Vec<MadLine<'a>> {
+    let columns = lines
+        .iter()
+        .map(|line| match line {
+            MadLine::TableRow(row) => row.cells.len(),
+            MadLine::TableRule(rule) => rule.cells.len(),
+            _ => 0,
+        })
+        .max()
+        .unwrap_or_default();
+    // One border between each column, and one on each side.
+    let Some(available) = width.checked_sub(columns + 1) else {
+        return lines;
+    };
+    if columns == 0 {
+        return lines;
+    }
+
+    let mut natural = vec![MIN_COLUMN_WIDTH; columns];
+    let mut has_numbers = vec![false; columns];
+    let mut has_text = vec![false; columns];
+    // Rows above the first rule are the header, which doesn't tell if a column is numeric.
+    let mut in_header = matches!(lines.first(), Some(MadLine::TableRow(_)));
+    for line in &lines {
+        match line {
+            MadLine::TableRow(row) => {
+                for (i, cell) in row.cells.iter().enumerate() {
+                    natural[i] = natural[i].max(cell_width(cell));
+                    let text: String = cell.compounds.iter().map(|c| c.as_str()).collect();
+                    if in_header || text.trim().is_empty() {
+                        continue;
+                    }
+                    if is_number(&text) {
+                        has_numbers[i] = true;
+                    } else {
+                        has_text[i] = true;
+                    }
+                }
+            }
+            MadLine::TableRule(_) => in_header = false,
+            _ => {}
+        }
+    }
+    let numeric: Vec<bool> = has_numbers
+        .iter()
+        .zip(&has_text)
+        .map(|(numbers, text)| *numbers && !text)
+        .collect();
+
+    let Some(widths) = column_widths(
+        &natural,
+        &numeric,
+        available,
+        options.table_min_column_width,
+        options.table_max_column_width,
+    ) else {
+        return lines;
+    };
+    if widths == natural {
+        return lines;
+    }
+
+    let mut fitted = Vec::with_capacity(lines.len());
+    for line in lines {
+        let MadLine::TableRow(row) = line else {
+            fitted.push(line);
+            continue;
+        };
+        let mut wrapped: Vec<Vec<Composite>> = row
+            .cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| wrap_cell(cell, *width))
+            .collect();
+        let height = wrapped.iter().map(Vec::len).max().unwrap_or(1);
+        for _ in 0..height {
+            let cells = wrapped
+                .iter_mut()
+                .map(|parts| {
+                    if parts.is_empty() {
+                        Composite::new()
+                    } else {
+                        parts.remove(0)
+                    }
+                })
+                .collect();
+            fitted.push(MadLine::TableRow(TableRow { cells }));
+        }
+    }
+    fitted
+}
+
+// Render a `Block::Table`, padding the cells to the widest of their column. Columns of a table
+// that is too wide are narrowed like in `fit_columns`, cutting off cells with an ellipsis. If even
+// that doesn't fit, the lines are cut off instead.
+pub fn table_lines(
+    rows: &[Vec<String>],
+    alignments: &[Alignment],
+    width: u16,
+    skin: &RatSkin,
+    options: &ParseOptions,
+) -> Vec<Line<'static>> {
+    let cells: Vec<Vec<Line<'static>>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| cell_line(cell, skin)).collect())
+        .collect();
+    let columns = alignments.len();
+    let mut natural = vec![1; columns];
+    let mut numeric = vec![true; columns];
+    for (i, row) in cells.iter().enumerate() {
+        for (column, cell) in row.iter().enumerate().take(columns) {
+            natural[column] = natural[column].max(cell.width());
+            // The header doesn't tell if a column is numeric.
+            if i > 0 && !rows[i][column].is_empty() && !is_number(&rows[i][column]) {
+                numeric[column] = false;
+            }
+        }
+    }
+    let width = usize::from(width);
+    let separators = 3 * columns.saturating_sub(1);
+    let widths = width.checked_sub(separators).and_then(|available| {
+        column_widths(
+            &natural,
+            &numeric,
+            available,
+            options.table_min_column_width,
+            options.table_max_column_width,
+        )
+    });
+    let fits = widths.is_some();
+    let widths = widths.unwrap_or(natural);
+
+    let separator = || Span::from(" │ ").fg(COLOR_DECOR);
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    for (i, row) in cells.into_iter().enumerate() {
+        let mut spans = Vec::new();
+        for (column, cell) in row.into_iter().enumerate().take(columns) {
+            if column > 0 {
+                spans.push(separator());
+            }
+            let cell = truncate_line(cell, widths[column]);
+            let pad = widths[column].saturating_sub(cell.width());
+            let (left, right) = match alignments[column] {
+                Alignment::Left => (0, pad),
+                Alignment::Center => (pad / 2, pad - pad / 2),
+                Alignment::Right => (pad, 0),
+            };
+            if left > 0 {
+                spans.push(Span::from(" ".repeat(left)));
+            }
+            spans.extend(cell.spans.into_iter().map(
+                |span| {
+                    if i == 0 { span.bold() } else { span }
+                },
+            ));
+            if right > 0 {
+                spans.push(Span::from(" ".repeat(right)));
+            }
+        }
+        lines.push(Line::from(spans));
+        if i == 0 {
+            let rule = widths.iter().map(|width| "─".repeat(*width)).join("─┼─");
+            lines.push(Line::from(rule).fg(COLOR_DECOR));
+        }
+    }
+    if !fits {
+        lines = lines
+            .into_iter()
+            .map(|line| truncate_line(line, width))
+            .collect();
+    }
+    lines
+}
+
+// A cell with its inline markdown styled, but not as a block: `- x` is not a list item.
+fn cell_line(cell: &str, skin: &RatSkin) -> Line<'static> {
+    let text = Text {
+        lines: vec![MadLine::Normal(Composite::from_inline(cell))],
+    };
+    // Wide enough to never wrap, the markdown is at least as wide as the styled text.
+    let width = u16::try_from(cell.width() + 1).unwrap_or(u16::MAX);
+    let line = skin
+        .parse(text, width)
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    Line::from(
+        line.spans
+            .into_iter()
+            .map(|span| Span::styled(span.content.into_owned(), span.style))
+            .collect::<Vec<_>>(),
+    )
+}
+
+// Cut off a line that is wider than `width`, ending it with an ellipsis.
+fn truncate_line(line: Line<'static>, width: usize) -> Line<'static> {
+    if line.width() <= width {
+        return line;
+    }
+    let style = line.style;
+    let mut used = 0;
+    let mut spans = Vec::new();
+    let mut last_style = None;
+    for span in line.spans {
+        let mut content = String::new();
+        for c in span.content.chars() {
+            let char_width = c.width().unwrap_or_default();
+            if used + char_width + 1 > width {
+                break;
+            }
+            used += char_width;
+            content.push(c);
+        }
+        last_style = Some(span.style);
+        let full = content.len() == span.content.len();
+        if !content.is_empty() {
+            spans.push(Span::styled(content, span.style));
+        }
+        if !full {
+            break;
+        }
+    }
+    if width > 0 {
+        spans.push(Span::styled("…", last_style.unwrap_or_default()));
+    }
+    Line::from(spans).style(style)
+}
+
+// The width of each column, given their natural (widest cell) widths. Numeric columns keep their
+// natural width, text columns share the remainder in proportion to how much they would need, but
+// not narrower than `min_width`. Everything is clamped to `max_width`.
+// `None` if not even that fits, in which case termimad gets to squeeze the table as it likes.
+fn column_widths(
+    natural: &[usize],
+    numeric: &[bool],
+    available: usize,
+    min_width: usize,
+    max_width: Option<usize>,
+) -> Option<Vec<usize>> {
+    let target: Vec<usize> = natural
+        .iter()
+        .map(|width| max_width.map_or(*width, |max| (*width).min(max)))
+        .collect();
+    if target.iter().sum::<usize>() <= available {
+        return Some(target);
+    }
+
+    let min_width = min_width.max(MIN_COLUMN_WIDTH);
+    let mut widths: Vec<usize> = target
+        .iter()
+        .zip(numeric)
+        .map(|(width, numeric)| {
+            if *numeric {
+                *width
+            } else {
+                (*width).min(min_width)
+            }
+        })
+        .collect();
+    let mut spare = available.checked_sub(widths.iter().sum())?;
+
+    while spare > 0 {
+        let lacking: Vec<(usize, usize)> = widths
+            .iter()
+            .zip(&target)
+            .enumerate()
+            .filter(|(_, (width, target))| width < target)
+            .map(|(i, (width, target))| (i, target - width))
+            .collect();
+        let total_lack: usize = lacking.iter().map(|(_, lack)| lack).sum();
+        if total_lack <= spare {
+            for (i, lack) in lacking {
+                widths[i] += lack;
+            }
+            break;
+        }
+        let mut given = 0;
+        for (i, lack) in &lacking {
+            let grow = lack * spare / total_lack;
+            widths[*i] += grow;
+            given += grow;
+        }
+        if given == 0 {
+            // Only rounding leftovers remain.
+            for (i, _) in lacking.iter().take(spare) {
+                widths[*i] += 1;
+            }
+            break;
+        }
+        spare -= given;
+    }
+    Some(widths)
+}
+
+// Not `MadLine::is_table_part`, which is also true for code and horizontal rules.
+fn is_table_line(line: &MadLine) -> bool {
+    matches!(line, MadLine::TableRow(_) | MadLine::TableRule(_))
+}
+
+fn cell_width(cell: &Composite) -> usize {
+    cell.compounds.iter().map(|c| c.as_str().width()).sum()
+}
+
+// Numbers, amounts, and percentages, like "-1,234.5", "$20" or "12%".
+fn is_number(text: &str) -> bool {
+    let text = text
+        .trim()
+        .trim_start_matches(['+', '-', '$', '€', '£'])
+        .trim_end_matches('%');
+    text.chars().any(|c| c.is_ascii_digit())
+        && text
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '_'))
+}
+
+// Word-wrap a cell into parts of at most `width`, cutting words that are longer than that.
+fn wrap_cell<'a>(cell: &Composite<'a>, width: usize) -> Vec<Composite<'a>> {
+    let new_part = || Composite {
+        style: cell.style,
+        compounds: Vec::new(),
+    };
+    let mut parts = vec![new_part()];
+    let mut used = 0;
+    for compound in &cell.compounds {
+        let mut start = 0;
+        for word in compound.as_str().split_inclusive(' ') {
+            let end = start + word.len();
+            let word_width = word.trim_end().width();
+            if used > 0 && used + word_width > width {
+                parts.push(new_part());
+                used = 0;
+            }
+            if word_width > width {
+                let mut cut = start;
+                for (i, c) in word.char_indices() {
+                    let char_width = c.width().unwrap_or_default();
+                    if used > 0 && used + char_width > width {
+                        if let Some(part) = parts.last_mut() {
+                            part.compounds.push(compound.sub(cut, start + i));
+                        }
+                        parts.push(new_part());
+                        cut = start + i;
+                        used = 0;
+                    }
+                    used += char_width;
+                }
+                if let Some(part) = parts.last_mut() {
+                    part.compounds.push(compound.sub(cut, end));
+                }
+            } else {
+                if let Some(part) = parts.last_mut() {
+                    part.compounds.push(compound.sub(start, end));
+                }
+                used += word.width();
+            }
+            start = end;
+        }
+    }
+    for part in &mut parts {
+        part.trim_spaces();
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::layout::Alignment;
+    use ratskin::RatSkin;
+
+    use super::{column_widths, fit_columns, is_number, table_lines};
+    use crate::markdown::ParseOptions;
+
+    #[test]
+    fn numeric_columns_keep_width() {
+        // A text column that would need 40, and a numeric one that needs 9.
+        let widths = column_widths(&[40, 9], &[false, true], 30, 8, None);
+        assert_eq!(widths, Some(vec![21, 9]));
+
+        // Two text columns share in proportion.
+        let widths = column_widths(&[40, 20, 6], &[false, false, true], 36, 8, None);
+        assert_eq!(widths, Some(vec![19, 11, 6]));
+
+        // Fits as it is, unless clamped.
+        let widths = column_widths(&[10, 5], &[false, true], 30, 8, None);
+        assert_eq!(widths, Some(vec![10, 5]));
+        let widths = column_widths(&[10, 5], &[false, true], 30, 8, Some(6));
+        assert_eq!(widths, Some(vec![6, 5]));
+
+        // The minimum doesn't fit.
+        let widths = column_widths(&[40, 20], &[false, true], 24, 8, None);
+        assert_eq!(widths, None);
+
+        assert!(is_number("-1,234.5"), "negative decimal");
+        assert!(is_number(" 12% "), "percentage");
+        assert!(is_number("$20"), "amount");
+        assert!(!is_number("v1.2"), "version");
+        assert!(!is_number("-"), "dash");
+    }
+
+    #[test]
+    fn fit_table_columns() {
+        let text = "\
+| Description | Population |
+|-------------|-----------:|
+| A rather long description of some place that goes on and on | 1,234,567 |
+| Short | 42 |
+";
+        let width = 40;
+        let options = ParseOptions::default();
+        let madtext = fit_columns(RatSkin::parse_text(text), width, &options);
+        let lines: Vec<String> = RatSkin::default()
+            .parse(madtext, width)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        for line in &lines {
+            assert!(
+                unicode_width::UnicodeWidthStr::width(line.trim_end()) <= usize::from(width),
+                "line too wide: {line}"
+            );
+        }
+        assert!(
+            lines.iter().any(|line| line.contains("Population")),
+            "header not wrapped: {lines:#?}"
+        );
+        assert!(
+            lines.iter().any(|line| line.contains("1,234,567")),
+            "number not wrapped: {lines:#?}"
+        );
+        assert!(
+            lines.len() >= 6,
+            "description should wrap over several rows: {lines:#?}"
+        );
+    }
+
+    #[test]
+    fn aligned_table_lines() {
+        let rows: Vec<Vec<String>> = [
+            ["Left", "Center", "Right"],
+            ["a", "b", "1"],
+            ["longer cell", "x", "1,234"],
+        ]
+        .iter()
+        .map(|row| row.iter().map(|cell| (*cell).to_owned()).collect())
+        .collect();
+        let alignments = [Alignment::Left, Alignment::Center, Alignment::Right];
+        let render = |width| {
+            table_lines(
+                &rows,
+                &alignments,
+                width,
+                &RatSkin::default(),
+                &ParseOptions::default(),
+            )
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            render(80),
+            vec![
+                "Left        │ Center │ Right",
+                "────────────┼────────┼──────",
+                "a           │   b    │     1",
+                "longer cell │   x    │ 1,234",
+            ]
+        );
+
+        // The text column is narrowed, the numeric one keeps its width.
+        assert_eq!(
+            render(26),
+            vec![
+                "Left      │ Center │ Right",
+                "──────────┼────────┼──────",
+                "a         │   b    │     1",
+                "longer c… │   x    │ 1,234",
+            ]
+        );
+
+        // Too narrow for the minimum column width, the lines are cut off.
+        assert_eq!(
+            render(20),
+            vec![
+                "Left        │ Cente…",
+                "────────────┼──────…",
+                "a           │   b  …",
+                "longer cell │   x  …",
+            ]
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::ops::Range;
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::Paragraph,
+};
+
+use crate::widget_sources::{WidgetSource, WidgetSourceData};
+
+// Width of the minimap column, see `Config::show_minimap`: a gap and the blocks.
+pub const WIDTH: u16 = 2;
+
+const COLOR_VIEWPORT: Color = Color::Indexed(237);
+
+// What a line of the document is, in the order of which one a squeezed row shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Blank,
+    Text,
+    Code,
+    Image,
+    Header,
+}
+
+impl Kind {
+    // Headers can be rendered as images or lines too, so they are told by the caller.
+    pub fn of(source: &WidgetSource, header: bool) -> Kind {
+        if header {
+            return Kind::Header;
+        }
+        match &source.data {
+            WidgetSourceData::Header(..) | WidgetSourceData::HeaderImage(..) => Kind::Header,
+            WidgetSourceData::Image(..)
+            | WidgetSourceData::AnimatedImage(..)
+            | WidgetSourceData::BrokenImage(..) => Kind::Image,
+            WidgetSourceData::Line(line, _) => {
+                // Code blocks have a background over all their text.
+                let mut spans = line
+                    .spans
+                    .iter()
+                    .filter(|span| !span.content.trim().is_empty())
+                    .peekable();
+                if spans.peek().is_none() {
+                    Kind::Blank
+                } else if spans.all(|span| span.style.bg.is_some()) {
+                    Kind::Code
+                } else {
+                    Kind::Text
+                }
+            }
+        }
+    }
+
+    fn color(self) -> Option<Color> {
+        match self {
+            Kind::Blank => None,
+            Kind::Text => Some(Color::Indexed(245)),
+            Kind::Code => Some(Color::Indexed(179)),
+            Kind::Image => Some(Color::Indexed(141)),
+            Kind::Header => Some(Color::Indexed(75)),
+        }
+    }
+}
+
+// The range of lines that each row stands for, squeezing `total` lines into `height` rows, or one
+// line per row if they fit.
+pub fn row_lines(row: usize, total: usize, height: usize) -> Range<usize> {
+    let rows = total.min(height).max(1);
+    row * total / rows..(row + 1) * total / rows
+}
+
+// The kind of each row, and whether it is in `view`, the range of visible lines.
+pub fn rows(lines: &[Kind], height: usize, view: &Range<usize>) -> Vec<(Kind, bool)> {
+    (0..lines.len().min(height))
+        .map(|row| {
+            let range = row_lines(row, lines.len(), height);
+            let kind = lines
+                .get(range.clone())
+                .and_then(|kinds| kinds.iter().max().copied())
+                .unwrap_or(Kind::Blank);
+            (kind, range.start < view.end && range.end > view.start)
+        })
+        .collect()
+}
+
+pub fn render(frame: &mut Frame, area: Rect, rows: &[(Kind, bool)]) {
+    let lines: Vec<Line> = rows
+        .iter()
+        .map(|(kind, in_view)| {
+            let style = if *in_view {
+                Style::new().bg(COLOR_VIEWPORT)
+            } else {
+                Style::new()
+            };
+            let block = match kind.color() {
+                Some(color) => Span::styled("█", style.fg(color)),
+                None => Span::styled(" ", style),
+            };
+            Line::from(vec![Span::styled(" ", style), block])
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{Kind, row_lines, rows};
+
+    #[test]
+    fn squeeze_rows() {
+        assert_eq!(row_lines(0, 5, 10), 0..1, "one line per row if they fit");
+        assert_eq!(row_lines(4, 5, 10), 4..5);
+        assert_eq!(row_lines(0, 100, 10), 0..10);
+        assert_eq!(row_lines(9, 100, 10), 90..100);
+
+        let lines = [
+            Kind::Header,
+            Kind::Blank,
+            Kind::Text,
+            Kind::Text,
+            Kind::Blank,
+            Kind::Image,
+        ];
+        assert_eq!(
+            rows(&lines, 3, &(0..2)),
+            [
+                (Kind::Header, true),
+                (Kind::Text, false),
+                (Kind::Image, false)
+            ]
+        );
+        assert_eq!(
+            rows(&lines, 10, &(3..6)).len(),
+            6,
+            "no rows below the document"
+        );
+    }
+}
@@ -1,32 +1,56 @@
 use std::{
     cmp::min,
+    collections::{HashMap, HashSet},
     fmt::Display,
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::mpsc::{Receiver, Sender},
+    time::{Duration, Instant},
 };
 
 use ratatui::{
     layout::{Rect, Size},
-    style::Stylize as _,
+    style::{Color, Style, Stylize as _},
     text::{Line, Span},
     widgets::Padding,
 };
-use regex::RegexBuilder;
+use ratatui_image::{picker::ProtocolType, protocol::Protocol};
+use ratskin::RatSkin;
+use regex::{Regex, RegexBuilder};
 
 use crate::setup::BgColor;
 use crate::{
-    Cmd,
-    config::{Config, PaddingConfig},
+    Cmd, clipboard,
+    config::{Config, EnterAction, HeaderWidth, PaddingConfig, SearchColors},
     error::Error,
-    widget_sources::{FindMode, FindTarget},
+    markdown::{ParseOptions, header_spans, links::is_image_url, tier_styles},
+    minimap,
+    outline::{self, OutlineEntry},
+    positions,
+    recent::{self, RecentMenu},
+    widget_sources::{FindMode, FindTarget, LineExtra, is_remote_url},
 };
 use crate::{Event, widget_sources::WidgetSources};
 use crate::{
-    cursor::Cursor,
-    widget_sources::{WidgetSource, WidgetSourceData},
+    cursor::{Cursor, CursorPointer, SearchState},
+    widget_sources::{SourceID, WidgetSource, WidgetSourceData},
 };
 
+// How long a message, like an error from the worker, is shown in the status line.
+const STATUS_DURATION: Duration = Duration::from_secs(5);
+// The status line message of a search regex that does not compile.
+const REGEX_ERROR: &str = "Regex error";
+// Images shown as their alt text, see `Model::text_only`.
+const COLOR_IMAGE_ALT: Color = Color::Indexed(244);
+
+// A message in the status line, see `Model::status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    // Confirms an action, like switching the protocol or copying a link.
+    Info(String),
+    Error(String),
+}
+
 pub struct Model<'a, 'b> {
     pub bg: Option<BgColor>,
     sources: WidgetSources<'a>,
@@ -34,13 +58,67 @@ pub struct Model<'a, 'b> {
     pub cursor: Cursor,
     pub log_snapshot: Option<flexi_logger::Snapshot>,
     original_file_path: Option<PathBuf>,
+    // The directory of relative links without a file, see `Config::basepath`.
+    pub basepath: Option<PathBuf>,
     screen_size: Size,
     config: Config,
     cmd_tx: Sender<Cmd>,
     event_rx: Receiver<Event<'b>>,
     document_id: DocumentId,
-    #[cfg(test)]
-    pub pending_image_count: usize,
+    status: Option<(Status, Instant)>,
+    outline: Vec<OutlineEntry>,
+    // The outline overlay is open, with the typed header number.
+    pub outline_input: Option<String>,
+    // The selected header of the table of contents overlay, if it is open.
+    pub toc: Option<usize>,
+    // Launched without a file, picking one of the recent files.
+    pub recent: Option<RecentMenu>,
+    // The debugging prompt to jump to a source id is open, with the typed id.
+    pub goto_source_input: Option<String>,
+    // Launched with `--log`, enables debugging commands.
+    pub debug: bool,
+    // Search needles that stay highlighted while searching for others, see `keep_search`.
+    highlights: Vec<String>,
+    // The `#anchor` of a link to another file, to jump to once that document is parsed.
+    pending_anchor: Option<(DocumentId, String)>,
+    // The files that links to other files were followed from, and their scroll positions.
+    back_stack: Vec<(PathBuf, u16)>,
+    // The scroll position to restore once the document is parsed, when going back.
+    pending_scroll: Option<(DocumentId, u16)>,
+    // Where the scroll positions of files are remembered, see `Config::remember_position`.
+    positions_file: Option<PathBuf>,
+    details: Details,
+    // Show link urls after the link texts, toggled with a key.
+    pub inline_link_urls: bool,
+    // The typed count before a key, like the `3` in `3gx`.
+    pub count_input: String,
+    // Whether `g` was pressed, waiting for `g` or `x` to follow.
+    pub g_pending: bool,
+    // The markdown text of the current document.
+    source: String,
+    show_source: bool,
+    // Show the highlighted markdown text in place of the rendered document, toggled with a key.
+    raw: bool,
+    // Dim everything but the current section, toggled with a key.
+    focus: bool,
+    // Inline image thumbnails by source id and column.
+    thumbnails: HashMap<(SourceID, u16), Protocol>,
+    // The styles of headers rendered as plain lines, by tier.
+    header_styles: Vec<Style>,
+    // The graphics protocol of the worker's picker, cycled with a key.
+    pub protocol_type: Option<ProtocolType>,
+    // Whether headers and images are deep-fried, toggled with a key.
+    pub deep_fry: bool,
+    // Images are shown as their alt text, without any graphics to show them.
+    pub text_only: bool,
+    // Started with `--no-cache`, which the config file cannot turn back on.
+    pub no_cache: bool,
+    // Headers, images and diagrams sent to the worker, and not rendered yet.
+    pending_renders: HashSet<SourceID>,
+    // Advanced by the run loop while `is_animating`.
+    pub animation_frame: usize,
+    // Animated images stay at their current frame, toggled with a key.
+    pub animations_paused: bool,
 }
 
 impl<'a, 'b: 'a> Model<'a, 'b> {
@@ -52,8 +130,14 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
         screen_size: Size,
         config: Config,
     ) -> Model<'a, 'b> {
+        let show_source = config.show_source;
+        let focus = config.focus_mode;
+        let header_styles = tier_styles(&RatSkin {
+            skin: config.theme.skin.clone(),
+        });
         Model {
             original_file_path,
+            basepath: None,
             bg,
             screen_size,
             config,
@@ -64,48 +148,355 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
             event_rx,
             log_snapshot: None,
             document_id: DocumentId::default(),
-            #[cfg(test)]
-            pending_image_count: 0,
+            status: None,
+            outline: Vec::new(),
+            outline_input: None,
+            toc: None,
+            recent: None,
+            goto_source_input: None,
+            debug: false,
+            pending_anchor: None,
+            back_stack: Vec::new(),
+            pending_scroll: None,
+            positions_file: positions::state_file(),
+            highlights: Vec::new(),
+            details: Details::default(),
+            inline_link_urls: false,
+            count_input: String::new(),
+            g_pending: false,
+            source: String::new(),
+            show_source,
+            raw: false,
+            focus,
+            thumbnails: HashMap::new(),
+            header_styles,
+            protocol_type: None,
+            deep_fry: false,
+            text_only: false,
+            no_cache: false,
+            pending_renders: HashSet::new(),
+            animation_frame: 0,
+            animations_paused: false,
         }
     }
 
+    // Editors that save without changes, or a `touch`, do not rebuild (and flicker) everything.
     pub fn reload(&mut self, screen_size: Size) -> Result<(), Error> {
+        self.reload_file(screen_size, false)
+    }
+
+    // Re-read the file even if unchanged, and fetch all images again instead of reusing them, for
+    // when an image failed to load or is stale.
+    pub fn hard_reload(&mut self, screen_size: Size) -> Result<(), Error> {
+        log::info!("hard reload");
+        self.cmd_tx.send(Cmd::ForgetDownloads)?;
+        self.sources.unload_images();
+        if self.original_file_path.is_some() {
+            self.reload_file(screen_size, true)
+        } else {
+            self.reparse(screen_size, self.source.clone())
+        }
+    }
+
+    fn reload_file(&mut self, screen_size: Size, force: bool) -> Result<(), Error> {
         if let Some(original_file_path) = &self.original_file_path {
             let text = fs::read_to_string(original_file_path)?;
+            if !force && text == self.source {
+                log::info!("reload: unchanged");
+                return Ok(());
+            }
             self.reparse(screen_size, text)?;
         }
         Ok(())
     }
 
-    pub fn open(&self, screen_size: Size, text: String) -> Result<(), Error> {
-        self.parse(self.document_id.open(), screen_size, text)
+    // Only a width change affects line wrapping, a height change just needs the scroll clamped.
+    pub fn resize(&mut self, screen_size: Size) -> Result<(), Error> {
+        let width_changed = self.screen_size.width != screen_size.width;
+        self.screen_size = screen_size;
+        if width_changed {
+            self.reload_file(screen_size, true)
+        } else {
+            self.scroll_by(0);
+            Ok(())
+        }
+    }
+
+    pub fn open(&mut self, screen_size: Size, text: String) -> Result<(), Error> {
+        let document_id = self.document_id.open();
+        // Going back, or following a link to a header, already knows where to scroll to.
+        if self.config.remember_position
+            && self.pending_scroll.is_none()
+            && self.pending_anchor.is_none()
+            && let Some(file) = &self.positions_file
+            && let Some(path) = &self.original_file_path
+            && let Some(scroll) = positions::load(file, path)
+        {
+            self.pending_scroll = Some((document_id, scroll));
+        }
+        self.parse(document_id, screen_size, text)
+    }
+
+    // Store the scroll position in the file, to start there when it is opened again.
+    pub fn remember_position(&self) {
+        if self.config.remember_position
+            && let Some(file) = &self.positions_file
+            && let Some(path) = &self.original_file_path
+        {
+            positions::remember(file, path, self.scroll);
+        }
+    }
+
+    // Open the file selected in the recent files menu.
+    pub fn open_recent(&mut self, screen_size: Size) -> Result<(), Error> {
+        let Some(path) = self.recent.as_ref().and_then(RecentMenu::selected).cloned() else {
+            return Ok(());
+        };
+        self.open_path(screen_size, path)
+    }
+
+    // The open file, `None` for stdin and `--text`.
+    pub fn file_path(&self) -> Option<&Path> {
+        self.original_file_path.as_deref()
+    }
+
+    // Open another file, showing an error if it cannot be read.
+    pub fn open_path(&mut self, screen_size: Size, path: PathBuf) -> Result<(), Error> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) => {
+                self.set_status(Status::Error(format!(
+                    "Failed to open {}: {err}",
+                    path.display()
+                )));
+                return Ok(());
+            }
+        };
+        self.recent = None;
+        self.remember_position();
+        if self.config.show_recent_on_launch {
+            recent::remember(&path, self.config.recent_files_max);
+        }
+        self.cmd_tx
+            .send(Cmd::SetBasepath(path.parent().map(Path::to_path_buf)))?;
+        self.original_file_path = Some(path);
+        self.open(screen_size, text)
+    }
+
+    // Switch to the next graphics protocol and reload all images with it, to compare how they
+    // render in the terminal.
+    pub fn cycle_protocol(&mut self, screen_size: Size) -> Result<(), Error> {
+        let Some(protocol_type) = self.protocol_type else {
+            return Ok(());
+        };
+        let protocol_type = match protocol_type {
+            ProtocolType::Kitty => ProtocolType::Iterm2,
+            ProtocolType::Iterm2 => ProtocolType::Sixel,
+            ProtocolType::Sixel => ProtocolType::Halfblocks,
+            ProtocolType::Halfblocks => ProtocolType::Kitty,
+        };
+        log::info!("protocol: {protocol_type:?}");
+        self.protocol_type = Some(protocol_type);
+        self.set_status(Status::Info(format!("Protocol: {protocol_type:?}")));
+        self.cmd_tx.send(Cmd::SetProtocol(protocol_type))?;
+        self.sources.unload_images();
+        self.reparse(screen_size, self.source.clone())
+    }
+
+    // Toggle deep-frying of headers and images, rendering them all again.
+    pub fn toggle_deep_fry(&mut self, screen_size: Size) -> Result<(), Error> {
+        self.deep_fry = !self.deep_fry;
+        log::info!("deep fry: {}", self.deep_fry);
+        let state = if self.deep_fry { "on" } else { "off" };
+        self.set_status(Status::Info(format!("Deep fry: {state}")));
+        self.cmd_tx.send(Cmd::SetDeepFry(self.deep_fry))?;
+        self.sources.unload_images();
+        self.reparse(screen_size, self.source.clone())
     }
 
-    pub fn reparse(&self, screen_size: Size, text: String) -> Result<(), Error> {
+    pub fn reparse(&mut self, screen_size: Size, text: String) -> Result<(), Error> {
         log::info!("reparse");
         self.parse(self.document_id.reload(), screen_size, text)
     }
 
     fn parse(
-        &self,
+        &mut self,
         next_document_id: DocumentId,
         screen_size: Size,
         text: String,
     ) -> Result<(), Error> {
         let inner_width = self.inner_width(screen_size.width);
+        self.source.clone_from(&text);
         self.cmd_tx
             .send(Cmd::Parse(next_document_id, inner_width, text))?;
         Ok(())
     }
 
     pub fn inner_width(&self, screen_width: u16) -> u16 {
-        self.config.padding.calculate_width(screen_width)
+        self.config.padding.calculate_width(
+            screen_width
+                - self.outline_panel_width(screen_width)
+                - self.source_pane_width(screen_width)
+                - self.minimap_width(screen_width),
+        )
+    }
+
+    // The width of header images, which may span the padding too, see `Config::header_width`.
+    pub fn header_width(&self, screen_width: u16) -> u16 {
+        match self.config.header_width {
+            HeaderWidth::Body => self.inner_width(screen_width),
+            HeaderWidth::Full => {
+                screen_width
+                    - self.outline_panel_width(screen_width)
+                    - self.source_pane_width(screen_width)
+                    - self.minimap_width(screen_width)
+            }
+        }
+    }
+
+    // Whether the source is (a line of) a header rendered as an image over the full width.
+    pub fn is_full_width_header(&self, id: SourceID) -> bool {
+        self.config.header_width == HeaderWidth::Full
+            && self.outline.iter().any(|entry| entry.id == id)
+    }
+
+    // Width taken by the raw markdown source pane, if shown.
+    pub fn source_pane_width(&self, screen_width: u16) -> u16 {
+        if self.show_source {
+            (screen_width - self.outline_panel_width(screen_width)) / 2
+        } else {
+            0
+        }
+    }
+
+    // Show or hide the source pane, which changes the width of the rendered markdown.
+    pub fn toggle_source(&mut self, screen_size: Size) -> Result<(), Error> {
+        self.show_source = !self.show_source;
+        let text = self.source.clone();
+        self.reparse(screen_size, text)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    // Switch between the rendered document and the raw markdown, keeping the scroll position
+    // proportionally, like the source pane.
+    pub fn toggle_raw(&mut self) {
+        let from_lines = self.total_lines().max(1);
+        self.raw = !self.raw;
+        log::info!("raw view: {}", self.raw);
+        // Links and search matches are positioned in the rendered document.
+        self.cursor = Cursor::None;
+        self.scroll = (usize::from(self.scroll) * usize::from(self.total_lines())
+            / usize::from(from_lines)) as u16;
+        self.scroll_by(0);
+    }
+
+    pub fn raw_view(&self) -> bool {
+        self.raw
+    }
+
+    // The source pane follows the rendered scroll position proportionally.
+    pub fn source_scroll(&self) -> u16 {
+        let source_lines = self.source.lines().count();
+        let total_lines = self.total_lines().max(1);
+        (usize::from(self.scroll) * source_lines / usize::from(total_lines)) as u16
+    }
+
+    // Width taken from the right side of the screen by the pinned outline, if enabled.
+    pub fn outline_panel_width(&self, screen_width: u16) -> u16 {
+        if self.config.show_outline {
+            outline::PANEL_WIDTH.min(screen_width / 3)
+        } else {
+            0
+        }
+    }
+
+    // Width taken by the minimap right of the content, if enabled.
+    pub fn minimap_width(&self, screen_width: u16) -> u16 {
+        if self.config.show_minimap {
+            minimap::WIDTH.min(screen_width / 3)
+        } else {
+            0
+        }
+    }
+
+    // Where the minimap is, right of the content and left of the source pane and outline.
+    pub fn minimap_area(&self, screen_size: Size) -> Option<Rect> {
+        let width = self.minimap_width(screen_size.width);
+        (width > 0).then(|| {
+            let x = screen_size.width
+                - self.outline_panel_width(screen_size.width)
+                - self.source_pane_width(screen_size.width)
+                - width;
+            Rect::new(x, 0, width, screen_size.height.saturating_sub(1))
+        })
+    }
+
+    // The kind of every line of the document, or of the markdown in the raw view.
+    fn minimap_lines(&self) -> Vec<minimap::Kind> {
+        if self.raw {
+            return self
+                .source
+                .lines()
+                .map(|line| match line.trim_start() {
+                    "" => minimap::Kind::Blank,
+                    line if line.starts_with('#') => minimap::Kind::Header,
+                    _ => minimap::Kind::Text,
+                })
+                .collect();
+        }
+        self.sources()
+            .flat_map(|source| {
+                let header = self.outline.iter().any(|entry| entry.id == source.id);
+                let kind = minimap::Kind::of(source, header);
+                std::iter::repeat_n(kind, usize::from(source.height))
+            })
+            .collect()
+    }
+
+    // The rows of a minimap of `height`, with the visible lines highlighted.
+    pub fn minimap_rows(&self, height: u16) -> Vec<(minimap::Kind, bool)> {
+        let (start_y, end_y) = self.visible_lines();
+        let view = start_y.max(0) as usize..(end_y + 1).max(0) as usize;
+        minimap::rows(&self.minimap_lines(), usize::from(height), &view)
+    }
+
+    // Scroll to the part of the document that `row` of a minimap of `height` stands for, centered.
+    pub fn minimap_jump(&mut self, row: u16, height: u16) {
+        let total = self.minimap_lines().len();
+        if usize::from(row) >= total.min(usize::from(height)) {
+            return;
+        }
+        let line = minimap::row_lines(usize::from(row), total, usize::from(height)).start;
+        let half = usize::from(self.inner_height(self.screen_size.height) / 2);
+        self.scroll = line.saturating_sub(half) as u16;
+        self.scroll_by(0);
     }
 
     pub fn inner_height(&self, screen_height: u16) -> u16 {
         self.config.padding.calculate_height(screen_height)
     }
 
+    pub fn thumbnail(&self, id: SourceID, column: u16) -> Option<&Protocol> {
+        self.thumbnails.get(&(id, column))
+    }
+
+    // The column of the guide in the content area, if any.
+    pub fn column_guide(&self) -> Option<u16> {
+        self.config.column_guide
+    }
+
+    pub fn search_colors(&self) -> SearchColors {
+        self.config.search_colors
+    }
+
+    // The background to fill the content area with, if any.
+    pub fn background(&self) -> Option<BgColor> {
+        self.bg.filter(|_| self.config.fill_background)
+    }
+
     pub fn block_padding(&self, area: Rect) -> Padding {
         match self.config.padding {
             PaddingConfig::None => Padding::default(),
@@ -118,12 +509,18 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
         }
     }
 
+    // Saturating, a long gallery of tall images could otherwise overflow.
     pub fn total_lines(&self) -> u16 {
-        self.sources.iter().map(|s| s.height).sum()
+        if self.raw {
+            return u16::try_from(self.source.lines().count()).unwrap_or(u16::MAX);
+        }
+        self.sources()
+            .fold(0, |total: u16, s| total.saturating_add(s.height))
     }
 
     pub fn process_events(&mut self, screen_width: u16) -> Result<(bool, bool), Error> {
         let inner_width = self.inner_width(screen_width);
+        let header_width = self.header_width(screen_width);
         let mut had_events = false;
         let mut had_done = false;
         while let Ok(event) = self.event_rx.try_recv() {
@@ -136,7 +533,19 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
             match event {
                 Event::NewDocument(document_id) => {
                     log::info!("NewDocument {document_id}");
+                    if !self.document_id.is_same_document(&document_id) {
+                        // Another file, nothing of the previous one is reused.
+                        self.details.expanded.clear();
+                        self.sources = WidgetSources::default();
+                        self.scroll = 0;
+                        self.cursor = Cursor::None;
+                    }
                     self.document_id = document_id;
+                    self.outline.clear();
+                    self.details.sections.clear();
+                    self.details.folds.clear();
+                    self.thumbnails.clear();
+                    self.pending_renders.clear();
                 }
                 Event::ParseDone(document_id, last_source_id) => {
                     if !self.document_id.is_same_document(&document_id) {
@@ -144,6 +553,19 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
                         continue;
                     }
                     self.sources.trim_last_source(last_source_id);
+                    if let Some((_, anchor)) = self
+                        .pending_anchor
+                        .take_if(|(pending_id, _)| *pending_id == document_id)
+                    {
+                        self.jump_to_anchor(&anchor);
+                    }
+                    if let Some((_, scroll)) = self
+                        .pending_scroll
+                        .take_if(|(pending_id, _)| *pending_id == document_id)
+                    {
+                        self.scroll = scroll;
+                        self.scroll_by(0);
+                    }
                     had_done = true;
                 }
                 Event::Parsed(document_id, source) => {
@@ -169,12 +591,8 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
                         log::debug!("stale event, ignoring");
                         continue;
                     }
-                    #[cfg(test)]
                     for source in &updates {
-                        if let WidgetSourceData::Image(_, _) = source.data {
-                            log::debug!("Update #{}: {:?}", source.id, source.data);
-                            self.pending_image_count -= 1;
-                        }
+                        self.pending_renders.remove(&source.id);
                     }
                     self.sources.update(updates);
                 }
@@ -184,41 +602,53 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
                         continue;
                     }
 
+                    if self.text_only {
+                        let alt = if text.is_empty() { &url } else { &text };
+                        let line = WidgetSource {
+                            id,
+                            height: 1,
+                            data: WidgetSourceData::Line(
+                                Line::from(format!("[image: {alt}]")).fg(COLOR_IMAGE_ALT),
+                                Vec::new(),
+                            ),
+                        };
+                        if self.document_id.is_first_load() {
+                            self.sources.push(line);
+                        } else {
+                            self.sources.update(vec![line]);
+                        }
+                        continue;
+                    }
+
                     if let Some(mut existing_image) = self.sources.replace(id, &url) {
                         log::debug!("replacing from existing image ({url})");
                         existing_image.id = id;
                         self.sources.update(vec![existing_image]);
                     } else {
+                        // Reserve some height, so that the content below jumps less on load.
+                        let placeholder = WidgetSource {
+                            id,
+                            height: self
+                                .config
+                                .image_placeholder_height
+                                .clamp(1, self.config.max_image_height.max(1)),
+                            data: WidgetSourceData::Line(
+                                Line::from(format!("![Loading...]({url})")),
+                                Vec::new(),
+                            ),
+                        };
                         if self.document_id.is_first_load() {
                             log::debug!(
                                 "existing image not found, push placeholder and process image ({url})"
                             );
-                            self.sources.push(WidgetSource {
-                                id,
-                                height: 1,
-                                data: WidgetSourceData::Line(
-                                    Line::from(format!("![Loading...]({url})")),
-                                    Vec::new(),
-                                ),
-                            });
+                            self.sources.push(placeholder);
                         } else {
                             log::debug!(
                                 "existing image not found, update placeholder and process image ({url})"
                             );
-                            self.sources.update(vec![WidgetSource {
-                                id,
-                                height: 1,
-                                data: WidgetSourceData::Line(
-                                    Line::from(format!("![Loading...]({url})")),
-                                    Vec::new(),
-                                ),
-                            }]);
-                        }
-                        #[cfg(test)]
-                        {
-                            log::debug!("UrlImage");
-                            self.pending_image_count += 1;
+                            self.sources.update(vec![placeholder]);
                         }
+                        self.pending_renders.insert(id);
                         self.cmd_tx.send(Cmd::UrlImage(
                             document_id,
                             id,
@@ -234,11 +664,24 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
                         log::debug!("stale event, ignoring");
                         continue;
                     }
-                    let line = Line::from(vec![
-                        #[expect(clippy::string_add)]
-                        Span::from("#".repeat(tier as usize) + " ").light_blue(),
-                        Span::from(text.clone()),
-                    ]);
+                    let style = self
+                        .header_styles
+                        .get(usize::from(tier).saturating_sub(1))
+                        .copied()
+                        .unwrap_or_default();
+                    #[expect(clippy::string_add)]
+                    let mut spans = vec![Span::styled("#".repeat(tier as usize) + " ", style)];
+                    spans.extend(header_spans(&text).into_iter().map(|span| {
+                        let mut styled = Span::styled(span.text, style);
+                        if self.config.header_emphasis && span.bold {
+                            styled = styled.bold();
+                        }
+                        if self.config.header_emphasis && span.italic {
+                            styled = styled.italic();
+                        }
+                        styled
+                    }));
+                    let line = Line::from(spans);
                     if self.document_id.is_first_load() {
                         self.sources.push(WidgetSource {
                             id,
@@ -252,23 +695,229 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
                             data: WidgetSourceData::Line(line, Vec::new()),
                         }]);
                     }
-                    #[cfg(test)]
-                    {
-                        log::debug!("ParseHeader");
-                        self.pending_image_count += 1;
-                    }
+                    self.pending_renders.insert(id);
                     self.cmd_tx
-                        .send(Cmd::Header(document_id, id, inner_width, tier, text))?;
+                        .send(Cmd::Header(document_id, id, header_width, tier, text))?;
+                }
+                Event::ParseDiagram(document_id, id, diagram, source) => {
+                    if !self.document_id.is_same_document(&document_id) {
+                        log::debug!("stale event, ignoring");
+                        continue;
+                    }
+                    let placeholder = WidgetSource {
+                        id,
+                        height: self
+                            .config
+                            .image_placeholder_height
+                            .clamp(1, self.config.max_image_height.max(1)),
+                        data: WidgetSourceData::Line(
+                            Line::from(format!("```{diagram} Rendering...")),
+                            Vec::new(),
+                        ),
+                    };
+                    if self.document_id.is_first_load() {
+                        self.sources.push(placeholder);
+                    } else {
+                        self.sources.update(vec![placeholder]);
+                    }
+                    self.pending_renders.insert(id);
+                    self.cmd_tx.send(Cmd::Diagram(
+                        document_id,
+                        id,
+                        inner_width,
+                        diagram,
+                        source,
+                    ))?;
+                }
+                Event::Thumbnail(document_id, id, column, data) => {
+                    if !self.document_id.is_same_document(&document_id) {
+                        log::debug!("stale event, ignoring");
+                        continue;
+                    }
+                    if let WidgetSourceData::Image(_, proto) = data {
+                        self.thumbnails.insert((id, column), proto);
+                    }
+                }
+                Event::Outline(document_id, id, tier, text) => {
+                    if !self.document_id.is_same_document(&document_id) {
+                        log::debug!("stale event, ignoring");
+                        continue;
+                    }
+                    self.outline.push(OutlineEntry { id, tier, text });
+                }
+                Event::Details(document_id, id, last_id) => {
+                    if !self.document_id.is_same_document(&document_id) {
+                        log::debug!("stale event, ignoring");
+                        continue;
+                    }
+                    self.details.sections.push((id, last_id));
+                }
+                Event::CodeFold(document_id, id, first_id) => {
+                    if !self.document_id.is_same_document(&document_id) {
+                        log::debug!("stale event, ignoring");
+                        continue;
+                    }
+                    self.details.folds.push((id, first_id));
                 }
                 Event::FileChanged => {
                     log::info!("reload: FileChanged");
                     self.reload(self.screen_size)?;
                 }
+                Event::OpenFile(path) => {
+                    log::info!("open: {}", path.display());
+                    self.open_path(self.screen_size, path)?;
+                }
+                Event::OpenImage(path) => {
+                    self.open_external(&path.display().to_string());
+                }
+                Event::Unrendered(document_id, id) => {
+                    if self.document_id.is_same_document(&document_id) {
+                        self.pending_renders.remove(&id);
+                    }
+                }
+                Event::Error(msg) => {
+                    self.show_error(msg);
+                }
             }
         }
         Ok((had_events, had_done))
     }
 
+    // Copy the markdown source, or the rendered text, of the whole document to the clipboard.
+    pub fn copy_document(&mut self, rendered: bool) {
+        let (text, what) = if rendered {
+            (self.rendered_text(), "text")
+        } else {
+            (self.source.clone(), "markdown")
+        };
+        self.copy_to_clipboard(&text, format!("Copied {} bytes of {what}", text.len()));
+    }
+
+    // Copy with the `clipboard_command`, or OSC 52 without one, and confirm it in the status line.
+    pub fn copy_to_clipboard(&mut self, text: &str, confirmation: String) {
+        let copied = match &self.config.clipboard_command {
+            Some(command) => clipboard::copy_with_command(command, text),
+            None => clipboard::copy(text),
+        };
+        match copied {
+            Ok(()) => self.set_status(Status::Info(confirmation)),
+            Err(err) => self.show_error(err.to_string()),
+        }
+    }
+
+    // The link or task of the link cursor.
+    pub fn cursor_extra(&self) -> Option<LineExtra> {
+        let Cursor::Links(CursorPointer { id, index }) = self.cursor else {
+            return None;
+        };
+        self.sources().find_map(|source| match &source.data {
+            WidgetSourceData::Line(_, extras) if source.id == id => extras.get(index).cloned(),
+            _ => None,
+        })
+    }
+
+    // The visible text of the document, without images.
+    pub fn rendered_text(&self) -> String {
+        self.sources()
+            .filter_map(|source| match &source.data {
+                WidgetSourceData::Line(line, _) => Some(line.to_string().trim_end().to_owned()),
+                WidgetSourceData::Header(text, _, _)
+                | WidgetSourceData::HeaderImage(text, _, _) => Some(text.clone()),
+                WidgetSourceData::Image(..)
+                | WidgetSourceData::AnimatedImage(..)
+                | WidgetSourceData::BrokenImage(..) => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn show_error(&mut self, msg: String) {
+        log::error!("{msg}");
+        self.set_status(Status::Error(msg));
+    }
+
+    fn set_status(&mut self, status: Status) {
+        self.status = Some((status, Instant::now()));
+    }
+
+    // The number of headers, images and diagrams that are still being rendered.
+    pub fn pending_renders(&self) -> usize {
+        self.pending_renders.len()
+    }
+
+    // Whether the rendering spinner or a playing animated image is on screen, which needs
+    // redrawing every frame. The spinner gives way to status messages.
+    pub fn is_animating(&self) -> bool {
+        (!self.pending_renders.is_empty() && self.status.is_none())
+            || (!self.animations_paused && self.visible_animations().next().is_some())
+    }
+
+    // The animated images on screen.
+    fn visible_animations(&self) -> impl Iterator<Item = SourceID> {
+        let (start_y, end_y) = self.visible_lines();
+        let mut y = 0;
+        self.sources().filter_map(move |source| {
+            let top = y;
+            y += source.height as i16;
+            (matches!(source.data, WidgetSourceData::AnimatedImage(..))
+                && y > start_y
+                && top <= end_y)
+                .then_some(source.id)
+        })
+    }
+
+    // Let `elapsed` pass for the animated images on screen, returns true if any shows another
+    // frame.
+    pub fn advance_animations(&mut self, elapsed: Duration) -> bool {
+        if self.animations_paused {
+            return false;
+        }
+        let visible: Vec<SourceID> = self.visible_animations().collect();
+        let mut advanced = false;
+        for source in self.sources.iter_mut() {
+            if visible.contains(&source.id)
+                && let WidgetSourceData::AnimatedImage(_, animation) = &mut source.data
+            {
+                advanced |= animation.advance(elapsed);
+            }
+        }
+        advanced
+    }
+
+    pub fn toggle_animations(&mut self) {
+        self.animations_paused = !self.animations_paused;
+        let state = if self.animations_paused {
+            "paused"
+        } else {
+            "playing"
+        };
+        self.set_status(Status::Info(format!("Animations: {state}")));
+    }
+
+    pub fn status(&self) -> Option<&Status> {
+        self.status.as_ref().map(|(status, _)| status)
+    }
+
+    pub fn error_message(&self) -> Option<&str> {
+        match self.status() {
+            Some(Status::Error(msg)) => Some(msg),
+            _ => None,
+        }
+    }
+
+    // Clear the status after it has been shown for a while, returns true if it was cleared.
+    pub fn expire_status(&mut self) -> bool {
+        if self
+            .status
+            .as_ref()
+            .is_some_and(|(_, since)| since.elapsed() > STATUS_DURATION)
+        {
+            self.status = None;
+            return true;
+        }
+        false
+    }
+
     pub fn scroll_by(&mut self, lines: i16) {
         self.scroll = min(
             self.scroll.saturating_add_signed(lines),
@@ -278,6 +927,53 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
         );
     }
 
+    // The last line just above the status line, or the top if everything fits.
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll = self
+            .total_lines()
+            .saturating_sub(self.inner_height(self.screen_size.height).saturating_sub(1));
+    }
+
+    // Scroll by a (half) page. With `snap_scroll`, land on the top edge of a source, so that
+    // headers and images are not left cut-off at the top.
+    pub fn scroll_page_by(&mut self, mut lines: i16) {
+        if self.config.snap_scroll && !self.raw {
+            let target = self.scroll.saturating_add_signed(lines);
+            let snapped = if lines > 0 {
+                self.source_tops()
+                    .take_while(|top| *top <= target)
+                    .last()
+                    .filter(|top| *top > self.scroll)
+            } else {
+                self.source_tops()
+                    .find(|top| *top >= target)
+                    .filter(|top| *top < self.scroll)
+            };
+            // If a source is taller than the page, there is nothing to snap to.
+            if let Some(top) = snapped {
+                lines = top as i16 - self.scroll as i16;
+            }
+        }
+        self.scroll_by(lines);
+    }
+
+    fn source_tops(&self) -> impl Iterator<Item = u16> {
+        self.sources().scan(0, |y, source| {
+            let top = *y;
+            *y += source.height;
+            Some(top)
+        })
+    }
+
+    // Whether there is more content above and below the screen, if enabled.
+    pub fn scroll_hints(&self) -> (bool, bool) {
+        if !self.config.show_scroll_hints {
+            return (false, false);
+        }
+        let (_, end_y) = self.visible_lines();
+        (self.scroll > 0, end_y + 1 < self.total_lines() as i16)
+    }
+
     pub fn visible_lines(&self) -> (i16, i16) {
         let start_y = self.scroll as i16;
         // We don't render the last line, so sub one extra:
@@ -285,17 +981,204 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
         (start_y, end_y)
     }
 
-    pub fn open_link(&self, url: String) -> Result<(), Error> {
-        std::process::Command::new("xdg-open").arg(&url).spawn()?;
+    // A link to a `#anchor` jumps to the header, and a local markdown file (optionally with an
+    // `#anchor`) is opened in place. Anything else is opened externally.
+    pub fn open_link(&mut self, url: String) -> Result<(), Error> {
+        // Like images on screen: the worker resolves the path, or writes the download. Remote images
+        // that are not in the document have not been downloaded, and are opened externally.
+        if is_image_url(&url) && (!is_remote_url(&url) || self.has_image(&url)) {
+            self.cmd_tx.send(Cmd::OpenImage(url))?;
+            return Ok(());
+        }
+        if !self.config.follow_local_links || url.contains("://") || url.starts_with("mailto:") {
+            self.open_external(&url);
+            return Ok(());
+        }
+        let (path, anchor) = match url.split_once('#') {
+            Some((path, anchor)) => (path, Some(anchor)),
+            None => (url.as_str(), None),
+        };
+        if path.is_empty() {
+            if let Some(anchor) = anchor {
+                self.jump_to_anchor(anchor);
+            }
+            return Ok(());
+        }
+        let is_markdown = Path::new(path).extension().is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("md") || extension.eq_ignore_ascii_case("markdown")
+        });
+        if !is_markdown {
+            self.open_external(&url);
+            return Ok(());
+        }
+        let dir = match &self.original_file_path {
+            Some(path) => path.parent(),
+            None => self.basepath.as_deref(),
+        };
+        let path = match dir {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        };
+        if !path.is_file() {
+            self.set_status(Status::Error(format!("No such file: {}", path.display())));
+            return Ok(());
+        }
+        // The header positions are only known once the file is parsed.
+        self.pending_anchor = anchor.map(|anchor| (self.document_id.open(), anchor.to_owned()));
+        if let Some(current) = self.original_file_path.clone() {
+            self.back_stack.push((current, self.scroll));
+        }
+        self.open_path(self.screen_size, path)
+    }
+
+    // Go back to the file that the last followed link was in, at the same scroll position.
+    pub fn go_back(&mut self, screen_size: Size) -> Result<(), Error> {
+        let Some((path, scroll)) = self.back_stack.pop() else {
+            self.set_status(Status::Error(String::from("No previous file")));
+            return Ok(());
+        };
+        self.pending_scroll = Some((self.document_id.open(), scroll));
+        self.open_path(screen_size, path)
+    }
+
+    // Reveal the config file's directory, when there is no editor to edit the file in.
+    pub fn open_config_dir(&mut self, path: &Path) {
+        if let Some(dir) = path.parent() {
+            self.open_external(&dir.display().to_string());
+        }
+    }
+
+    pub fn mouse_capture(&self) -> bool {
+        self.config.enable_mouse_capture
+    }
+
+    pub fn idle_poll(&self) -> Duration {
+        Duration::from_millis(self.config.idle_poll_milliseconds)
+    }
+
+    // Apply the edited config file, parsing the document again with it. The graphics and font
+    // setup, and the mouse capture, stay as they were started with.
+    pub fn apply_config(&mut self, screen_size: Size, mut config: Config) -> Result<(), Error> {
+        log::info!("apply config");
+        if self.text_only {
+            config.render_diagrams = false;
+            config.inline_image_thumbnails = false;
+        }
+        if self.no_cache {
+            config.image_cache = false;
+        }
+        self.header_styles = tier_styles(&RatSkin {
+            skin: config.theme.skin.clone(),
+        });
+        self.cmd_tx.send(Cmd::SetConfig(
+            Box::new(ParseOptions::from(&config)),
+            config.theme.skin.clone(),
+        ))?;
+        self.config = config;
+        self.set_status(Status::Info(String::from("Config applied")));
+        self.sources.unload_images();
+        self.reparse(screen_size, self.source.clone())
+    }
+
+    // Whether the image at `url` is shown in the document, so the worker has downloaded it.
+    fn has_image(&self, url: &str) -> bool {
+        self.sources().any(|source| {
+            matches!(
+                &source.data,
+                WidgetSourceData::Image(image_url, _) | WidgetSourceData::AnimatedImage(image_url, _)
+                    if image_url == url
+            )
+        })
+    }
+
+    // Open with the `open_command`, showing an error if it cannot be started.
+    fn open_external(&mut self, url: &str) {
+        let args = open_command_args(&self.config.open_command, url);
+        let Some((program, args)) = args.split_first() else {
+            return;
+        };
+        log::debug!("open {program} {args:?}");
+        if let Err(err) = std::process::Command::new(program).args(args).spawn() {
+            let err = Error::OpenCommand(format!("{program}: {err}"));
+            log::error!("{err}");
+            self.set_status(Status::Error(err.to_string()));
+        }
+    }
+
+    fn jump_to_anchor(&mut self, anchor: &str) {
+        match outline::anchor_index(&self.outline, anchor) {
+            Some(index) => self.jump_to_outline(index),
+            None => self.set_status(Status::Error(format!("Anchor not found: #{anchor}"))),
+        }
+    }
+
+    // Open the `nth` link from the top of the screen, or the nearest below, counting from 1.
+    pub fn open_link_from_top(&mut self, nth: usize) -> Result<(), Error> {
+        if let Some(url) = self.link_from_top(nth) {
+            log::debug!("open link_from_top {url}");
+            self.open_link(url)?;
+        }
+        Ok(())
+    }
+
+    fn link_from_top(&self, nth: usize) -> Option<String> {
+        let mut pointer = WidgetSources::find_first_cursor(
+            self.details.visible(&self.sources),
+            FindTarget::Link,
+            self.scroll,
+        )?;
+        for _ in 1..nth {
+            pointer = WidgetSources::find_next_cursor(
+                self.details.visible(&self.sources),
+                &pointer,
+                FindMode::Next,
+                FindTarget::Link,
+            )?;
+        }
+        match self.sources.find_extra_by_cursor(&pointer)? {
+            LineExtra::Link(url, _, _) => Some(url.clone()),
+            _ => None,
+        }
+    }
+
+    // Open the first image on screen (or the closest one above) in an external viewer.
+    pub fn open_image(&self) -> Result<(), Error> {
+        let mut y = 0;
+        let mut target = None;
+        for source in self.sources() {
+            if let WidgetSourceData::Image(url, _) | WidgetSourceData::AnimatedImage(url, _) =
+                &source.data
+            {
+                target = Some(url);
+                if y >= self.scroll {
+                    break;
+                }
+            }
+            y += source.height;
+        }
+        if let Some(url) = target {
+            log::debug!("open image {url}");
+            self.cmd_tx.send(Cmd::OpenImage(url.clone()))?;
+        }
         Ok(())
     }
 
+    // Tasks can only be toggled in a file, not in stdin or `--text`.
+    fn link_target(&self) -> FindTarget {
+        if self.original_file_path.is_some() {
+            FindTarget::LinkOrTask
+        } else {
+            FindTarget::Link
+        }
+    }
+
     pub fn cursor_next(&mut self) {
+        let target = self.link_target();
         match &mut self.cursor {
             Cursor::None => {
                 if let Some(pointer) = WidgetSources::find_first_cursor(
-                    self.sources.iter(),
-                    FindTarget::Link,
+                    self.details.visible(&self.sources),
+                    target,
                     self.scroll,
                 ) {
                     self.cursor = Cursor::Links(pointer);
@@ -303,10 +1186,10 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
             }
             Cursor::Links(current) => {
                 if let Some(pointer) = WidgetSources::find_next_cursor(
-                    self.sources.iter(),
+                    self.details.visible(&self.sources),
                     current,
                     FindMode::Next,
-                    FindTarget::Link,
+                    target,
                 ) {
                     self.cursor = Cursor::Links(pointer);
                 }
@@ -314,14 +1197,14 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
             Cursor::Search(_, pointer) => match pointer {
                 None => {
                     *pointer = WidgetSources::find_first_cursor(
-                        self.sources.iter(),
+                        self.details.visible(&self.sources),
                         FindTarget::Search,
                         self.scroll,
                     );
                 }
                 Some(current) => {
                     *pointer = WidgetSources::find_next_cursor(
-                        self.sources.iter(),
+                        self.details.visible(&self.sources),
                         current,
                         FindMode::Next,
                         FindTarget::Search,
@@ -333,11 +1216,12 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
     }
 
     pub fn cursor_prev(&mut self) {
+        let target = self.link_target();
         match &mut self.cursor {
             Cursor::None => {
                 if let Some(pointer) = WidgetSources::find_first_cursor(
-                    self.sources.iter(),
-                    FindTarget::Link,
+                    self.details.visible(&self.sources),
+                    target,
                     self.scroll,
                 ) {
                     self.cursor = Cursor::Links(pointer);
@@ -345,10 +1229,10 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
             }
             Cursor::Links(current) => {
                 if let Some(pointer) = WidgetSources::find_next_cursor(
-                    self.sources.iter(),
+                    self.details.visible(&self.sources),
                     current,
                     FindMode::Prev,
-                    FindTarget::Link,
+                    target,
                 ) {
                     self.cursor = Cursor::Links(pointer);
                 }
@@ -356,14 +1240,14 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
             Cursor::Search(_, pointer) => match pointer {
                 None => {
                     *pointer = WidgetSources::find_first_cursor(
-                        self.sources.iter(),
+                        self.details.visible(&self.sources),
                         FindTarget::Search,
                         self.scroll,
                     )
                 }
                 Some(current) => {
                     *pointer = WidgetSources::find_next_cursor(
-                        self.sources.iter(),
+                        self.details.visible(&self.sources),
                         current,
                         FindMode::Prev,
                         FindTarget::Search,
@@ -374,35 +1258,402 @@ impl<'a, 'b: 'a> Model<'a, 'b> {
         self.jump_to_pointer();
     }
 
+    // Highlight the needle, in the regex mode of the search cursor, and the kept highlights. A regex
+    // that does not compile keeps the previous matches, and shows the error.
     pub fn add_searches(&mut self, needle: Option<String>) {
-        let re = needle.and_then(|needle| {
-            RegexBuilder::new(&regex::escape(&needle))
-                .case_insensitive(true)
-                .build()
-                .inspect_err(|err| log::error!("{err}"))
-                .ok()
-        });
+        let regex_mode = matches!(
+            &self.cursor,
+            Cursor::Search(SearchState { regex: true, .. }, _)
+        );
+        let needle = match needle
+            .map(|needle| search_regex(&search_pattern(&needle, regex_mode)))
+            .transpose()
+        {
+            Ok(needle) => needle,
+            Err(err) => {
+                // The error comes with the pattern and a caret, over several lines.
+                let reason = err.to_string();
+                let reason = reason.lines().last().unwrap_or_default();
+                self.show_error(format!(
+                    "{REGEX_ERROR}: {}",
+                    reason.trim_start_matches("error: ")
+                ));
+                return;
+            }
+        };
+        if self
+            .error_message()
+            .is_some_and(|msg| msg.starts_with(REGEX_ERROR))
+        {
+            self.status = None;
+        }
+        let res: Vec<_> = std::iter::once(needle)
+            .chain(self.highlights.iter().map(|pattern| {
+                search_regex(pattern)
+                    .inspect_err(|err| log::error!("{err}"))
+                    .ok()
+            }))
+            .collect();
         for source in self.sources.iter_mut() {
-            source.add_search(&re);
+            source.add_search(&res);
         }
     }
 
-    fn jump_to_pointer(&mut self) {
-        if let Some(pointer) = self.cursor.pointer() {
-            let id = pointer.id;
-            let pointer_y = self.sources.get_y(id);
-            let (from, to) = self.visible_lines();
-            if pointer_y > to {
-                self.scroll_by(pointer_y - to);
-            } else if pointer_y < from {
-                self.scroll_by(pointer_y - from);
-            }
-        }
+    // The position of the search cursor among the matches, and the number of matches. Before
+    // jumping to one, the match that the first jump goes to.
+    pub fn search_count(&self) -> Option<(usize, usize)> {
+        let Cursor::Search(_, pointer) = &self.cursor else {
+            return None;
+        };
+        let current = pointer.clone().or_else(|| {
+            WidgetSources::find_first_cursor(
+                self.details.visible(&self.sources),
+                FindTarget::Search,
+                self.scroll,
+            )
+        });
+        Some(WidgetSources::search_position(
+            self.details.visible(&self.sources),
+            current.as_ref(),
+        ))
     }
 
-    pub fn sources(&self) -> impl Iterator<Item = &WidgetSource<'a>> {
-        self.sources.iter()
-    }
+    // Whether there are matches of the accepted search above and below the screen, if there are
+    // none on it.
+    pub fn offscreen_matches(&self) -> Option<(bool, bool)> {
+        let Cursor::Search(mode, _) = &self.cursor else {
+            return None;
+        };
+        if !mode.accepted {
+            return None;
+        }
+        let ids = WidgetSources::search_match_ids(self.details.visible(&self.sources));
+        let (start_y, end_y) = self.visible_lines();
+        let (mut above, mut below) = (false, false);
+        let mut y = 0;
+        for source in self.sources() {
+            let top = y;
+            y += source.height as i16;
+            if !ids.contains(&source.id) {
+                continue;
+            }
+            if y <= start_y {
+                above = true;
+            } else if top > end_y {
+                below = true;
+            } else {
+                return None;
+            }
+        }
+        (above || below).then_some((above, below))
+    }
+
+    // Switch the search cursor between literal and regex needles.
+    pub fn toggle_search_regex(&mut self) {
+        if let Cursor::Search(mode, _) = &mut self.cursor {
+            mode.regex = !mode.regex;
+            let needle = mode.needle.clone();
+            self.add_searches(Some(needle));
+        }
+    }
+
+    // Keep highlighting the accepted search needle in its own color, and start another search.
+    pub fn keep_search(&mut self) {
+        if let Cursor::Search(
+            SearchState {
+                needle,
+                accepted: true,
+                regex,
+            },
+            _,
+        ) = &self.cursor
+            && !needle.is_empty()
+        {
+            let pattern = search_pattern(needle, *regex);
+            if !self.highlights.contains(&pattern) {
+                self.highlights.push(pattern);
+            }
+        }
+        self.cursor = Cursor::Search(SearchState::default(), None);
+        self.add_searches(None);
+    }
+
+    pub fn clear_highlights(&mut self) {
+        self.highlights.clear();
+        let needle = match &self.cursor {
+            Cursor::Search(SearchState { needle, .. }, _) => Some(needle.clone()),
+            _ => None,
+        };
+        self.add_searches(needle);
+    }
+
+    pub fn highlights(&self) -> &[String] {
+        &self.highlights
+    }
+
+    fn jump_to_pointer(&mut self) {
+        if let Some(pointer) = self.cursor.pointer() {
+            let id = pointer.id;
+            let pointer_y = self.get_y(id);
+            let (from, to) = self.visible_lines();
+            if pointer_y > to {
+                self.scroll_by(pointer_y - to);
+            } else if pointer_y < from {
+                self.scroll_by(pointer_y - from);
+            }
+        }
+    }
+
+    pub fn outline(&self) -> &[OutlineEntry] {
+        &self.outline
+    }
+
+    // The index of the header of the section at the top of the screen.
+    pub fn current_outline_index(&self) -> Option<usize> {
+        self.outline
+            .iter()
+            .rposition(|entry| self.get_y(entry.id) <= self.scroll as i16)
+    }
+
+    pub fn toggle_focus(&mut self) {
+        self.focus = !self.focus;
+    }
+
+    // The lines of the current section, from its header to the next header, if focusing.
+    pub fn focus_range(&self) -> Option<(i16, i16)> {
+        if !self.focus {
+            return None;
+        }
+        let current = self.current_outline_index();
+        let from = current.map_or(0, |index| self.get_y(self.outline[index].id));
+        let to = self
+            .outline
+            .get(current.map_or(0, |index| index + 1))
+            .map_or(i16::MAX, |entry| self.get_y(entry.id));
+        Some((from, to))
+    }
+
+    pub fn jump_to_outline(&mut self, index: usize) {
+        if let Some(entry) = self.outline.get(index) {
+            // Expand any collapsed `<details>` that the header is in.
+            let id = entry.id;
+            let sections = self.details.sections.clone();
+            for (summary, last) in sections {
+                if (summary + 1..=last).contains(&id) {
+                    self.details.expanded.insert(summary);
+                }
+            }
+            let y = self.get_y(id);
+            self.scroll = 0;
+            self.scroll_by(y);
+        }
+    }
+
+    // Type a digit of the header number in the outline overlay. Jumps right away and closes the
+    // overlay, if no further digit could make a valid number.
+    pub fn outline_input_digit(&mut self, digit: char) {
+        let Some(input) = &mut self.outline_input else {
+            return;
+        };
+        input.push(digit);
+        if let Ok(number) = input.parse::<usize>()
+            && number * 10 > self.outline.len()
+        {
+            self.outline_input_accept();
+        }
+    }
+
+    pub fn outline_input_accept(&mut self) {
+        if let Some(number) = self
+            .outline_input
+            .take()
+            .and_then(|input| input.parse::<usize>().ok())
+            && number > 0
+        {
+            self.jump_to_outline(number - 1);
+        }
+    }
+
+    // Open the table of contents overlay, with the header of the current section selected.
+    pub fn open_toc(&mut self) {
+        if self.outline.is_empty() {
+            self.set_status(Status::Error(String::from("No headers")));
+            return;
+        }
+        self.toc = Some(self.current_outline_index().unwrap_or(0));
+    }
+
+    pub fn toc_next(&mut self) {
+        if let Some(selected) = &mut self.toc
+            && *selected + 1 < self.outline.len()
+        {
+            *selected += 1;
+        }
+    }
+
+    pub fn toc_prev(&mut self) {
+        if let Some(selected) = &mut self.toc {
+            *selected = selected.saturating_sub(1);
+        }
+    }
+
+    pub fn toc_accept(&mut self) {
+        if let Some(selected) = self.toc.take() {
+            self.jump_to_outline(selected);
+        }
+    }
+
+    // Debugging aid: scroll to the source with the typed id, and log its data.
+    pub fn goto_source_accept(&mut self) {
+        let Some(id) = self
+            .goto_source_input
+            .take()
+            .and_then(|input| input.parse::<SourceID>().ok())
+        else {
+            return;
+        };
+        match self.sources.iter().find(|source| source.id == id) {
+            Some(source) => {
+                log::info!("source #{id} ({} rows): {:?}", source.height, source.data);
+                let y = self.get_y(id);
+                self.scroll = 0;
+                self.scroll_by(y);
+            }
+            None => self.set_status(Status::Error(format!("No source #{id}"))),
+        }
+    }
+
+    // The sources that are not hidden in a collapsed `<details>`.
+    pub fn sources(&self) -> impl Iterator<Item = &WidgetSource<'a>> {
+        self.details.visible(&self.sources)
+    }
+
+    fn get_y(&self, id: SourceID) -> i16 {
+        let hidden: i16 = self
+            .sources
+            .iter()
+            .take_while(|source| source.id != id)
+            .filter(|source| self.details.is_hidden(source.id))
+            .map(|source| source.height as i16)
+            .sum();
+        self.sources.get_y(id) - hidden
+    }
+
+    pub fn details_expanded(&self, id: SourceID) -> bool {
+        self.details.expanded.contains(&id)
+    }
+
+    pub fn is_code_fold(&self, id: SourceID) -> bool {
+        self.details.folds.iter().any(|(marker, _)| *marker == id)
+    }
+
+    // Check or uncheck a task in the file, which is then parsed again. Only the marker at `offset` is
+    // replaced, keeping everything else of the file as is.
+    pub fn toggle_task(
+        &mut self,
+        screen_size: Size,
+        checked: bool,
+        offset: usize,
+    ) -> Result<(), Error> {
+        let Some(path) = self.original_file_path.clone() else {
+            return Ok(());
+        };
+        let mut text = fs::read_to_string(&path)?;
+        let (from, to) = if checked {
+            ("[x]", "[ ]")
+        } else {
+            ("[ ]", "[x]")
+        };
+        let range = offset..offset + from.len();
+        if !text
+            .get(range.clone())
+            .is_some_and(|marker| marker.eq_ignore_ascii_case(from))
+        {
+            self.set_status(Status::Error(String::from(
+                "The task changed in the file, press r to reload",
+            )));
+            return Ok(());
+        }
+        log::info!("toggle task at {offset} in {}", path.display());
+        text.replace_range(range, to);
+        fs::write(&path, &text)?;
+        self.reparse(screen_size, text)
+    }
+
+    // `Enter` without a link or search cursor, see `EnterAction`.
+    pub fn enter(&mut self) -> Result<(), Error> {
+        match self.config.enter_action {
+            EnterAction::Details => self.toggle_details(),
+            EnterAction::Scroll => self.scroll_by(1),
+            EnterAction::Link => self.open_link_from_top(1)?,
+            EnterAction::None => {}
+        }
+        Ok(())
+    }
+
+    // Expand or collapse the first `<details>` summary or code fold marker on screen.
+    pub fn toggle_details(&mut self) {
+        let (from, to) = self.visible_lines();
+        let mut y = 0;
+        let mut target = None;
+        for source in self.sources() {
+            if y > to {
+                break;
+            }
+            if y >= from
+                && (self.details.sections.iter().any(|(id, _)| *id == source.id)
+                    || self.is_code_fold(source.id))
+            {
+                target = Some(source.id);
+                break;
+            }
+            y += source.height as i16;
+        }
+        if let Some(id) = target
+            && !self.details.expanded.remove(&id)
+        {
+            self.details.expanded.insert(id);
+        }
+        self.scroll_by(0);
+    }
+
+    // Collapse or expand all `<details>` sections and folded code blocks at once.
+    pub fn set_all_details(&mut self, expanded: bool) {
+        if expanded {
+            let summaries = self.details.sections.iter().map(|(summary, _)| *summary);
+            let markers = self.details.folds.iter().map(|(marker, _)| *marker);
+            self.details.expanded = summaries.chain(markers).collect();
+        } else {
+            self.details.expanded.clear();
+        }
+        self.scroll_by(0);
+    }
+}
+
+// Collapsible `<details>` sections, as the summary id and the last id of the content.
+// Folded code blocks, as the marker id after the hidden lines, and the first id of them.
+#[derive(Default)]
+struct Details {
+    sections: Vec<(SourceID, SourceID)>,
+    folds: Vec<(SourceID, SourceID)>,
+    expanded: HashSet<SourceID>,
+}
+
+impl Details {
+    fn is_hidden(&self, id: SourceID) -> bool {
+        self.sections.iter().any(|(summary, last)| {
+            !self.expanded.contains(summary) && (summary + 1..=*last).contains(&id)
+        }) || self.folds.iter().any(|(marker, first)| {
+            !self.expanded.contains(marker) && (*first..*marker).contains(&id)
+        })
+    }
+
+    fn visible<'s, 'a>(
+        &'s self,
+        sources: &'s WidgetSources<'a>,
+    ) -> impl DoubleEndedIterator<Item = &'s WidgetSource<'a>> {
+        sources.iter().filter(|source| !self.is_hidden(source.id))
+    }
 }
 
 #[derive(Default, Debug, PartialEq, Clone, Copy)]
@@ -441,30 +1692,87 @@ impl Display for DocumentId {
     }
 }
 
+// Searches are case insensitive, and literal unless in regex mode.
+fn search_pattern(needle: &str, regex: bool) -> String {
+    if regex {
+        needle.to_owned()
+    } else {
+        regex::escape(needle)
+    }
+}
+
+fn search_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+// The program and arguments of the `open_command`, split at whitespace, with `{url}` replaced.
+fn open_command_args(command: &str, url: &str) -> Vec<String> {
+    command
+        .split_whitespace()
+        .map(|arg| arg.replace("{url}", url))
+        .collect()
+}
+
 #[cfg(test)]
 #[expect(clippy::unwrap_used)]
 mod tests {
 
-    use std::sync::mpsc;
+    use std::{
+        collections::{HashMap, HashSet},
+        fs,
+        path::PathBuf,
+        sync::mpsc::{self, Receiver},
+        time::{Duration, Instant},
+    };
 
-    use ratatui::text::Line;
+    use image::DynamicImage;
+    use ratatui::{layout::Rect, text::Line};
+    use ratatui_image::{
+        Resize,
+        picker::{Picker, ProtocolType},
+    };
+    use ratskin::RatSkin;
 
     use crate::{
         Cmd, DocumentId, Event,
-        config::UserConfig,
+        config::{Config, EnterAction, HeaderWidth, PaddingConfig, UserConfig},
         cursor::{Cursor, CursorPointer, SearchState},
-        model::Model,
-        widget_sources::{LineExtra, WidgetSource, WidgetSourceData, WidgetSources},
+        markdown::tier_styles,
+        minimap,
+        model::Details,
+        model::{Model, Status, open_command_args},
+        outline::OutlineEntry,
+        positions,
+        widget_sources::{
+            LineExtra, SourceID, WidgetSource, WidgetSourceData, WidgetSources, header_sources,
+        },
     };
 
     fn test_model<'a, 'b>() -> Model<'a, 'b> {
-        let (cmd_tx, _) = mpsc::channel::<Cmd>();
+        test_model_with_config(UserConfig::default().into())
+    }
+
+    fn test_model_with_config<'a, 'b>(config: Config) -> Model<'a, 'b> {
         let (_, event_rx) = mpsc::channel::<Event>();
+        test_model_with_events(config, event_rx)
+    }
+
+    fn test_model_with_events<'a, 'b>(
+        config: Config,
+        event_rx: Receiver<Event<'b>>,
+    ) -> Model<'a, 'b> {
+        let (cmd_tx, _) = mpsc::channel::<Cmd>();
+        let show_source = config.show_source;
+        let focus = config.focus_mode;
+        let header_styles = tier_styles(&RatSkin {
+            skin: config.theme.skin.clone(),
+        });
         Model {
             original_file_path: None,
+            basepath: None,
             bg: None,
             screen_size: (80, 20).into(),
-            config: UserConfig::default().into(),
+            config,
             scroll: 0,
             cursor: Cursor::default(),
             sources: WidgetSources::default(),
@@ -472,10 +1780,59 @@ mod tests {
             event_rx,
             log_snapshot: None,
             document_id: DocumentId::default(),
-            pending_image_count: 0,
+            status: None,
+            outline: Vec::new(),
+            outline_input: None,
+            toc: None,
+            recent: None,
+            goto_source_input: None,
+            debug: false,
+            pending_anchor: None,
+            back_stack: Vec::new(),
+            pending_scroll: None,
+            positions_file: None,
+            highlights: Vec::new(),
+            details: Details::default(),
+            inline_link_urls: false,
+            count_input: String::new(),
+            g_pending: false,
+            source: String::new(),
+            show_source,
+            raw: false,
+            focus,
+            thumbnails: HashMap::new(),
+            header_styles,
+            protocol_type: None,
+            deep_fry: false,
+            text_only: false,
+            no_cache: false,
+            pending_renders: HashSet::new(),
+            animation_frame: 0,
+            animations_paused: false,
+        }
+    }
+
+    // An image of two rows, see `widget_sources::tests::image_source`.
+    fn image_source<'a>(id: SourceID, url: &str) -> WidgetSource<'a> {
+        let proto = Picker::halfblocks()
+            .new_protocol(
+                DynamicImage::new_rgba8(8, 8),
+                Rect::new(0, 0, 2, 2),
+                Resize::Fit(None),
+            )
+            .unwrap();
+        WidgetSource {
+            id,
+            height: 2,
+            data: WidgetSourceData::Image(url.to_owned(), proto),
         }
     }
 
+    // A path in the temp dir that is unique to this test run.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mdfried_{name}_{}", std::process::id()))
+    }
+
     #[track_caller]
     fn assert_cursor_link(model: &Model, expected_url: &str) {
         let LineExtra::Link(url, ..) = model
@@ -552,6 +1909,42 @@ mod tests {
         assert_cursor_link(&model, "http://3.com");
     }
 
+    #[test]
+    fn link_from_top() {
+        let mut model = test_model();
+        for i in 1..5 {
+            let link = format!("http://{}.com", i);
+            model.sources.push(WidgetSource {
+                id: i,
+                height: 1,
+                data: WidgetSourceData::Line(
+                    Line::from(format!("text {link}")),
+                    vec![LineExtra::Link(link, 5, 16)],
+                ),
+            });
+        }
+        model.sources.push(WidgetSource {
+            id: 5,
+            height: 1,
+            data: WidgetSourceData::Line(Line::from("text"), Vec::new()),
+        });
+
+        assert_eq!(model.link_from_top(1), Some(String::from("http://1.com")));
+        assert_eq!(model.link_from_top(3), Some(String::from("http://3.com")));
+
+        model.scroll = 2;
+        assert_eq!(model.link_from_top(1), Some(String::from("http://3.com")));
+        assert_eq!(model.link_from_top(2), Some(String::from("http://4.com")));
+
+        model.scroll = 4;
+        assert_eq!(
+            model.link_from_top(1),
+            Some(String::from("http://1.com")),
+            "no link below, first link"
+        );
+        assert_eq!(model.cursor, Cursor::None, "not in links mode");
+    }
+
     #[test]
     fn finds_link_with_scroll_wrapping() {
         let mut model = test_model();
@@ -642,6 +2035,73 @@ mod tests {
         assert_cursor_link(&model, "http://b.com");
     }
 
+    #[test]
+    fn text_only_images() {
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut model = test_model_with_events(Config::from(UserConfig::default()), event_rx);
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        model.text_only = true;
+
+        event_tx
+            .send(Event::ParseImage(
+                DocumentId::default(),
+                0,
+                String::from("./image.png"),
+                String::from("a photo"),
+                String::new(),
+            ))
+            .unwrap();
+        model.process_events(80).unwrap();
+
+        assert!(
+            matches!(cmd_rx.try_recv(), Err(mpsc::TryRecvError::Empty)),
+            "the image was loaded"
+        );
+        let lines: Vec<String> = model.sources().map(ToString::to_string).collect();
+        assert_eq!(lines, vec![String::from("[image: a photo]")]);
+    }
+
+    #[test]
+    fn images_only_document() {
+        let mut model = test_model();
+        let image = |id, height| WidgetSource {
+            height,
+            ..image_source(id, &format!("./image{id}.png"))
+        };
+        for id in 0..5 {
+            model.sources.push(image(id, 30));
+        }
+
+        model.scroll_to_bottom();
+        assert_eq!(model.scroll, 131);
+        model.scroll = 0;
+        model.scroll_by(i16::MAX);
+        assert_eq!(model.scroll, 131, "scroll_by clamps to the same bottom");
+
+        model.cursor_next();
+        assert_eq!(model.cursor, Cursor::None, "no links");
+        model.cursor = Cursor::Search(
+            SearchState {
+                needle: String::from("image"),
+                accepted: true,
+                regex: false,
+            },
+            None,
+        );
+        model.add_searches(Some(String::from("image")));
+        model.cursor_next();
+        model.cursor_prev();
+        assert_eq!(model.cursor.pointer(), None, "no matches");
+        assert_eq!(model.scroll, 131);
+
+        model.sources.push(image(5, 40000));
+        model.sources.push(image(6, 40000));
+        assert_eq!(model.total_lines(), u16::MAX);
+        model.scroll_to_bottom();
+        model.cursor_next();
+    }
+
     #[test]
     fn jump_to_pointer() {
         let mut model = test_model();
@@ -691,6 +2151,1279 @@ mod tests {
         assert_eq!(model.scroll, 0);
     }
 
+    // Five lines, an image of 15 rows, and more lines.
+    fn push_image_document(model: &mut Model) {
+        for i in 0..5 {
+            model.sources.push(WidgetSource {
+                id: i,
+                height: 1,
+                data: WidgetSourceData::Line(Line::from(format!("line {}", i + 1)), Vec::new()),
+            });
+        }
+        model.sources.push(WidgetSource {
+            height: 15,
+            ..image_source(5, "image.png")
+        });
+        for i in 6..40 {
+            model.sources.push(WidgetSource {
+                id: i,
+                height: 1,
+                data: WidgetSourceData::Line(Line::from(format!("line {}", i + 1)), Vec::new()),
+            });
+        }
+    }
+
+    #[test]
+    fn resize_height_only_does_not_reparse() {
+        let path = temp_path("resize").with_extension("md");
+        fs::write(&path, "# header\n\ntext").unwrap();
+        let mut model = test_model();
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        model.original_file_path = Some(path.clone());
+
+        model.resize((80, 40).into()).unwrap();
+        assert!(
+            matches!(cmd_rx.try_recv(), Err(mpsc::TryRecvError::Empty)),
+            "height-only resize enqueued a command"
+        );
+
+        model.resize((60, 40).into()).unwrap();
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(_, 60, _))));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reload_unchanged_does_not_reparse() {
+        let path = temp_path("reload").with_extension("md");
+        fs::write(&path, "# header\n\ntext").unwrap();
+        let mut model = test_model();
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        model.original_file_path = Some(path.clone());
+
+        model.reload((80, 20).into()).unwrap();
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(..))));
+
+        model.reload((80, 20).into()).unwrap();
+        assert!(
+            matches!(cmd_rx.try_recv(), Err(mpsc::TryRecvError::Empty)),
+            "identical content enqueued a command"
+        );
+
+        fs::write(&path, "# header\n\nchanged").unwrap();
+        model.reload((80, 20).into()).unwrap();
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(..))));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn toggle_task() {
+        let mut model = test_model();
+        model.sources.push(WidgetSource {
+            id: 0,
+            height: 1,
+            data: WidgetSourceData::Line(Line::from("☐ task"), vec![LineExtra::Task(false, 6)]),
+        });
+        model.cursor_next();
+        assert_eq!(model.cursor, Cursor::None, "not without a file");
+
+        let path = temp_path("task").with_extension("md");
+        fs::write(&path, "text\n\t- [ ] task  \n").unwrap();
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        model.original_file_path = Some(path.clone());
+        model.cursor_next();
+        assert_eq!(
+            model.cursor,
+            Cursor::Links(CursorPointer { id: 0, index: 0 })
+        );
+
+        model.toggle_task((80, 20).into(), false, 6).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "text\n\t- [x] task  \n");
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(..))));
+
+        model.toggle_task((80, 20).into(), false, 6).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "text\n\t- [x] task  \n",
+            "already checked in the file"
+        );
+        assert!(model.error_message().is_some());
+
+        model.toggle_task((80, 20).into(), true, 6).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "text\n\t- [ ] task  \n");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rendered_text() {
+        let mut model = test_model();
+        model.sources.push(WidgetSource {
+            id: 0,
+            height: 2,
+            data: WidgetSourceData::Header(String::from("Title"), 1, Vec::new()),
+        });
+        model.sources.push(image_source(1, "./image.png"));
+        model.sources.push(WidgetSource {
+            id: 2,
+            height: 1,
+            data: WidgetSourceData::Line(Line::from("some text   "), Vec::new()),
+        });
+        assert_eq!(model.rendered_text(), "Title\nsome text");
+    }
+
+    #[test]
+    fn hard_reload() {
+        let path = temp_path("hard").with_extension("md");
+        fs::write(&path, "![image](./image.png)").unwrap();
+        let mut model = test_model();
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        model.original_file_path = Some(path.clone());
+        model.reload((80, 20).into()).unwrap();
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(..))));
+        model.sources.push(image_source(0, "./image.png"));
+
+        model.hard_reload((80, 20).into()).unwrap();
+        assert!(
+            matches!(cmd_rx.try_recv(), Ok(Cmd::ForgetDownloads)),
+            "images are downloaded again"
+        );
+        assert!(
+            matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(..))),
+            "unchanged content was not reparsed"
+        );
+        assert!(
+            model.sources.replace(0, "./image.png").is_none(),
+            "the image would be reused"
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn toggle_deep_fry() {
+        let mut model = test_model();
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+
+        model.toggle_deep_fry((80, 20).into()).unwrap();
+        assert!(model.deep_fry, "not toggled on");
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::SetDeepFry(true))));
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(..))));
+
+        model.toggle_deep_fry((80, 20).into()).unwrap();
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::SetDeepFry(false))));
+    }
+
+    #[test]
+    fn cycle_protocol() {
+        let mut model = test_model();
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        model.sources.push(image_source(0, "./image.png"));
+
+        model.cycle_protocol((80, 20).into()).unwrap();
+        assert!(
+            matches!(cmd_rx.try_recv(), Err(mpsc::TryRecvError::Empty)),
+            "cycled without a protocol"
+        );
+
+        model.protocol_type = Some(ProtocolType::Sixel);
+        model.cycle_protocol((80, 20).into()).unwrap();
+        assert_eq!(model.protocol_type, Some(ProtocolType::Halfblocks));
+        assert!(matches!(
+            cmd_rx.try_recv(),
+            Ok(Cmd::SetProtocol(ProtocolType::Halfblocks))
+        ));
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(..))));
+        assert!(
+            model.sources.replace(0, "./image.png").is_none(),
+            "the image would be reused"
+        );
+
+        model.cycle_protocol((80, 20).into()).unwrap();
+        assert_eq!(model.protocol_type, Some(ProtocolType::Kitty));
+    }
+
+    #[test]
+    fn toggle_source_pane() {
+        let mut model = test_model();
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        model.open((80, 20).into(), String::from("text")).unwrap();
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(_, 80, _))));
+
+        model.toggle_source((80, 20).into()).unwrap();
+        assert!(
+            matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(_, 40, text)) if text == "text"),
+            "reparsed at half width"
+        );
+    }
+
+    #[test]
+    fn toggle_raw_view() {
+        let mut model = test_model();
+        push_image_document(&mut model);
+        model.source = "line\n".repeat(27);
+        assert_eq!(model.total_lines(), 54);
+        model.scroll = 20;
+
+        model.toggle_raw();
+        assert!(model.raw_view());
+        assert_eq!(model.total_lines(), 27, "one line per source line");
+        assert_eq!(model.scroll, 10, "at the same place in the text");
+        model.scroll_to_bottom();
+        assert_eq!(model.scroll, 8);
+
+        model.toggle_raw();
+        assert!(!model.raw_view());
+        assert_eq!(model.total_lines(), 54);
+        assert_eq!(model.scroll, 16);
+    }
+
+    #[test]
+    fn open_command() {
+        assert_eq!(
+            open_command_args(" firefox  --new-tab {url} ", "http://a b"),
+            ["firefox", "--new-tab", "http://a b"]
+        );
+
+        let mut model = test_model_with_config(
+            UserConfig {
+                open_command: Some(String::from("mdfried-no-such-command {url}")),
+                ..Default::default()
+            }
+            .into(),
+        );
+        model.open_link(String::from("http://a")).unwrap();
+        assert!(
+            model
+                .error_message()
+                .is_some_and(|msg| msg.contains("mdfried-no-such-command")),
+            "failing to start is shown"
+        );
+    }
+
+    #[test]
+    fn copy_link() {
+        let path = temp_path("copy").with_extension("txt");
+        let mut model = test_model_with_config(
+            UserConfig {
+                clipboard_command: Some(format!("tee {}", path.display())),
+                ..Default::default()
+            }
+            .into(),
+        );
+        model.sources.push(WidgetSource {
+            id: 0,
+            height: 1,
+            data: WidgetSourceData::Line(
+                Line::from("a link"),
+                vec![LineExtra::Link(String::from("http://a.com"), 2, 6)],
+            ),
+        });
+        assert_eq!(model.cursor_extra(), None, "no link cursor");
+
+        model.cursor_next();
+        let Some(LineExtra::Link(url, _, _)) = model.cursor_extra() else {
+            panic!("no link under the cursor");
+        };
+        model.copy_to_clipboard(&url, format!("Copied {url}"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "http://a.com");
+        assert_eq!(
+            model.status(),
+            Some(&Status::Info(String::from("Copied http://a.com")))
+        );
+        fs::remove_file(&path).unwrap();
+
+        model.config.clipboard_command = Some(String::from("mdfried-no-such-command"));
+        model.copy_to_clipboard("text", String::from("Copied"));
+        assert!(
+            model
+                .error_message()
+                .is_some_and(|msg| msg.contains("mdfried-no-such-command")),
+            "failing to copy is shown"
+        );
+    }
+
+    #[test]
+    fn apply_config() {
+        let mut model = test_model();
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        model.text_only = true;
+        model.open((80, 20).into(), String::from("text")).unwrap();
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(..))));
+
+        model
+            .apply_config(
+                (80, 20).into(),
+                UserConfig {
+                    render_html: Some(false),
+                    ..Default::default()
+                }
+                .into(),
+            )
+            .unwrap();
+        assert!(matches!(
+            cmd_rx.try_recv(),
+            Ok(Cmd::SetConfig(options, _))
+                if !options.render_html && !options.render_diagrams
+        ));
+        assert!(
+            matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(_, 80, text)) if text == "text"),
+            "reparsed with the new config"
+        );
+    }
+
+    #[test]
+    fn open_image_link() {
+        let mut model = test_model();
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+
+        model.open_link(String::from("shots/a.png")).unwrap();
+        assert!(
+            matches!(cmd_rx.try_recv(), Ok(Cmd::OpenImage(url)) if url == "shots/a.png"),
+            "opened as an image, not as a document"
+        );
+
+        model.config.open_command = String::from("true {url}");
+        model.open_link(String::from("https://x/a.png")).unwrap();
+        assert!(
+            matches!(cmd_rx.try_recv(), Err(mpsc::TryRecvError::Empty)),
+            "remote images that are not in the document are opened externally"
+        );
+        model.sources.push(image_source(0, "https://x/a.png"));
+        model.open_link(String::from("https://x/a.png")).unwrap();
+        assert!(
+            matches!(cmd_rx.try_recv(), Ok(Cmd::OpenImage(url)) if url == "https://x/a.png"),
+            "remote images in the document have been downloaded"
+        );
+    }
+
+    #[test]
+    fn open_image_on_screen() {
+        let mut model = test_model();
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        push_image_document(&mut model);
+
+        model.open_image().unwrap();
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::OpenImage(url)) if url == "image.png"));
+
+        model.scroll = 30;
+        model.open_image().unwrap();
+        assert!(
+            matches!(cmd_rx.try_recv(), Ok(Cmd::OpenImage(url)) if url == "image.png"),
+            "falls back to the image above"
+        );
+    }
+
+    #[test]
+    fn page_scroll_without_snap() {
+        let mut model = test_model();
+        push_image_document(&mut model);
+
+        model.scroll_page_by(18);
+        assert_eq!(model.scroll, 18, "image is cut off at the top");
+    }
+
+    #[test]
+    fn page_scroll_snaps_to_image() {
+        let mut model = test_model_with_config(
+            UserConfig {
+                snap_scroll: Some(true),
+                ..Default::default()
+            }
+            .into(),
+        );
+        push_image_document(&mut model);
+
+        model.scroll_page_by(18);
+        assert_eq!(model.scroll, 5, "top of image");
+
+        model.scroll_page_by(18);
+        assert_eq!(model.scroll, 23, "below image, on a line");
+
+        model.scroll_page_by(-18);
+        assert_eq!(model.scroll, 5, "back to top of image");
+
+        model.scroll_page_by(-18);
+        assert_eq!(model.scroll, 0);
+    }
+
+    #[test]
+    fn page_scroll_snap_taller_than_page() {
+        let mut model = test_model_with_config(
+            UserConfig {
+                snap_scroll: Some(true),
+                ..Default::default()
+            }
+            .into(),
+        );
+        push_image_document(&mut model);
+        model.scroll = 5;
+
+        model.scroll_page_by(9);
+        assert_eq!(model.scroll, 14, "cannot snap inside image");
+    }
+
+    #[test]
+    fn minimap() {
+        let mut model = test_model();
+        push_outline_document(&mut model);
+        assert_eq!(model.minimap_area((80, 20).into()), None, "disabled");
+
+        model.config.show_minimap = true;
+        let area = model.minimap_area((80, 20).into()).expect("minimap area");
+        assert_eq!(area, Rect::new(78, 0, 2, 19));
+        let rows = model.minimap_rows(area.height);
+        assert_eq!(rows.len(), 19);
+        assert_eq!(rows.first(), Some(&(minimap::Kind::Header, true)));
+        assert_eq!(rows.last().map(|(_, in_view)| *in_view), Some(false));
+
+        model.minimap_jump(18, area.height);
+        let rows = model.minimap_rows(area.height);
+        assert!(model.scroll > 0, "scrolled down");
+        assert_eq!(rows.first().map(|(_, in_view)| *in_view), Some(false));
+        assert_eq!(rows.last().map(|(_, in_view)| *in_view), Some(true));
+    }
+
+    #[test]
+    fn scroll_hints() {
+        let mut model = test_model();
+        push_outline_document(&mut model);
+        assert_eq!(model.scroll_hints(), (false, false), "disabled");
+
+        model.config.show_scroll_hints = true;
+        assert_eq!(model.scroll_hints(), (false, true), "at the top");
+
+        model.scroll = 10;
+        assert_eq!(model.scroll_hints(), (true, true));
+
+        model.scroll_by(i16::MAX);
+        assert_eq!(model.scroll_hints(), (true, false), "at the bottom");
+    }
+
+    #[test]
+    fn shows_worker_error() {
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut model = test_model_with_events(UserConfig::default().into(), event_rx);
+
+        event_tx
+            .send(Event::Error(String::from("failed to decode image")))
+            .unwrap();
+        model.process_events(80).unwrap();
+        assert_eq!(model.error_message(), Some("failed to decode image"));
+
+        assert!(!model.expire_status(), "not expired yet");
+        model.status = Some((
+            Status::Error(String::from("failed to decode image")),
+            Instant::now() - Duration::from_secs(60),
+        ));
+        assert!(model.expire_status(), "expired");
+        assert_eq!(model.error_message(), None);
+    }
+
+    #[test]
+    fn pending_renders() {
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut model = test_model_with_events(UserConfig::default().into(), event_rx);
+        let (cmd_tx, _cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        let document_id = DocumentId::default();
+
+        for id in 0..3 {
+            event_tx
+                .send(Event::ParseHeader(
+                    document_id,
+                    id,
+                    1,
+                    format!("Header {id}"),
+                ))
+                .unwrap();
+        }
+        event_tx
+            .send(Event::ParseImage(
+                document_id,
+                3,
+                String::from("./image.png"),
+                String::new(),
+                String::new(),
+            ))
+            .unwrap();
+        model.process_events(80).unwrap();
+        assert_eq!(model.pending_renders(), 4);
+        assert!(model.is_animating());
+        model.show_error(String::from("oops"));
+        assert!(!model.is_animating(), "the spinner is covered");
+        model.status = None;
+
+        event_tx
+            .send(Event::Update(
+                document_id,
+                vec![WidgetSource::image_unknown(
+                    3,
+                    String::from("./image.png"),
+                    String::new(),
+                )],
+            ))
+            .unwrap();
+        event_tx.send(Event::Unrendered(document_id, 0)).unwrap();
+        model.process_events(80).unwrap();
+        assert_eq!(model.pending_renders(), 2, "a broken image is done too");
+
+        event_tx
+            .send(Event::NewDocument(document_id.open()))
+            .unwrap();
+        model.process_events(80).unwrap();
+        assert_eq!(model.pending_renders(), 0, "cleared for another document");
+        assert!(!model.is_animating());
+    }
+
+    #[test]
+    fn collapsed_details() {
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut model = test_model_with_events(UserConfig::default().into(), event_rx);
+        for (id, height) in [(0, 1), (1, 5), (2, 1)] {
+            model.sources.push(WidgetSource {
+                id,
+                height,
+                data: WidgetSourceData::Line(Line::from(format!("line {id}")), Vec::new()),
+            });
+        }
+        event_tx
+            .send(Event::Details(DocumentId::default(), 0, 1))
+            .unwrap();
+        model.process_events(80).unwrap();
+
+        assert_eq!(model.total_lines(), 2, "content is hidden");
+        assert_eq!(
+            model.sources().map(|source| source.id).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+
+        model.toggle_details();
+        assert!(model.details_expanded(0));
+        assert_eq!(model.total_lines(), 7);
+
+        model.toggle_details();
+        assert!(!model.details_expanded(0));
+        assert_eq!(model.total_lines(), 2);
+    }
+
+    #[test]
+    fn enter_action() {
+        let mut model = test_model_with_config(
+            UserConfig {
+                enter_action: Some(EnterAction::Scroll),
+                ..Default::default()
+            }
+            .into(),
+        );
+        push_image_document(&mut model);
+        model.enter().unwrap();
+        model.enter().unwrap();
+        assert_eq!(model.scroll, 2);
+
+        let mut model = test_model_with_config(
+            UserConfig {
+                enter_action: Some(EnterAction::None),
+                ..Default::default()
+            }
+            .into(),
+        );
+        push_image_document(&mut model);
+        model.enter().unwrap();
+        assert_eq!(model.scroll, 0);
+    }
+
+    #[test]
+    fn folded_code_block() {
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut model = test_model_with_events(UserConfig::default().into(), event_rx);
+        for id in 0..5 {
+            model.sources.push(WidgetSource {
+                id,
+                height: 1,
+                data: WidgetSourceData::Line(Line::from(format!("line {id}")), Vec::new()),
+            });
+        }
+        event_tx
+            .send(Event::CodeFold(DocumentId::default(), 3, 1))
+            .unwrap();
+        model.process_events(80).unwrap();
+
+        assert!(model.is_code_fold(3));
+        assert_eq!(
+            model.sources().map(|source| source.id).collect::<Vec<_>>(),
+            vec![0, 3, 4],
+            "folded lines are hidden"
+        );
+        assert_eq!(model.total_lines(), 3);
+
+        model.toggle_details();
+        assert!(model.details_expanded(3));
+        assert_eq!(model.total_lines(), 5);
+    }
+
+    #[test]
+    fn all_details() {
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut model = test_model_with_events(UserConfig::default().into(), event_rx);
+        for id in 0..8 {
+            model.sources.push(WidgetSource {
+                id,
+                height: 1,
+                data: WidgetSourceData::Line(Line::from(format!("line {id}")), Vec::new()),
+            });
+        }
+        event_tx
+            .send(Event::Details(DocumentId::default(), 0, 2))
+            .unwrap();
+        event_tx
+            .send(Event::CodeFold(DocumentId::default(), 6, 4))
+            .unwrap();
+        model.process_events(80).unwrap();
+        assert_eq!(model.total_lines(), 4);
+
+        model.set_all_details(true);
+        assert!(model.details_expanded(0));
+        assert!(model.details_expanded(6));
+        assert_eq!(model.total_lines(), 8);
+
+        model.toggle_details();
+        model.set_all_details(true);
+        assert_eq!(model.total_lines(), 8, "also the one collapsed again");
+
+        model.set_all_details(false);
+        assert_eq!(model.total_lines(), 4);
+    }
+
+    #[test]
+    fn image_placeholder_height() {
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut model = test_model_with_events(
+            UserConfig {
+                image_placeholder_height: Some(8),
+                ..Default::default()
+            }
+            .into(),
+            event_rx,
+        );
+        let (cmd_tx, _cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+
+        event_tx
+            .send(Event::ParseImage(
+                DocumentId::default(),
+                0,
+                String::from("image.png"),
+                String::new(),
+                String::new(),
+            ))
+            .unwrap();
+        model.process_events(80).unwrap();
+        assert_eq!(model.sources[0].height, 8);
+    }
+
+    fn push_outline_document(model: &mut Model) {
+        let mut id = 0;
+        for header in 0..12 {
+            model.outline.push(OutlineEntry {
+                id,
+                tier: 1,
+                text: format!("header {}", header + 1),
+            });
+            model.sources.push(WidgetSource {
+                id,
+                height: 2,
+                data: WidgetSourceData::Header(format!("header {}", header + 1), 1, Vec::new()),
+            });
+            id += 1;
+            for _ in 0..5 {
+                model.sources.push(WidgetSource {
+                    id,
+                    height: 1,
+                    data: WidgetSourceData::Line(Line::from("text"), Vec::new()),
+                });
+                id += 1;
+            }
+        }
+        // Enough to be able to scroll the last header to the top.
+        for _ in 0..20 {
+            model.sources.push(WidgetSource {
+                id,
+                height: 1,
+                data: WidgetSourceData::Line(Line::from("text"), Vec::new()),
+            });
+            id += 1;
+        }
+    }
+
+    #[test]
+    fn current_outline_index() {
+        let mut model = test_model();
+        assert_eq!(model.current_outline_index(), None);
+
+        push_outline_document(&mut model);
+        assert_eq!(model.current_outline_index(), Some(0));
+
+        model.scroll = 6;
+        assert_eq!(
+            model.current_outline_index(),
+            Some(0),
+            "last line of section"
+        );
+
+        model.scroll = 7;
+        assert_eq!(model.current_outline_index(), Some(1), "next header at top");
+    }
+
+    #[test]
+    fn focus_range() {
+        let mut model = test_model();
+        push_outline_document(&mut model);
+        assert_eq!(model.focus_range(), None, "not focusing");
+
+        model.toggle_focus();
+        assert_eq!(model.focus_range(), Some((0, 7)));
+
+        model.scroll = 9;
+        assert_eq!(model.focus_range(), Some((7, 14)), "second section");
+
+        model.scroll = model.get_y(model.outline[11].id) as u16;
+        assert_eq!(
+            model.focus_range(),
+            Some((77, i16::MAX)),
+            "last section until the end"
+        );
+    }
+
+    #[test]
+    fn outline_quick_jump() {
+        let mut model = test_model();
+        push_outline_document(&mut model);
+
+        model.outline_input = Some(String::new());
+        model.outline_input_digit('3');
+        assert_eq!(model.scroll, 14, "jumped to third header");
+        assert_eq!(model.outline_input, None, "closed overlay");
+
+        model.outline_input = Some(String::new());
+        model.outline_input_digit('1');
+        assert_eq!(
+            model.outline_input,
+            Some(String::from("1")),
+            "could be 1, 10, 11, 12"
+        );
+        model.outline_input_digit('1');
+        assert_eq!(model.outline_input, None, "closed overlay");
+        assert_eq!(model.current_outline_index(), Some(10));
+
+        model.outline_input = Some(String::new());
+        model.outline_input_digit('1');
+        model.outline_input_accept();
+        assert_eq!(model.scroll, 0, "jumped to first header");
+    }
+
+    #[test]
+    fn toc() {
+        let mut model = test_model();
+        model.open_toc();
+        assert_eq!(model.toc, None, "no headers");
+        assert_eq!(model.error_message(), Some("No headers"));
+
+        push_outline_document(&mut model);
+        model.scroll = 7;
+        let search = || {
+            Cursor::Search(
+                SearchState {
+                    needle: String::from("text"),
+                    accepted: true,
+                    regex: false,
+                },
+                None,
+            )
+        };
+        model.cursor = search();
+        model.open_toc();
+        assert_eq!(model.toc, Some(1), "current section selected");
+
+        model.toc_prev();
+        model.toc_prev();
+        assert_eq!(model.toc, Some(0));
+        for _ in 0..20 {
+            model.toc_next();
+        }
+        assert_eq!(
+            model.toc,
+            Some(model.outline().len() - 1),
+            "stops at the last"
+        );
+
+        model.toc_prev();
+        model.toc_accept();
+        assert_eq!(model.toc, None, "closed overlay");
+        assert_eq!(
+            model.current_outline_index(),
+            Some(model.outline().len() - 2),
+            "jumped to the header"
+        );
+        assert_eq!(model.cursor, search(), "kept the cursor");
+    }
+
+    #[test]
+    fn full_width_headers() {
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut model = test_model_with_events(
+            UserConfig {
+                padding: Some(PaddingConfig::Centered(60)),
+                header_width: Some(HeaderWidth::Full),
+                ..Default::default()
+            }
+            .into(),
+            event_rx,
+        );
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        assert_eq!(model.inner_width(100), 60);
+        assert_eq!(model.header_width(100), 100);
+
+        let document_id = DocumentId::default();
+        event_tx
+            .send(Event::Outline(document_id, 0, 1, String::from("Title")))
+            .unwrap();
+        event_tx
+            .send(Event::ParseHeader(document_id, 0, 1, String::from("Title")))
+            .unwrap();
+        model.process_events(100).unwrap();
+        assert!(
+            matches!(cmd_rx.try_recv(), Ok(Cmd::Header(_, 0, 100, 1, _))),
+            "rendered over the padding"
+        );
+        assert!(model.is_full_width_header(0));
+        assert!(!model.is_full_width_header(1), "not a header");
+
+        model.config.header_width = HeaderWidth::Body;
+        assert_eq!(model.header_width(100), 60);
+        assert!(!model.is_full_width_header(0));
+    }
+
+    #[test]
+    fn link_to_anchor() {
+        let mut model = test_model();
+        push_outline_document(&mut model);
+
+        model.open_link(String::from("#header-3")).unwrap();
+        assert_eq!(model.scroll, 14, "jumped to third header");
+
+        model.open_link(String::from("#nothing")).unwrap();
+        assert_eq!(model.scroll, 14, "did not move");
+        assert_eq!(model.error_message(), Some("Anchor not found: #nothing"));
+    }
+
+    #[test]
+    fn link_to_anchor_in_other_file() {
+        let dir = temp_path("links");
+        fs::create_dir_all(&dir).unwrap();
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut model = test_model_with_events(UserConfig::default().into(), event_rx);
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        model.original_file_path = Some(dir.join("a.md"));
+
+        model.open_link(String::from("b.md#part-two")).unwrap();
+        assert!(
+            matches!(cmd_rx.try_recv(), Err(mpsc::TryRecvError::Empty)),
+            "opened a missing file"
+        );
+        assert!(
+            model
+                .error_message()
+                .is_some_and(|msg| msg.starts_with("No such file")),
+        );
+
+        fs::write(dir.join("b.md"), "# Part one\n\n# Part two").unwrap();
+        model.open_link(String::from("b.md#part-two")).unwrap();
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::SetBasepath(_))));
+        assert!(matches!(cmd_rx.try_recv(), Ok(Cmd::Parse(..))));
+
+        // What the worker would send for the new document.
+        let document_id = DocumentId::default().open();
+        event_tx.send(Event::NewDocument(document_id)).unwrap();
+        for (id, text) in [(0, "Part one"), (1, "Part two")] {
+            event_tx
+                .send(Event::Outline(document_id, id, 1, String::from(text)))
+                .unwrap();
+            event_tx
+                .send(Event::Parsed(
+                    document_id,
+                    WidgetSource {
+                        id,
+                        height: 30,
+                        data: WidgetSourceData::Line(Line::from(text), Vec::new()),
+                    },
+                ))
+                .unwrap();
+        }
+        event_tx
+            .send(Event::ParseDone(document_id, Some(1)))
+            .unwrap();
+        model.process_events(80).unwrap();
+        assert_eq!(model.scroll, 30, "jumped to the second header");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn open_remembered_position() {
+        let dir = temp_path("remembered");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.md");
+        fs::write(&path, "# A").unwrap();
+        let state = dir.join("positions.toml");
+        positions::remember(&state, &path, 12);
+        let mut model = test_model();
+        let (cmd_tx, _cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        model.positions_file = Some(state);
+        model.original_file_path = Some(path);
+
+        model.open((80, 20).into(), String::from("# A")).unwrap();
+        assert_eq!(model.pending_scroll.map(|(_, scroll)| scroll), Some(12));
+
+        model.pending_scroll = Some((model.document_id.open(), 3));
+        model.open((80, 20).into(), String::from("# A")).unwrap();
+        assert_eq!(
+            model.pending_scroll.map(|(_, scroll)| scroll),
+            Some(3),
+            "going back keeps its scroll position"
+        );
+
+        model.pending_scroll = None;
+        model.pending_anchor = Some((model.document_id.open(), String::from("a")));
+        model.open((80, 20).into(), String::from("# A")).unwrap();
+        assert_eq!(
+            model.pending_scroll.map(|(_, scroll)| scroll),
+            None,
+            "a link to a header scrolls to it instead"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn go_back_to_linking_file() {
+        let dir = temp_path("back");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.md"), "[next](b.md)").unwrap();
+        fs::write(dir.join("b.md"), "# Next").unwrap();
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut model = test_model_with_events(UserConfig::default().into(), event_rx);
+        let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>();
+        model.cmd_tx = cmd_tx;
+        model.original_file_path = Some(dir.join("a.md"));
+
+        model.go_back((80, 20).into()).unwrap();
+        assert_eq!(model.error_message(), Some("No previous file"));
+
+        model.scroll = 7;
+        model.open_link(String::from("b.md")).unwrap();
+        assert_eq!(model.original_file_path, Some(dir.join("b.md")));
+        event_tx
+            .send(Event::NewDocument(DocumentId::default().open()))
+            .unwrap();
+        model.process_events(80).unwrap();
+        assert_eq!(model.scroll, 0);
+
+        model.go_back((80, 20).into()).unwrap();
+        assert_eq!(model.original_file_path, Some(dir.join("a.md")));
+        assert_eq!(model.source(), "[next](b.md)");
+        let parsed: Vec<Cmd> = cmd_rx.try_iter().collect();
+        assert!(
+            matches!(parsed.last(), Some(Cmd::Parse(..))),
+            "parsed the previous file again"
+        );
+
+        // What the worker would send for the previous document.
+        let document_id = DocumentId::default().open().open();
+        event_tx.send(Event::NewDocument(document_id)).unwrap();
+        event_tx
+            .send(Event::Parsed(
+                document_id,
+                WidgetSource {
+                    id: 0,
+                    height: 50,
+                    data: WidgetSourceData::Line(Line::from("next"), Vec::new()),
+                },
+            ))
+            .unwrap();
+        event_tx
+            .send(Event::ParseDone(document_id, Some(0)))
+            .unwrap();
+        model.process_events(80).unwrap();
+        assert_eq!(model.scroll, 7, "restored the scroll position");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keep_search_highlights() {
+        let mut model = test_model();
+        for (id, text) in ["one", "two", "one two"].into_iter().enumerate() {
+            model.sources.push(WidgetSource {
+                id,
+                height: 1,
+                data: WidgetSourceData::Line(Line::from(text), Vec::new()),
+            });
+        }
+        model.cursor = Cursor::Search(
+            SearchState {
+                needle: String::from("one"),
+                accepted: true,
+                regex: false,
+            },
+            None,
+        );
+        model.keep_search();
+        assert_eq!(model.highlights(), ["one"]);
+        assert_eq!(
+            model.cursor,
+            Cursor::Search(SearchState::default(), None),
+            "started another search"
+        );
+
+        model.add_searches(Some(String::from("two")));
+        let WidgetSourceData::Line(_, extras) = &model.sources[2].data else {
+            panic!("Line");
+        };
+        assert_eq!(
+            extras,
+            &vec![
+                LineExtra::SearchMatch(4, 7, String::from("two"), 0),
+                LineExtra::SearchMatch(0, 3, String::from("one"), 1),
+            ]
+        );
+
+        model.cursor_next();
+        assert_eq!(
+            model.cursor.pointer(),
+            Some(&CursorPointer { id: 1, index: 0 }),
+            "skipped the highlight"
+        );
+
+        model.clear_highlights();
+        assert!(model.highlights().is_empty());
+        let WidgetSourceData::Line(_, extras) = &model.sources[0].data else {
+            panic!("Line");
+        };
+        assert!(extras.is_empty(), "highlight removed");
+    }
+
+    #[test]
+    fn regex_search() {
+        let mut model = test_model();
+        for (id, text) in ["one", "two", "o(e"].into_iter().enumerate() {
+            model.sources.push(WidgetSource {
+                id,
+                height: 1,
+                data: WidgetSourceData::Line(Line::from(text), Vec::new()),
+            });
+        }
+        let extras = |model: &Model, i: usize| {
+            let WidgetSourceData::Line(_, extras) = &model.sources[i].data else {
+                panic!("Line");
+            };
+            extras.clone()
+        };
+        model.cursor = Cursor::Search(
+            SearchState {
+                regex: true,
+                ..Default::default()
+            },
+            None,
+        );
+
+        model.add_searches(Some(String::from("^o.e$")));
+        assert_eq!(
+            extras(&model, 0),
+            vec![LineExtra::SearchMatch(0, 3, String::from("one"), 0)]
+        );
+        assert_eq!(
+            extras(&model, 2),
+            vec![LineExtra::SearchMatch(0, 3, String::from("o(e"), 0)]
+        );
+
+        model.add_searches(Some(String::from("o(e")));
+        assert!(
+            model
+                .error_message()
+                .is_some_and(|msg| msg.starts_with("Regex error")),
+            "shows the compile error"
+        );
+        assert_eq!(extras(&model, 0).len(), 1, "kept the previous matches");
+
+        if let Cursor::Search(mode, _) = &mut model.cursor {
+            mode.needle = String::from("o(e");
+        }
+        model.toggle_search_regex();
+        assert_eq!(model.error_message(), None, "cleared the error");
+        assert!(extras(&model, 0).is_empty(), "literal now");
+        assert_eq!(extras(&model, 2).len(), 1);
+    }
+
+    #[test]
+    fn search_count() {
+        let mut model = test_model();
+        for (id, text) in ["one", "two", "one one"].into_iter().enumerate() {
+            model.sources.push(WidgetSource {
+                id,
+                height: 1,
+                data: WidgetSourceData::Line(Line::from(text), Vec::new()),
+            });
+        }
+        assert_eq!(model.search_count(), None, "not searching");
+
+        model.cursor = Cursor::Search(SearchState::default(), None);
+        model.add_searches(Some(String::from("one")));
+        assert_eq!(model.search_count(), Some((1, 3)), "the first jump");
+
+        model.cursor_next();
+        model.cursor_next();
+        assert_eq!(model.search_count(), Some((2, 3)));
+        model.cursor_prev();
+        model.cursor_prev();
+        assert_eq!(model.search_count(), Some((3, 3)), "wrapped around");
+
+        model.add_searches(Some(String::from("three")));
+        assert_eq!(model.search_count(), Some((0, 0)));
+    }
+
+    #[test]
+    fn offscreen_matches() {
+        let mut model = test_model();
+        for id in 0..60 {
+            let text = if id == 40 { "needle" } else { "hay" };
+            model.sources.push(WidgetSource {
+                id,
+                height: 1,
+                data: WidgetSourceData::Line(Line::from(text), Vec::new()),
+            });
+        }
+        model.cursor = Cursor::Search(SearchState::default(), None);
+        model.add_searches(Some(String::from("needle")));
+        assert_eq!(model.offscreen_matches(), None, "not accepted yet");
+
+        model.cursor = Cursor::Search(
+            SearchState {
+                needle: String::from("needle"),
+                accepted: true,
+                regex: false,
+            },
+            None,
+        );
+        assert_eq!(model.offscreen_matches(), Some((false, true)));
+
+        model.scroll = 35;
+        assert_eq!(model.offscreen_matches(), None, "on screen");
+
+        model.scroll = 45;
+        assert_eq!(model.offscreen_matches(), Some((true, false)));
+
+        model.add_searches(Some(String::from("nothing")));
+        assert_eq!(model.offscreen_matches(), None, "no matches at all");
+    }
+
+    #[test]
+    fn search_headers() {
+        let mut model = test_model();
+        for id in 0..30 {
+            let data = if id == 25 {
+                WidgetSourceData::Header(String::from("Deep Fried"), 1, Vec::new())
+            } else {
+                WidgetSourceData::Line(Line::from("line"), Vec::new())
+            };
+            model.sources.push(WidgetSource {
+                id,
+                height: 1,
+                data,
+            });
+        }
+        model.cursor = Cursor::Search(SearchState::default(), None);
+        model.add_searches(Some(String::from("fried")));
+        assert_eq!(model.search_count(), Some((1, 1)));
+
+        model.cursor_next();
+        assert_eq!(
+            model.cursor.pointer(),
+            Some(&CursorPointer { id: 25, index: 0 })
+        );
+        assert!(model.scroll > 0, "scrolled the header into view");
+    }
+
+    #[test]
+    fn search_header_images() {
+        let (event_tx, event_rx) = mpsc::channel::<Event>();
+        let mut model = test_model_with_events(UserConfig::default().into(), event_rx);
+        for id in 0..30 {
+            model.sources.push(WidgetSource {
+                id,
+                height: 1,
+                data: WidgetSourceData::Line(Line::from("line"), Vec::new()),
+            });
+        }
+        // With a font, the worker renders headers as images.
+        let header = header_sources(
+            &Picker::halfblocks(),
+            20,
+            25,
+            vec![(String::from("Needle"), DynamicImage::new_rgb8(40, 8))],
+            false,
+        )
+        .unwrap();
+        event_tx
+            .send(Event::Update(model.document_id, header))
+            .unwrap();
+        model.process_events(80).unwrap();
+        assert!(
+            model
+                .sources()
+                .any(|source| matches!(source.data, WidgetSourceData::HeaderImage(..)))
+        );
+
+        model.cursor = Cursor::Search(SearchState::default(), None);
+        model.add_searches(Some(String::from("needle")));
+        assert_eq!(model.search_count(), Some((1, 1)));
+        model.cursor_next();
+        assert_eq!(
+            model.cursor.pointer(),
+            Some(&CursorPointer { id: 25, index: 0 })
+        );
+    }
+
+    #[test]
+    fn goto_source() {
+        let mut model = test_model();
+        push_outline_document(&mut model);
+
+        model.goto_source_input = Some(String::from("12"));
+        model.goto_source_accept();
+        assert_eq!(model.scroll, 14, "jumped to third header");
+        assert_eq!(model.goto_source_input, None, "closed prompt");
+
+        model.goto_source_input = Some(String::from("999"));
+        model.goto_source_accept();
+        assert_eq!(model.scroll, 14, "did not move");
+        assert_eq!(model.error_message(), Some("No source #999"));
+    }
+
     #[test]
     fn scrolls_into_view() {
         let mut model = test_model();
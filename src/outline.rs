@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Stylize as _},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Padding, Paragraph},
+};
+
+use crate::{markdown::header_spans, widget_sources::SourceID};
+
+// Width of the pinned outline panel, see `Config::show_outline`.
+pub const PANEL_WIDTH: u16 = 30;
+
+const COLOR_NUMBER: Color = Color::Indexed(63);
+pub const COLOR_CURRENT: Color = Color::Indexed(220);
+
+// A header of the document, as it appears in the outline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineEntry {
+    // The (first) source of the header.
+    pub id: SourceID,
+    pub tier: u8,
+    pub text: String,
+}
+
+impl OutlineEntry {
+    fn indent(&self) -> String {
+        "  ".repeat(self.tier.saturating_sub(1) as usize)
+    }
+}
+
+// The index of the header that a link `#anchor` points to, with GitHub style anchors: repeated
+// headers get `-1`, `-2`, ... appended.
+pub fn anchor_index(entries: &[OutlineEntry], anchor: &str) -> Option<usize> {
+    let anchor = anchor.to_lowercase();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    entries.iter().position(|entry| {
+        let text: String = header_spans(&entry.text)
+            .into_iter()
+            .map(|span| span.text)
+            .collect();
+        let slug = slug(&text);
+        let count = seen.entry(slug.clone()).or_default();
+        let candidate = if *count == 0 {
+            slug
+        } else {
+            format!("{slug}-{count}")
+        };
+        *count += 1;
+        candidate == anchor
+    })
+}
+
+// Lowercase, without punctuation, and spaces as dashes.
+fn slug(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+// Render the numbered outline as an overlay, with the typed jump number in the title.
+pub fn render_overlay(
+    frame: &mut Frame,
+    entries: &[OutlineEntry],
+    current: Option<usize>,
+    input: &str,
+) {
+    let number_width = entries.len().to_string().len();
+
+    let lines: Vec<Line> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let mut text = Span::from(format!("{}{}", entry.indent(), entry.text));
+            if current == Some(i) {
+                text = text.fg(COLOR_CURRENT);
+            }
+            Line::from(vec![
+                Span::from(format!("{:>number_width$}. ", i + 1)).fg(COLOR_NUMBER),
+                text,
+            ])
+        })
+        .collect();
+
+    let title = if input.is_empty() {
+        String::from("Outline")
+    } else {
+        format!("Outline: {input}")
+    };
+    render_list_overlay(frame, title, lines, current);
+}
+
+// Render the table of contents as an overlay, with the selected header highlighted.
+pub fn render_toc(frame: &mut Frame, entries: &[OutlineEntry], selected: usize) {
+    let lines: Vec<Line> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let line = Line::from(format!("{}{}", entry.indent(), entry.text));
+            if i == selected {
+                line.fg(COLOR_CURRENT)
+            } else {
+                line
+            }
+        })
+        .collect();
+    render_list_overlay(frame, String::from("Contents"), lines, Some(selected));
+}
+
+// Render a centered list overlay, keeping the `current` item in view.
+pub fn render_list_overlay(
+    frame: &mut Frame,
+    title: String,
+    lines: Vec<Line>,
+    current: Option<usize>,
+) {
+    let frame_area = frame.area();
+    let content_width = lines.iter().map(Line::width).max().unwrap_or_default() as u16;
+    let width = (content_width + 4).max(20).min(frame_area.width);
+    let height = (lines.len() as u16 + 2).min(frame_area.height.saturating_sub(1));
+    let area = Rect::new(
+        frame_area.x + (frame_area.width - width) / 2,
+        frame_area.y + (frame_area.height.saturating_sub(1) - height) / 2,
+        width,
+        height,
+    );
+
+    let block = Block::bordered()
+        .title(title)
+        .border_style(COLOR_NUMBER)
+        .padding(Padding::horizontal(1));
+
+    // Keep the current item in view if the list is longer than the overlay.
+    let inner_height = block.inner(area).height;
+    let scroll = visible_offset(current, lines.len(), inner_height);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block).scroll((scroll, 0)), area);
+}
+
+// Render the outline pinned to the side, highlighting the current section.
+pub fn render_panel(
+    frame: &mut Frame,
+    area: Rect,
+    entries: &[OutlineEntry],
+    current: Option<usize>,
+) {
+    let block = Block::default()
+        .borders(Borders::LEFT)
+        .border_style(Color::Indexed(237))
+        .padding(Padding::horizontal(1));
+
+    let lines: Vec<Line> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let line = Line::from(format!("{}{}", entry.indent(), entry.text));
+            if current == Some(i) {
+                line.fg(COLOR_CURRENT)
+            } else {
+                line.dark_gray()
+            }
+        })
+        .collect();
+
+    let scroll = visible_offset(current, lines.len(), block.inner(area).height);
+    frame.render_widget(Paragraph::new(lines).block(block).scroll((scroll, 0)), area);
+}
+
+fn visible_offset(current: Option<usize>, len: usize, height: u16) -> u16 {
+    let height = height as usize;
+    match current {
+        Some(current) if len > height && current >= height / 2 => {
+            (current - height / 2).min(len - height) as u16
+        }
+        _ => 0,
+    }
+}
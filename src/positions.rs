@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::recent::STATE_APP_NAME;
+
+const STATE_POSITIONS_NAME: &str = "positions";
+
+// Files beyond this are forgotten, least recently closed first.
+const POSITIONS_MAX: usize = 100;
+
+// The state file of the last scroll positions in files, most recent first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Positions {
+    files: Vec<Position>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Position {
+    path: PathBuf,
+    scroll: u16,
+}
+
+// The state file, in the same directory as the recent files.
+pub fn state_file() -> Option<PathBuf> {
+    confy::get_configuration_file_path(STATE_APP_NAME, STATE_POSITIONS_NAME)
+        .inspect_err(|err| log::warn!("no state file for positions: {err}"))
+        .ok()
+}
+
+fn load_all(file: &Path) -> Vec<Position> {
+    match confy::load_path::<Positions>(file) {
+        Ok(positions) => positions.files,
+        Err(err) => {
+            log::warn!("could not load positions: {err}");
+            Vec::new()
+        }
+    }
+}
+
+// The scroll position that `path` was left at, from the state `file`.
+pub fn load(file: &Path, path: &Path) -> Option<u16> {
+    let path = path.canonicalize().ok()?;
+    load_all(file)
+        .into_iter()
+        .find(|position| position.path == path)
+        .map(|position| position.scroll)
+}
+
+// Failing to write the state file is not worth bothering the user, like with the recent files.
+pub fn remember(file: &Path, path: &Path, scroll: u16) {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut files = load_all(file);
+    files.retain(|position| position.path != path);
+    files.insert(0, Position { path, scroll });
+    files.truncate(POSITIONS_MAX);
+    if let Err(err) = confy::store_path(file, Positions { files }) {
+        log::warn!("could not store positions: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{POSITIONS_MAX, load, remember};
+
+    #[test]
+    fn remember_and_load() {
+        let dir = std::env::temp_dir().join(format!("mdfried_positions_{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("create_dir_all");
+        let state = dir.join("positions.toml");
+        let a = dir.join("a.md");
+        fs::write(&a, "a").expect("write");
+
+        assert_eq!(load(&state, &a), None);
+        remember(&state, &a, 7);
+        assert_eq!(load(&state, &a), Some(7));
+        remember(&state, &a, 3);
+        assert_eq!(load(&state, &a), Some(3), "the latest position");
+
+        for i in 0..POSITIONS_MAX {
+            let file = dir.join(format!("{i}.md"));
+            fs::write(&file, "").expect("write");
+            remember(&state, &file, 1);
+        }
+        assert_eq!(load(&state, &a), None, "the least recent file is forgotten");
+        assert_eq!(load(&state, &dir.join("0.md")), Some(1));
+
+        fs::remove_dir_all(&dir).expect("remove_dir_all");
+    }
+}
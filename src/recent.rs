@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+use ratatui::{Frame, style::Stylize as _, text::Line};
+use serde::{Deserialize, Serialize};
+
+use crate::outline::{COLOR_CURRENT, render_list_overlay};
+
+pub const STATE_APP_NAME: &str = "mdfried";
+const STATE_RECENT_NAME: &str = "recent";
+
+// The state file of recently opened files, most recent first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RecentFiles {
+    files: Vec<PathBuf>,
+}
+
+pub fn load() -> Vec<PathBuf> {
+    match confy::load::<RecentFiles>(STATE_APP_NAME, STATE_RECENT_NAME) {
+        Ok(recent) => recent.files,
+        Err(err) => {
+            log::warn!("could not load recent files: {err}");
+            Vec::new()
+        }
+    }
+}
+
+// Move `path` to the top of the recent files, keeping at most `max` files.
+// Failing to write the state file is not worth bothering the user.
+pub fn remember(path: &Path, max: usize) {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut files = load();
+    files.retain(|file| *file != path);
+    files.insert(0, path);
+    files.truncate(max);
+    if let Err(err) = confy::store(STATE_APP_NAME, STATE_RECENT_NAME, RecentFiles { files }) {
+        log::warn!("could not store recent files: {err}");
+    }
+}
+
+// The recent files picker, shown when launched without a file.
+#[derive(Debug)]
+pub struct RecentMenu {
+    files: Vec<PathBuf>,
+    selected: usize,
+}
+
+impl RecentMenu {
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        RecentMenu { files, selected: 0 }
+    }
+
+    pub fn next(&mut self) {
+        if self.selected + 1 < self.files.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn prev(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn selected(&self) -> Option<&PathBuf> {
+        self.files.get(self.selected)
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let lines = self
+            .files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| {
+                let line = Line::from(file.display().to_string());
+                if i == self.selected {
+                    line.fg(COLOR_CURRENT)
+                } else {
+                    line
+                }
+            })
+            .collect();
+        render_list_overlay(
+            frame,
+            String::from("Recent files"),
+            lines,
+            Some(self.selected),
+        );
+    }
+}
@@ -2,6 +2,11 @@ pub mod configpicker;
 mod fontpicker;
 pub mod notification;
 
+use std::{
+    io::{self, Write as _},
+    time::Duration,
+};
+
 use cosmic_text::{FontSystem, SwashCache};
 use image::Rgba;
 use ratatui_image::{
@@ -10,14 +15,20 @@ use ratatui_image::{
 };
 
 use crate::{
-    config::{self, UserConfig},
+    config::{self, HeaderMode, UserConfig},
     error::Error,
 };
 use fontpicker::interactive_font_picker;
 
-#[derive(Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct BgColor([u8; 4]);
 
+impl From<[u8; 3]> for BgColor {
+    fn from([r, g, b]: [u8; 3]) -> Self {
+        BgColor([r, g, b, 255])
+    }
+}
+
 impl From<BgColor> for Rgba<u8> {
     fn from(value: BgColor) -> Self {
         Rgba(value.0)
@@ -56,7 +67,11 @@ impl FontRenderer {
 pub enum SetupResult {
     Aborted,
     TextSizing(Picker, Option<BgColor>),
+    // No fonts to render headers with, or `headers = "text"`, they will be plain text.
+    NoFont(Picker, Option<BgColor>),
     Complete(Picker, Option<BgColor>, Box<FontRenderer>),
+    // Neither graphics nor fonts, headers are plain text and images are shown as their alt text.
+    TextOnly(Picker, Option<BgColor>),
 }
 
 pub fn setup_graphics(
@@ -64,17 +79,35 @@ pub fn setup_graphics(
     force_font_setup: bool,
     no_cap_checks: bool,
     debug_override_protocol_type: Option<ProtocolType>,
+    query_timeout: Duration,
 ) -> Result<SetupResult, Error> {
     let mut picker = if no_cap_checks {
         Picker::halfblocks()
     } else {
         print!("Detecting supported graphics protocols...");
-        let picker = Picker::from_query_stdio_with_options(QueryStdioOptions {
+        // Without a newline, the message would only show up once the query is done.
+        io::stdout().flush()?;
+        // Terminals that don't answer, or slow connections, must not block the start.
+        match Picker::from_query_stdio_with_options(QueryStdioOptions {
             text_sizing_protocol: true,
-            ..Default::default()
-        })?;
-        println!(" {:?}.", picker.protocol_type());
-        picker
+            timeout: query_timeout,
+        }) {
+            Ok(picker) => {
+                println!(" {:?}.", picker.protocol_type());
+                picker
+            }
+            Err(err) => {
+                log::warn!("capability query failed: {err}");
+                println!(
+                    " failed ({err}), falling back to halfblocks. If the terminal did not answer \
+                    within {}ms, set a longer capability_query_timeout_milliseconds, or pick the \
+                    protocol with debug_override_protocol_type, or skip the detection with \
+                    --no-cap-checks.",
+                    query_timeout.as_millis()
+                );
+                Picker::halfblocks()
+            }
+        }
     };
 
     let bg = if picker.protocol_type() == ProtocolType::Sixel {
@@ -84,6 +117,10 @@ pub fn setup_graphics(
         None
     };
 
+    if config.headers == Some(HeaderMode::Text) {
+        return Ok(SetupResult::NoFont(picker, bg));
+    }
+
     let has_text_size_protocol = picker
         .capabilities()
         .contains(&Capability::TextSizingProtocol);
@@ -100,6 +137,20 @@ pub fn setup_graphics(
         .map(|faceinfo| faceinfo.families[0].0.clone())
         .collect();
 
+    if all_font_families.is_empty() {
+        log::warn!("no system fonts found");
+        println!(
+            "No system fonts found, headers will be shown as plain text. \
+            Install some fonts (and fontconfig), or use a terminal that supports the \
+            Text Sizing Protocol, to get big headers."
+        );
+    }
+    let protocol_type = debug_override_protocol_type.unwrap_or(picker.protocol_type());
+    if let Some(result) = degraded_setup(&picker, bg, protocol_type, !all_font_families.is_empty())
+    {
+        return Ok(result);
+    }
+
     let config_font_family = if force_font_setup {
         println!("Forced font setup");
         None
@@ -116,7 +167,14 @@ pub fn setup_graphics(
 
     let font_name = match config_font_family {
         Some(font_family) => font_family.clone(),
-        None => match interactive_font_picker(&mut picker, bg) {
+        None => match interactive_font_picker(
+            &mut picker,
+            bg,
+            config
+                .font_preview_text
+                .as_deref()
+                .unwrap_or(config::DEFAULT_FONT_PREVIEW_TEXT),
+        ) {
             Ok(Some(setup_font_family)) => {
                 config::store_font_family(config, setup_font_family.clone())?;
                 notification::interactive_notification("Font has been written to config file.")?;
@@ -145,3 +203,53 @@ pub fn setup_graphics(
         )),
     ))
 }
+
+// Without fonts, headers are plain text. Without graphics either, there is nothing to render at all
+// but text. Halfblocks with fonts still get the font setup, for rasterized headers.
+fn degraded_setup(
+    picker: &Picker,
+    bg: Option<BgColor>,
+    protocol_type: ProtocolType,
+    has_fonts: bool,
+) -> Option<SetupResult> {
+    let text_only = match (protocol_type, has_fonts) {
+        (ProtocolType::Halfblocks, false) => true,
+        (_, false) => false,
+        (_, true) => return None,
+    };
+    let mut picker = picker.clone();
+    picker.set_protocol_type(protocol_type);
+    Some(if text_only {
+        SetupResult::TextOnly(picker, bg)
+    } else {
+        SetupResult::NoFont(picker, bg)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui_image::picker::{Picker, ProtocolType};
+
+    use super::{SetupResult, degraded_setup};
+
+    #[test]
+    fn degraded() {
+        let picker = Picker::halfblocks();
+        assert!(matches!(
+            degraded_setup(&picker, None, ProtocolType::Halfblocks, false),
+            Some(SetupResult::TextOnly(..))
+        ));
+        assert!(
+            degraded_setup(&picker, None, ProtocolType::Halfblocks, true).is_none(),
+            "halfblocks can show rasterized headers"
+        );
+        assert!(matches!(
+            degraded_setup(&picker, None, ProtocolType::Kitty, false),
+            Some(SetupResult::NoFont(..))
+        ));
+        assert!(
+            degraded_setup(&picker, None, ProtocolType::Kitty, true).is_none(),
+            "fonts and graphics need the font setup"
+        );
+    }
+}
@@ -14,6 +14,7 @@ use ratatui_image::{Image, picker::Picker, protocol::Protocol};
 
 use crate::{
     Error,
+    markdown::ParseOptions,
     setup::{BgColor, FontRenderer},
     widget_sources::{WidgetSourceData, header_images, header_sources},
 };
@@ -22,6 +23,7 @@ use crate::{
 pub fn interactive_font_picker(
     picker: &mut Picker,
     bg: Option<BgColor>,
+    preview_text: &str,
 ) -> Result<Option<String>, Error> {
     let mut input = String::new();
 
@@ -111,21 +113,20 @@ pub fn interactive_font_picker(
                         .is_none_or(|(m, _)| *m != first_match)
                 {
                     renderer.font_name.clone_from(&first_match);
-                    const SAMPLE: &str = "The fox jumped over the goat or something";
-                    let spans = vec![SAMPLE.into()];
                     let dyn_imgs = header_images(
                         bg,
                         &mut renderer,
                         inner_width,
-                        Line::from(spans).to_string(),
+                        preview_text.to_owned(),
                         1,
+                        &ParseOptions::default(),
                         false,
                     )?;
                     let sources = header_sources(picker, inner_width, 0, dyn_imgs, false)?;
 
                     // Just render the first line if it got split.
                     if let Some(source) = sources.into_iter().next() {
-                        if let WidgetSourceData::Image(_, proto) = source.data {
+                        if let WidgetSourceData::HeaderImage(_, proto, _) = source.data {
                             last_rendered = Some((first_match.clone(), proto));
                         }
                     }
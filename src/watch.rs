@@ -1,7 +1,7 @@
 use std::{
     cell::Cell,
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::mpsc::Sender,
     time::{Duration, SystemTime},
 };
@@ -71,3 +71,37 @@ pub fn watch(
         .watch(&parent, RecursiveMode::NonRecursive)?;
     Ok(debouncer)
 }
+
+// The watcher of the open file, which follows the model when another file is opened.
+pub struct FileWatcher {
+    path: PathBuf,
+    tx: Sender<Event<'static>>,
+    debounce_milliseconds: u64,
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+impl FileWatcher {
+    pub fn new(
+        path: PathBuf,
+        tx: Sender<Event<'static>>,
+        debounce_milliseconds: u64,
+    ) -> Result<FileWatcher, Error> {
+        let debouncer = watch(&path, tx.clone(), debounce_milliseconds)?;
+        Ok(FileWatcher {
+            path,
+            tx,
+            debounce_milliseconds,
+            _debouncer: debouncer,
+        })
+    }
+
+    // Watch `path` instead, if it is another file than the watched one.
+    pub fn follow(&mut self, path: &Path) -> Result<(), Error> {
+        if path == self.path {
+            return Ok(());
+        }
+        log::info!("watch follows to {path:?}");
+        *self = FileWatcher::new(path.to_owned(), self.tx.clone(), self.debounce_milliseconds)?;
+        Ok(())
+    }
+}
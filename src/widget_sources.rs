@@ -1,39 +1,55 @@
 use std::{
     any::Any as _,
+    collections::HashSet,
     fmt::{Debug, Display, Write as _},
+    fs::File,
+    io::{BufRead, BufReader, Cursor, Seek},
     ops::{Deref, DerefMut},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use itertools::Either;
 
-use cosmic_text::{Attrs, Buffer, Color, Family, Metrics, Shaping};
+use cosmic_text::{Attrs, Buffer, Color, Family, Metrics, Shaping, Style, Weight};
 use image::{
-    DynamicImage, GenericImage as _, ImageFormat, ImageReader, Pixel as _, Rgba, RgbaImage,
+    AnimationDecoder as _, DynamicImage, GenericImage as _, ImageDecoder as _, ImageFormat,
+    ImageReader, Pixel as _, Rgba, RgbaImage,
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
     imageops,
 };
-use ratatui::{layout::Rect, text::Line, widgets::Widget};
+use ratatui::{
+    layout::Rect,
+    style::Stylize as _,
+    text::{Line, Span},
+    widgets::Widget,
+};
 
 use ratatui_image::{Resize, picker::Picker, protocol::Protocol};
 use regex::{Match, Regex};
 use reqwest::{
     Client,
     header::{ACCEPT, CONTENT_TYPE, HeaderMap, HeaderValue},
+    redirect::Policy,
 };
 use tokio::sync::RwLock;
-use unicode_width::UnicodeWidthStr as _;
+use unicode_width::{UnicodeWidthChar as _, UnicodeWidthStr as _};
 
 use crate::{
     Error,
+    config::ImageWidth,
     cursor::CursorPointer,
+    data_uri,
+    image_cache::ImageCache,
+    markdown::{ParseOptions, header_spans},
     setup::{BgColor, FontRenderer},
 };
 
 #[derive(Default)]
 pub struct WidgetSources<'a> {
     sources: Vec<WidgetSource<'a>>,
-    updated_images: Vec<(u16, String, Protocol)>,
+    updated_images: Vec<WidgetSource<'a>>,
 }
 
 impl<'a> WidgetSources<'a> {
@@ -71,11 +87,8 @@ impl<'a> WidgetSources<'a> {
 
         if let Some((start, end)) = range {
             let splice = self.sources.splice(start..end, updates);
-            for splice in splice {
-                if let WidgetSourceData::Image(url, proto) = splice.data {
-                    self.updated_images.push((splice.height, url, proto));
-                }
-            }
+            self.updated_images
+                .extend(splice.filter(|source| source.data.image_url().is_some()));
         } else if let Some(last) = self.sources.last()
             && last.id < first_id
         {
@@ -88,40 +101,64 @@ impl<'a> WidgetSources<'a> {
         }
     }
 
+    // Find an already loaded image by url, to be reused for source `id`.
+    // Candidates are either sources not yet reached by the current parse (`id` or later), or images
+    // that have been overwritten by it. The candidate with the nearest id wins, so that reordered
+    // images with identical urls don't steal each other's protocols.
     pub fn replace(&mut self, id: SourceID, url: &str) -> Option<WidgetSource<'a>> {
-        for source in &mut self.sources {
-            if source.id < id {
-                continue;
+        let in_place = self
+            .sources
+            .iter()
+            .position(|source| source.id >= id && source.data.image_url() == Some(url));
+        let updated = self
+            .updated_images
+            .iter()
+            .enumerate()
+            .filter(|(_, source)| source.data.image_url() == Some(url))
+            .min_by_key(|(_, source)| source.id.abs_diff(id))
+            .map(|(i, source)| (i, source.id));
+
+        match (in_place, updated) {
+            (Some(i), Some((_, stored_id)))
+                if self.sources[i].id.abs_diff(id) <= stored_id.abs_diff(id) =>
+            {
+                Some(self.take_image(i, url))
             }
-            if let WidgetSourceData::Image(existing_url, _) = &source.data
-                && *existing_url == url
+            (Some(i), None) => Some(self.take_image(i, url)),
+            (_, Some((i, _))) => Some(self.updated_images.remove(i)), // id overwritten by caller
+            (None, None) => None,
+        }
+    }
+
+    // Turn loaded images back into placeholders, so that the next parse loads them again instead
+    // of reusing them.
+    pub fn unload_images(&mut self) {
+        self.updated_images.clear();
+        for source in &mut self.sources {
+            if let WidgetSourceData::Image(url, _) | WidgetSourceData::AnimatedImage(url, _) =
+                &source.data
             {
-                let removed_image = std::mem::replace(
-                    source,
-                    WidgetSource {
-                        id: source.id,
-                        height: 1,
-                        data: WidgetSourceData::Line(
-                            Line::from(format!("![Replacing...]({url})")),
-                            Vec::new(),
-                        ),
-                    },
-                );
-                log::debug!("search & replaced #{}: {}", source.id, source.data);
-                return Some(removed_image);
+                source.data =
+                    WidgetSourceData::Line(Line::from(format!("![Loading...]({url})")), Vec::new());
             }
         }
-        self.updated_images
-            .iter()
-            .position(|(_, stored_url, _)| stored_url == url)
-            .map(|i| {
-                let (height, url, proto) = self.updated_images.remove(i);
-                WidgetSource {
-                    id: 0, // will be overwritten by caller
-                    height,
-                    data: WidgetSourceData::Image(url, proto),
-                }
-            })
+    }
+
+    fn take_image(&mut self, i: usize, url: &str) -> WidgetSource<'a> {
+        let source = &mut self.sources[i];
+        let removed_image = std::mem::replace(
+            source,
+            WidgetSource {
+                id: source.id,
+                height: 1,
+                data: WidgetSourceData::Line(
+                    Line::from(format!("![Replacing...]({url})")),
+                    Vec::new(),
+                ),
+            },
+        );
+        log::debug!("search & replaced #{}: {}", source.id, source.data);
+        removed_image
     }
 
     pub fn trim_last_source(&mut self, last_source_id: Option<usize>) {
@@ -163,7 +200,7 @@ impl<'a> WidgetSources<'a> {
         scroll: u16,
     ) -> Option<CursorPointer> {
         let locate = move |source: &WidgetSource| -> Option<CursorPointer> {
-            if let WidgetSourceData::Line(_, extras) = &source.data
+            if let Some(extras) = source.data.extras()
                 && let Some(i) = extras.iter().position(|extra| target.matches(extra))
             {
                 Some(CursorPointer {
@@ -178,8 +215,8 @@ impl<'a> WidgetSources<'a> {
         let mut first = None;
         let mut offset_acc = 0;
         for source in iter {
-            offset_acc += source.height;
-            if offset_acc < scroll + 1 {
+            offset_acc = source.height.saturating_add(offset_acc);
+            if offset_acc <= scroll {
                 if first.is_none() {
                     first = locate(source);
                 }
@@ -215,6 +252,32 @@ impl<'a> WidgetSources<'a> {
         first
     }
 
+    // The 1-based position of `current` among the search matches, or 0 if it is none of them, and
+    // the number of matches.
+    pub fn search_position<'b, Iter: DoubleEndedIterator<Item = &'b WidgetSource<'b>>>(
+        iter: Iter,
+        current: Option<&CursorPointer>,
+    ) -> (usize, usize) {
+        let mut position = 0;
+        let mut total = 0;
+        for pointer in WidgetSources::flatten_sources(iter, &FindMode::Next, &FindTarget::Search) {
+            total += 1;
+            if current == Some(&pointer) {
+                position = total;
+            }
+        }
+        (position, total)
+    }
+
+    // The ids of the sources with search matches.
+    pub fn search_match_ids<'b, Iter: DoubleEndedIterator<Item = &'b WidgetSource<'b>>>(
+        iter: Iter,
+    ) -> HashSet<SourceID> {
+        WidgetSources::flatten_sources(iter, &FindMode::Next, &FindTarget::Search)
+            .map(|pointer| pointer.id)
+            .collect()
+    }
+
     fn flatten_sources<'b>(
         iter: impl DoubleEndedIterator<Item = &'b WidgetSource<'b>>,
         mode: &FindMode,
@@ -240,7 +303,7 @@ impl<'a> WidgetSources<'a> {
     > {
         match mode {
             FindMode::Next => {
-                if let WidgetSourceData::Line(_, extras) = &source.data {
+                if let Some(extras) = source.data.extras() {
                     let id = source.id;
                     Either::Left(Either::Left(
                         extras
@@ -254,7 +317,7 @@ impl<'a> WidgetSources<'a> {
                 }
             }
             FindMode::Prev => {
-                if let WidgetSourceData::Line(_, extras) = &source.data {
+                if let Some(extras) = source.data.extras() {
                     let id = source.id;
                     Either::Left(Either::Right(
                         extras
@@ -271,13 +334,12 @@ impl<'a> WidgetSources<'a> {
         }
     }
 
-    #[cfg(test)]
     pub fn find_extra_by_cursor(&self, pointer: &CursorPointer) -> Option<&LineExtra> {
         for source in self.iter() {
             if source.id != pointer.id {
                 continue;
             }
-            let WidgetSourceData::Line(_, extras) = &source.data else {
+            let Some(extras) = source.data.extras() else {
                 continue;
             };
             if let Some(extra) = extras.get(pointer.index) {
@@ -311,13 +373,19 @@ pub enum FindMode {
 #[derive(Debug)]
 pub enum FindTarget {
     Link,
+    // Links, and tasks that can be toggled.
+    LinkOrTask,
     Search,
 }
 impl FindTarget {
     fn matches(&self, extra: &LineExtra) -> bool {
         match self {
             FindTarget::Link => matches!(extra, LineExtra::Link(_, _, _)),
-            FindTarget::Search => matches!(extra, LineExtra::SearchMatch(_, _, _)),
+            FindTarget::LinkOrTask => {
+                matches!(extra, LineExtra::Link(_, _, _) | LineExtra::Task(_, _))
+            }
+            // Only the current needle, not the kept highlights.
+            FindTarget::Search => matches!(extra, LineExtra::SearchMatch(_, _, _, 0)),
         }
     }
 }
@@ -333,33 +401,81 @@ pub struct WidgetSource<'a> {
 
 pub enum WidgetSourceData<'a> {
     Image(String, Protocol),
+    AnimatedImage(String, Animation),
     BrokenImage(String, String),
     Line(Line<'a>, Vec<LineExtra>),
-    Header(String, u8),
+    // The text, tier, and search matches, which are jumped to but not highlighted over the big
+    // text.
+    Header(String, u8, Vec<LineExtra>),
+    // A header rendered as an image, with its text and search matches, like `Header`.
+    HeaderImage(String, Protocol, Vec<LineExtra>),
 }
 
 impl WidgetSourceData<'_> {
-    pub fn add_search(&mut self, re: &Option<Regex>) {
-        if let WidgetSourceData::Line(line, extras) = self {
-            let line_string = line.to_string();
-            extras.retain(|extra| !matches!(extra, LineExtra::SearchMatch(_, _, _)));
+    // The index of each regex is the group of its matches, `None` leaves a group out.
+    pub fn add_search(&mut self, res: &[Option<Regex>]) {
+        let (line_string, extras) = match self {
+            WidgetSourceData::Line(line, extras) => (line.to_string(), extras),
+            WidgetSourceData::Header(text, _, extras)
+            | WidgetSourceData::HeaderImage(text, _, extras) => (text.clone(), extras),
+            WidgetSourceData::Image(..)
+            | WidgetSourceData::AnimatedImage(..)
+            | WidgetSourceData::BrokenImage(..) => return,
+        };
+        extras.retain(|extra| !matches!(extra, LineExtra::SearchMatch(..)));
+        for (group, re) in res.iter().enumerate() {
             if let Some(re) = re {
                 extras.extend(
                     re.find_iter(&line_string)
-                        .map(WidgetSourceData::regex_to_searchmatch(&line_string)),
+                        .map(WidgetSourceData::regex_to_searchmatch(&line_string, group)),
                 );
             }
         }
-        // TODO: search in headers
+    }
+
+    // The links, matches and such of lines, and the search matches of headers.
+    fn extras(&self) -> Option<&Vec<LineExtra>> {
+        match self {
+            WidgetSourceData::Line(_, extras)
+            | WidgetSourceData::Header(_, _, extras)
+            | WidgetSourceData::HeaderImage(_, _, extras) => Some(extras),
+            WidgetSourceData::Image(..)
+            | WidgetSourceData::AnimatedImage(..)
+            | WidgetSourceData::BrokenImage(..) => None,
+        }
+    }
+
+    // The url of a loaded image, animated or not, that can be reused by `WidgetSources::replace`.
+    fn image_url(&self) -> Option<&str> {
+        match self {
+            WidgetSourceData::Image(url, _) | WidgetSourceData::AnimatedImage(url, _) => Some(url),
+            WidgetSourceData::BrokenImage(..)
+            | WidgetSourceData::Line(..)
+            | WidgetSourceData::Header(..)
+            | WidgetSourceData::HeaderImage(..) => None,
+        }
+    }
+
+    // The protocol of an image, or the current frame of an animated one.
+    pub fn protocol(&self) -> Option<&Protocol> {
+        match self {
+            WidgetSourceData::Image(_, proto) | WidgetSourceData::HeaderImage(_, proto, _) => {
+                Some(proto)
+            }
+            WidgetSourceData::AnimatedImage(_, animation) => Some(animation.protocol()),
+            WidgetSourceData::BrokenImage(..)
+            | WidgetSourceData::Line(..)
+            | WidgetSourceData::Header(..) => None,
+        }
     }
 
     #[expect(clippy::string_slice)] // Regex byte ranges are guaranteed to fall between characters.
-    fn regex_to_searchmatch(line_string: &str) -> impl Fn(Match<'_>) -> LineExtra {
-        |m: Match| {
+    fn regex_to_searchmatch(line_string: &str, group: usize) -> impl Fn(Match<'_>) -> LineExtra {
+        move |m: Match| {
             // Convert from byte positions to character positions, with unicode_width.
             let start = line_string[..m.start()].width();
             let end = line_string[..m.end()].width();
-            LineExtra::SearchMatch(start, end, m.as_str().to_owned())
+            LineExtra::SearchMatch(start, end, m.as_str().to_owned(), group)
         }
     }
 }
@@ -368,9 +484,17 @@ impl PartialEq for WidgetSourceData<'_> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Self::Image(l0, l1), Self::Image(r0, r1)) => l0 == r0 && l1.type_id() == r1.type_id(),
+            (Self::AnimatedImage(l0, l1), Self::AnimatedImage(r0, r1)) => {
+                l0 == r0 && l1.frames.len() == r1.frames.len()
+            }
             (Self::BrokenImage(l0, l1), Self::BrokenImage(r0, r1)) => l0 == r0 && l1 == r1,
             (Self::Line(l0, l1), Self::Line(r0, r1)) => l0 == r0 && l1 == r1,
-            (Self::Header(l0, l1), Self::Header(r0, r1)) => l0 == r0 && l1 == r1,
+            (Self::Header(l0, l1, l2), Self::Header(r0, r1, r2)) => {
+                l0 == r0 && l1 == r1 && l2 == r2
+            }
+            (Self::HeaderImage(l0, l1, l2), Self::HeaderImage(r0, r1, r2)) => {
+                l0 == r0 && l1.type_id() == r1.type_id() && l2 == r2
+            }
             _ => false,
         }
     }
@@ -380,6 +504,10 @@ impl Debug for WidgetSourceData<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Image(url, _) => f.debug_tuple(format!("Image({url})").as_str()).finish(),
+            Self::AnimatedImage(url, animation) => f
+                .debug_tuple(format!("AnimatedImage({url})").as_str())
+                .field(&animation.frames.len())
+                .finish(),
             Self::BrokenImage(url, _) => f
                 .debug_tuple(format!("BrokenImage({url})").as_str())
                 .finish(),
@@ -391,7 +519,8 @@ impl Debug for WidgetSourceData<'_> {
                 }
                 tuple.finish()
             }
-            Self::Header(text, tier) => f.debug_tuple("Header").field(text).field(tier).finish(),
+            Self::Header(text, tier, _) => f.debug_tuple("Header").field(text).field(tier).finish(),
+            Self::HeaderImage(text, _, _) => f.debug_tuple("HeaderImage").field(text).finish(),
         }
     }
 }
@@ -400,9 +529,15 @@ impl Display for WidgetSourceData<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Image(url, protocol) => write!(f, "Image({url}, {:?})", protocol.type_id()),
+            Self::AnimatedImage(url, animation) => {
+                write!(f, "AnimatedImage({url}, {} frames)", animation.frames.len())
+            }
             Self::BrokenImage(url, _) => write!(f, "BrokenImage({url})"),
             Self::Line(line, extra) => write!(f, "Line({}, {})", line, extra.len()),
-            Self::Header(text, tier) => write!(f, "Header({text}, {tier})"),
+            Self::Header(text, tier, _) => write!(f, "Header({text}, {tier})"),
+            Self::HeaderImage(text, protocol, _) => {
+                write!(f, "HeaderImage({text}, {:?})", protocol.type_id())
+            }
         }
     }
 }
@@ -416,8 +551,8 @@ impl<'a> WidgetSource<'a> {
         }
     }
 
-    pub fn add_search(&mut self, re: &Option<Regex>) {
-        self.data.add_search(re);
+    pub fn add_search(&mut self, res: &[Option<Regex>]) {
+        self.data.add_search(res);
     }
 }
 
@@ -426,19 +561,197 @@ impl Display for WidgetSource<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.data {
             WidgetSourceData::Image(_, _) => write!(f, "<image>"),
+            WidgetSourceData::AnimatedImage(_, _) => write!(f, "<animated-image>"),
             WidgetSourceData::BrokenImage(_, _) => write!(f, "<broken-image>"),
             WidgetSourceData::Line(line, _) => Display::fmt(&line, f),
-            WidgetSourceData::Header(text, tier) => {
+            WidgetSourceData::Header(text, tier, _) => {
                 write!(f, "{} {}", "#".repeat(*tier as usize), text)
             }
+            WidgetSourceData::HeaderImage(_, _, _) => write!(f, "<header-image>"),
         }
     }
 }
 
+// Like browsers, frames with a delay under 20ms are shown for 100ms.
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+// Animations with more frames are cut off, every frame is a protocol in memory.
+const MAX_ANIMATION_FRAMES: usize = 200;
+
+// The frames of an animated image with their delays, and which one is shown.
+pub struct Animation {
+    frames: Vec<(Protocol, Duration)>,
+    current: usize,
+    // How long the current frame has been shown.
+    elapsed: Duration,
+}
+
+impl Animation {
+    // Expects at least one frame.
+    fn new(frames: Vec<(Protocol, Duration)>) -> Animation {
+        let frames = frames
+            .into_iter()
+            .map(|(proto, delay)| {
+                let delay = if delay < MIN_FRAME_DELAY {
+                    DEFAULT_FRAME_DELAY
+                } else {
+                    delay
+                };
+                (proto, delay)
+            })
+            .collect();
+        Animation {
+            frames,
+            current: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn protocol(&self) -> &Protocol {
+        &self.frames[self.current].0
+    }
+
+    // Let `elapsed` pass, and return whether another frame is shown.
+    pub fn advance(&mut self, elapsed: Duration) -> bool {
+        let cycle: Duration = self.frames.iter().map(|(_, delay)| *delay).sum();
+        if cycle.is_zero() {
+            return false;
+        }
+        let before = self.current;
+        // Whole cycles make no difference, and may be many after a long pause.
+        let elapsed = Duration::from_nanos(
+            u64::try_from(elapsed.as_nanos() % cycle.as_nanos()).unwrap_or_default(),
+        );
+        self.elapsed += elapsed;
+        while let Some((_, delay)) = self.frames.get(self.current)
+            && self.elapsed >= *delay
+        {
+            self.elapsed -= *delay;
+            self.current = (self.current + 1) % self.frames.len();
+        }
+        self.current != before
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum LineExtra {
     Link(String, u16, u16),
-    SearchMatch(usize, usize, String),
+    // Start, end, text, and the group: 0 for the current needle, 1 and up for kept highlights.
+    SearchMatch(usize, usize, String, usize),
+    // An inline image, rendered as a thumbnail over the blank columns.
+    Thumbnail(String, u16, u16),
+    // A task list item, whether it is checked, and the byte offset of its `[ ]` in the file.
+    Task(bool, usize),
+}
+
+// Columns of an inline image thumbnail.
+pub const THUMBNAIL_WIDTH: u16 = 2;
+
+// Append each link's url after the link text, shortening the urls to not overflow `width`. The
+// extras are returned with their columns shifted past the inserted urls.
+pub fn with_link_urls<'a>(
+    line: &Line<'a>,
+    extras: &[LineExtra],
+    width: u16,
+) -> (Line<'a>, Vec<LineExtra>) {
+    let mut ends: Vec<(usize, &str)> = extras
+        .iter()
+        .filter_map(|extra| match extra {
+            LineExtra::Link(url, _, end) => Some((*end as usize, url.as_str())),
+            LineExtra::SearchMatch(..) | LineExtra::Thumbnail(..) | LineExtra::Task(..) => None,
+        })
+        .collect();
+    if ends.is_empty() {
+        return (line.clone(), extras.to_vec());
+    }
+    ends.sort_by_key(|(end, _)| *end);
+
+    let mut spare = (width as usize).saturating_sub(line.width());
+    let suffixes: Vec<(usize, String)> = ends
+        .into_iter()
+        .filter_map(|(end, url)| Some((end, link_url_suffix(url, &mut spare)?)))
+        .collect();
+    // The width of the urls inserted before `column`.
+    let shift = |column: usize| -> usize {
+        suffixes
+            .iter()
+            .filter(|(end, _)| *end <= column)
+            .map(|(_, suffix)| suffix.width())
+            .sum()
+    };
+    let extras = extras
+        .iter()
+        .map(|extra| match extra {
+            LineExtra::Link(url, start, end) => {
+                let by = shift(*start as usize) as u16;
+                LineExtra::Link(url.clone(), start + by, end + by)
+            }
+            LineExtra::Thumbnail(url, start, end) => {
+                let by = shift(*start as usize) as u16;
+                LineExtra::Thumbnail(url.clone(), start + by, end + by)
+            }
+            LineExtra::SearchMatch(start, end, text, group) => {
+                let by = shift(*start);
+                LineExtra::SearchMatch(start + by, end + by, text.clone(), *group)
+            }
+            LineExtra::Task(..) => extra.clone(),
+        })
+        .collect();
+    let mut suffixes = suffixes.into_iter().peekable();
+
+    let mut spans = Vec::with_capacity(line.spans.len());
+    let mut column = 0;
+    for span in &line.spans {
+        let mut content = String::new();
+        for c in span.content.chars() {
+            let char_width = c.width().unwrap_or_default();
+            // Combining marks belong to the character before them.
+            while char_width > 0
+                && let Some((end, _)) = suffixes.peek()
+                && *end <= column
+                && let Some((_, suffix)) = suffixes.next()
+            {
+                spans.push(Span::styled(std::mem::take(&mut content), span.style));
+                spans.push(Span::from(suffix).dark_gray());
+            }
+            content.push(c);
+            column += char_width;
+        }
+        spans.push(Span::styled(content, span.style));
+    }
+    spans.extend(suffixes.map(|(_, suffix)| Span::from(suffix).dark_gray()));
+    spans.retain(|span| !span.content.is_empty());
+
+    let mut line = line.clone();
+    line.spans = spans;
+    (line, extras)
+}
+
+// The ` [url]` suffix, truncated with an ellipsis if it doesn't fit in `spare`.
+fn link_url_suffix(url: &str, spare: &mut usize) -> Option<String> {
+    let suffix = format!(" [{url}]");
+    let suffix_width = suffix.width();
+    if suffix_width <= *spare {
+        *spare -= suffix_width;
+        return Some(suffix);
+    }
+    if *spare < " [x…]".width() {
+        return None;
+    }
+    let mut truncated = String::from(" [");
+    let mut used = " […]".width();
+    for c in url.chars() {
+        let char_width = c.width().unwrap_or_default();
+        if used + char_width > *spare {
+            break;
+        }
+        truncated.push(c);
+        used += char_width;
+    }
+    truncated.push_str("…]");
+    *spare -= used;
+    Some(truncated)
 }
 
 /// Layout/shape and render `text` into a list of [`DynamicImage`] with a given terminal width.
@@ -448,6 +761,7 @@ pub fn header_images(
     width: u16,
     text: String,
     tier: u8,
+    options: &ParseOptions,
     deep_fry_meme: bool,
 ) -> Result<Vec<(String, DynamicImage)>, Error> {
     let bg = bg.unwrap_or_default(); // Default is transparent (black, but that's irrelevant).
@@ -455,13 +769,13 @@ pub fn header_images(
     const HEADER_ROW_COUNT: u16 = 2;
     let (font_width, font_height) = font_renderer.font_size;
 
-    let tier_scale = f32::from(12 - tier) / 12.0_f32;
-
     let line_height = f32::from(font_height * HEADER_ROW_COUNT);
-    let font_size = line_height * tier_scale;
-    let metrics = Metrics::new(font_size, line_height);
+    let tier_metrics = |tier: u8| {
+        let tier_scale = f32::from(12 - tier) / 12.0_f32;
+        Metrics::new(line_height * tier_scale, line_height)
+    };
 
-    let mut buffer = Buffer::new(&mut font_renderer.font_system, metrics);
+    let mut buffer = Buffer::new(&mut font_renderer.font_system, tier_metrics(tier));
 
     let mut attrs = Attrs::new();
     attrs = attrs.family(Family::Name(&font_renderer.font_name));
@@ -472,24 +786,50 @@ pub fn header_images(
         Some(f32::from(max_width)),
         None,
     );
-    buffer.set_text(
+    let spans: Vec<(String, Attrs)> = header_spans(&text)
+        .into_iter()
+        .map(|span| {
+            let mut span_attrs = attrs.clone();
+            if options.header_emphasis && span.bold {
+                span_attrs = span_attrs.weight(Weight::BOLD);
+            }
+            if options.header_emphasis && span.italic {
+                span_attrs = span_attrs.style(Style::Italic);
+            }
+            let text = if deep_fry_meme {
+                span.text.replace('a', "🤣")
+            } else {
+                span.text
+            };
+            (text, span_attrs)
+        })
+        .collect();
+    buffer.set_rich_text(
         &mut font_renderer.font_system,
-        &(if deep_fry_meme {
-            text.replace('a', "🤣")
-        } else {
-            text
-        }),
+        spans
+            .iter()
+            .map(|(text, attrs)| (text.as_str(), attrs.clone())),
         &attrs,
         Shaping::Advanced,
+        None,
     );
     buffer.shape_until_scroll(&mut font_renderer.font_system, false);
 
+    // Demote to smaller tiers, and cut off if even the smallest is too long.
+    let max_lines = options.max_header_lines.unwrap_or(usize::MAX);
+    let mut tier = tier;
+    while tier < 6 && buffer.layout_runs().count() > max_lines {
+        tier += 1;
+        buffer.set_metrics(&mut font_renderer.font_system, tier_metrics(tier));
+        buffer.shape_until_scroll(&mut font_renderer.font_system, false);
+    }
+
     // Make one image per shaped line.
-    let run_count = buffer.layout_runs().collect::<Vec<_>>().len();
+    let run_count = buffer.layout_runs().count().min(max_lines);
     let mut dyn_imgs = Vec::with_capacity(run_count);
     let img_height = u32::from(font_height * 2);
     let img_width = u32::from(width * font_width);
-    for layout_run in buffer.layout_runs() {
+    for layout_run in buffer.layout_runs().take(run_count) {
         let img: RgbaImage = RgbaImage::from_pixel(img_width, img_height, bg.into());
         let dyn_img = DynamicImage::ImageRgba8(img);
         dyn_imgs.push((layout_run.text.into(), dyn_img));
@@ -561,29 +901,82 @@ pub fn header_sources<'a>(
         sources.push(WidgetSource {
             id,
             height: HEADER_ROW_COUNT,
-            data: WidgetSourceData::Image(text, proto),
+            data: WidgetSourceData::HeaderImage(text, proto, Vec::new()),
         });
     }
 
     Ok(sources)
 }
 
-#[expect(clippy::too_many_arguments)]
-pub async fn image_source<'a>(
-    picker: &Arc<Picker>,
-    max_height: u16,
-    width: u16,
-    basepath: &Option<PathBuf>,
-    client: Arc<RwLock<Client>>,
-    id: SourceID,
-    url: &str,
-    deep_fry_meme: bool,
-) -> Result<WidgetSource<'a>, Error> {
-    enum ImageSource {
-        Bytes(Vec<u8>, ImageFormat),
-        Path(String),
+// A remote image's bytes, kept to open the image in an external viewer, and to show it again
+// after a resize or protocol change without downloading it again.
+#[derive(Clone)]
+pub struct Download {
+    // The url that was fetched, which is a resized variant with `image_resize_url_template`.
+    pub url: String,
+    pub bytes: Vec<u8>,
+    pub format: ImageFormat,
+}
+
+pub fn is_remote_url(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+// Resolve a relative local image url against the markdown file's directory, like browsers do.
+pub fn image_path(basepath: &Option<PathBuf>, url: &str) -> String {
+    match basepath {
+        Some(basepath) if Path::new(url).is_relative() => basepath
+            .join(url)
+            .to_str()
+            .map(String::from)
+            .unwrap_or(url.to_owned()),
+        _ => url.to_owned(),
     }
-    let image_source = if url.starts_with("https://") || url.starts_with("http://") {
+}
+
+// The client for remote images. A redirect can lead anywhere, including trackers, so it can be
+// disabled, and then shows a broken image, see `Config::image_follow_redirects`.
+pub fn http_client(follow_redirects: bool, max_redirects: usize) -> Result<Client, Error> {
+    let policy = if follow_redirects {
+        Policy::limited(max_redirects)
+    } else {
+        Policy::none()
+    };
+    Ok(Client::builder().redirect(policy).build()?)
+}
+
+enum ImageSource {
+    Bytes(Vec<u8>, ImageFormat),
+    Path(String),
+}
+
+impl ImageSource {
+    async fn fetch(
+        basepath: &Option<PathBuf>,
+        client: Arc<RwLock<Client>>,
+        id: SourceID,
+        url: &str,
+        cache: Option<ImageCache>,
+        max_data_bytes: usize,
+    ) -> Result<ImageSource, Error> {
+        if data_uri::is_data_uri(url) {
+            return match data_uri::decode(url, max_data_bytes) {
+                Some((bytes, format)) => Ok(ImageSource::Bytes(bytes, format)),
+                None => Err(Error::UnknownImage(id, url.to_owned())),
+            };
+        }
+        if !is_remote_url(url) {
+            return Ok(ImageSource::Path(image_path(basepath, url)));
+        }
+        // On the blocking side, and before taking the client lock.
+        if let Some(cache) = cache.clone() {
+            let cache_url = url.to_owned();
+            if let Some((bytes, format)) =
+                tokio::task::spawn_blocking(move || cache.load(&cache_url)).await?
+            {
+                return Ok(ImageSource::Bytes(bytes, format));
+            }
+        }
         let mut headers = HeaderMap::new();
         headers.insert(ACCEPT, HeaderValue::from_static("image/png,image/jpg")); // or "image/jpeg"
         let client = client.read().await;
@@ -604,53 +997,299 @@ pub async fn image_source<'a>(
             _ => Err(Error::UnknownImage(id, url.to_owned())),
         }?;
 
-        ImageSource::Bytes(response.bytes().await?.to_vec(), format)
-    } else {
-        let path: String = match basepath {
-            Some(basepath) if url.starts_with("./") => basepath
-                .join(url)
-                .to_str()
-                .map(String::from)
-                .unwrap_or(url.to_owned()),
-            _ => url.to_owned(),
-        };
-        ImageSource::Path(path)
+        let bytes = response.bytes().await?.to_vec();
+        if let Some(cache) = cache {
+            let cache_url = url.to_owned();
+            let cache_bytes = bytes.clone();
+            tokio::task::spawn_blocking(move || cache.store(&cache_url, &cache_bytes, format))
+                .await?;
+        }
+        Ok(ImageSource::Bytes(bytes, format))
+    }
+
+    // Only the header is read first, to not decode images that are far too big, see
+    // `check_pixels`.
+    // Blocking.
+    fn decode(&self, max_pixels: u64) -> Result<DynamicImage, Error> {
+        Ok(match self {
+            ImageSource::Bytes(bytes, format) => {
+                let reader = || ImageReader::with_format(Cursor::new(bytes), *format);
+                check_pixels(reader().into_dimensions()?, max_pixels)?;
+                reader().decode()?
+            }
+            ImageSource::Path(path) => {
+                check_pixels(ImageReader::open(path)?.into_dimensions()?, max_pixels)?;
+                ImageReader::open(path)?.decode()?
+            }
+        })
+    }
+
+    // The bytes fetched from `url`, to be kept by the worker. Local files are not kept.
+    fn into_download(self, url: String) -> Option<Download> {
+        match self {
+            ImageSource::Bytes(bytes, format) => Some(Download { url, bytes, format }),
+            ImageSource::Path(_) => None,
+        }
+    }
+
+    // The frames and delays of an animated GIF, PNG or WebP, or `None` for still images.
+    // Every frame goes through `each` as soon as it is decoded, so that only one full frame is in
+    // memory at a time.
+    // Blocking.
+    fn animation_frames<T, F: FnMut(DynamicImage) -> Result<T, Error>>(
+        &self,
+        max_pixels: u64,
+        each: F,
+    ) -> Result<Option<Vec<(T, Duration)>>, Error> {
+        match self {
+            ImageSource::Bytes(bytes, format) => {
+                decode_frames(Cursor::new(bytes), *format, max_pixels, each)
+            }
+            ImageSource::Path(path) => match ImageFormat::from_path(path) {
+                Ok(format) => {
+                    decode_frames(BufReader::new(File::open(path)?), format, max_pixels, each)
+                }
+                Err(_) => Ok(None),
+            },
+        }
+    }
+}
+
+fn decode_frames<R: BufRead + Seek, T, F: FnMut(DynamicImage) -> Result<T, Error>>(
+    reader: R,
+    format: ImageFormat,
+    max_pixels: u64,
+    mut each: F,
+) -> Result<Option<Vec<(T, Duration)>>, Error> {
+    let frames = match format {
+        ImageFormat::Gif => {
+            let decoder = GifDecoder::new(reader)?;
+            check_pixels(decoder.dimensions(), max_pixels)?;
+            decoder.into_frames()
+        }
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(reader)?;
+            if !decoder.is_apng()? {
+                return Ok(None);
+            }
+            check_pixels(decoder.dimensions(), max_pixels)?;
+            decoder.apng()?.into_frames()
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(reader)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            check_pixels(decoder.dimensions(), max_pixels)?;
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    let frames = frames
+        .take(MAX_ANIMATION_FRAMES)
+        .map(|frame| {
+            let frame = frame?;
+            let delay = Duration::from(frame.delay());
+            Ok((each(DynamicImage::ImageRgba8(frame.into_buffer()))?, delay))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok((frames.len() > 1).then_some(frames))
+}
+
+#[expect(clippy::too_many_arguments)]
+pub async fn image_source<'a>(
+    picker: &Arc<Picker>,
+    max_height: u16,
+    // Half again the `max_height` if `None`.
+    max_width: Option<ImageWidth>,
+    width: u16,
+    basepath: &Option<PathBuf>,
+    client: Arc<RwLock<Client>>,
+    id: SourceID,
+    url: &str,
+    // The url to fetch, see `ParseOptions::resized_image_url`.
+    fetch_url: &str,
+    // Kept from an earlier load of `fetch_url`, which is then not fetched again.
+    downloaded: Option<Download>,
+    deep_fry_meme: bool,
+    background: Option<BgColor>,
+    hero: bool,
+    max_pixels: u64,
+    cache: Option<ImageCache>,
+    // Keep all frames of animated images, not only the first.
+    animate: bool,
+    max_data_bytes: usize,
+) -> Result<(WidgetSource<'a>, Option<Download>), Error> {
+    let image_source = match downloaded {
+        Some(download) if download.url == fetch_url => {
+            ImageSource::Bytes(download.bytes, download.format)
+        }
+        _ => {
+            ImageSource::fetch(
+                basepath,
+                client,
+                id,
+                fetch_url,
+                cache.clone(),
+                max_data_bytes,
+            )
+            .await?
+        }
     };
 
     // Now do all the blocking stuff
     let picker = picker.clone();
     let url = String::from(url);
+    let fetch_url = String::from(fetch_url);
     let source = tokio::task::spawn_blocking(move || {
-        let mut dyn_img = match image_source {
-            ImageSource::Bytes(bytes, format) => {
-                ImageReader::with_format(std::io::Cursor::new(bytes), format).decode()?
-            }
-            ImageSource::Path(path) => ImageReader::open(path)?.decode()?,
+        let (max_width, max_height) = if hero {
+            (width, max_height.saturating_mul(2))
+        } else {
+            let max_width = match max_width {
+                Some(max_width) => max_width.columns(width),
+                None => max_height * 3 / 2,
+            };
+            (max_width.min(width), max_height)
         };
+        let new_protocol = |mut dyn_img: DynamicImage| {
+            dyn_img = limit_pixels(dyn_img, max_pixels);
 
-        if deep_fry_meme {
-            dyn_img = deep_fry(dyn_img);
-        }
+            if let Some(background) = background {
+                dyn_img = composite(dyn_img, background);
+            }
 
-        let max_width: u16 = (max_height * 3 / 2).min(width);
+            if deep_fry_meme {
+                dyn_img = deep_fry(dyn_img);
+            }
 
-        let proto = picker.new_protocol(
-            dyn_img,
-            Rect::new(0, 0, max_width, max_height),
-            Resize::Fit(None),
-        )?;
+            picker.new_protocol(
+                dyn_img,
+                Rect::new(0, 0, max_width, max_height),
+                Resize::Fit(None),
+            )
+        };
 
-        let height = proto.area().height;
-        Ok::<WidgetSource<'_>, Error>(WidgetSource {
-            id,
-            height,
-            data: WidgetSourceData::Image(url, proto),
-        })
+        // A broken animation still shows its first frame.
+        let frames = if animate {
+            image_source
+                .animation_frames(max_pixels, |frame| Ok(new_protocol(frame)?))
+                .unwrap_or_else(|err| {
+                    log::warn!("animation frames of {url}: {err}");
+                    None
+                })
+        } else {
+            None
+        };
+
+        let (height, data, download) = match frames {
+            Some(frames) => {
+                let animation = Animation::new(frames);
+                (
+                    animation.protocol().area().height,
+                    WidgetSourceData::AnimatedImage(url, animation),
+                    image_source.into_download(fetch_url),
+                )
+            }
+            None => {
+                let dyn_img = image_source
+                    .decode(max_pixels)
+                    .inspect_err(|_| remove_cached(cache.as_ref(), &fetch_url))?;
+                let proto = new_protocol(dyn_img)?;
+                (
+                    proto.area().height,
+                    WidgetSourceData::Image(url, proto),
+                    image_source.into_download(fetch_url),
+                )
+            }
+        };
+        Ok::<_, Error>((WidgetSource { id, height, data }, download))
     })
     .await??;
     Ok(source)
 }
 
+// A download that does not decode would otherwise be loaded from the cache again and again.
+fn remove_cached(cache: Option<&ImageCache>, url: &str) {
+    if let Some(cache) = cache {
+        cache.remove(url);
+    }
+}
+
+// A one row protocol of an inline image.
+pub async fn thumbnail_protocol(
+    picker: &Arc<Picker>,
+    basepath: &Option<PathBuf>,
+    client: Arc<RwLock<Client>>,
+    id: SourceID,
+    url: &str,
+    background: Option<BgColor>,
+    max_pixels: u64,
+    cache: Option<ImageCache>,
+    max_data_bytes: usize,
+) -> Result<Protocol, Error> {
+    let image_source =
+        ImageSource::fetch(basepath, client, id, url, cache.clone(), max_data_bytes).await?;
+    let picker = picker.clone();
+    let url = url.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let mut dyn_img = image_source
+            .decode(max_pixels)
+            .inspect_err(|_| remove_cached(cache.as_ref(), &url))?;
+        if let Some(background) = background {
+            dyn_img = composite(dyn_img, background);
+        }
+        Ok(picker.new_protocol(
+            dyn_img,
+            Rect::new(0, 0, THUMBNAIL_WIDTH, 1),
+            Resize::Fit(None),
+        )?)
+    })
+    .await?
+}
+
+// Blend the image onto a solid background, so that transparency does not depend on the terminal.
+fn composite(dyn_img: DynamicImage, background: BgColor) -> DynamicImage {
+    if !dyn_img.color().has_alpha() {
+        return dyn_img;
+    }
+    let mut img = dyn_img.into_rgba8();
+    for pixel in img.pixels_mut() {
+        let mut blended: Rgba<u8> = background.into();
+        blended.blend(pixel);
+        *pixel = blended;
+    }
+    DynamicImage::ImageRgba8(img)
+}
+
+// Images this many times over `max_pixels` are refused before decoding, which alone would take too
+// much memory and time, instead of being downscaled by `limit_pixels`.
+const MAX_DECODE_FACTOR: u64 = 4;
+
+fn check_pixels((width, height): (u32, u32), max_pixels: u64) -> Result<(), Error> {
+    if u64::from(width) * u64::from(height) > max_pixels.saturating_mul(MAX_DECODE_FACTOR) {
+        return Err(Error::ImageTooBig(width, height));
+    }
+    Ok(())
+}
+
+// Downscale images with more than `max_pixels`, keeping the aspect ratio, see
+// `Config::max_image_pixels`.
+fn limit_pixels(dyn_img: DynamicImage, max_pixels: u64) -> DynamicImage {
+    let pixels = u64::from(dyn_img.width()) * u64::from(dyn_img.height());
+    if pixels <= max_pixels {
+        return dyn_img;
+    }
+    let scale = (max_pixels as f64 / pixels as f64).sqrt();
+    let width = ((f64::from(dyn_img.width()) * scale) as u32).max(1);
+    let height = ((f64::from(dyn_img.height()) * scale) as u32).max(1);
+    log::debug!(
+        "downscaling {}x{} image to {width}x{height}",
+        dyn_img.width(),
+        dyn_img.height()
+    );
+    dyn_img.resize(width, height, imageops::FilterType::Triangle)
+}
+
 fn deep_fry(mut dyn_img: DynamicImage) -> DynamicImage {
     let width = dyn_img.width();
     let height = dyn_img.height();
@@ -769,9 +1408,123 @@ impl Widget for BigText<'_> {
 #[cfg(test)]
 mod tests {
 
+    use std::{
+        fs,
+        io::{BufRead as _, BufReader, Cursor, Write as _},
+        net::TcpListener,
+        path::PathBuf,
+        sync::Arc,
+        thread,
+        time::Duration,
+    };
+
+    use image::{Delay, DynamicImage, Frame, Rgba, RgbaImage, codecs::gif::GifEncoder};
+    use ratatui::layout::Rect;
+    use ratatui_image::{Resize, picker::Picker};
     use regex::Regex;
+    use tokio::sync::RwLock;
+
+    use crate::{
+        config::ImageWidth,
+        error::Error,
+        setup::BgColor,
+        widget_sources::{
+            Animation, DEFAULT_FRAME_DELAY, FindTarget, ImageSource, WidgetSources, composite,
+            http_client, image_path, image_source as load_image, limit_pixels, with_link_urls,
+        },
+        *,
+    };
 
-    use crate::{widget_sources::WidgetSources, *};
+    fn image_source<'a>(id: SourceID, url: &str) -> WidgetSource<'a> {
+        let proto = Picker::halfblocks()
+            .new_protocol(
+                DynamicImage::new_rgba8(8, 8),
+                Rect::new(0, 0, 4, 4),
+                Resize::Fit(None),
+            )
+            .expect("new_protocol");
+        WidgetSource {
+            id,
+            height: proto.area().height,
+            data: WidgetSourceData::Image(url.to_owned(), proto),
+        }
+    }
+
+    fn line_source<'a>(id: SourceID, text: &'static str) -> WidgetSource<'a> {
+        WidgetSource {
+            id,
+            height: 1,
+            data: WidgetSourceData::Line(Line::from(text), Vec::new()),
+        }
+    }
+
+    // Mimic what `Model::process_events` does on reload, for `ParseImage`.
+    fn reparse_image(ws: &mut WidgetSources, id: SourceID, url: &str) {
+        let mut existing = ws.replace(id, url).expect("existing image");
+        existing.id = id;
+        ws.update(vec![existing]);
+    }
+
+    #[test]
+    fn link_urls_inline() {
+        let line = Line::from("see link here");
+        let extras = vec![LineExtra::Link(String::from("http://a.b"), 4, 8)];
+        assert_eq!(
+            with_link_urls(&line, &extras, 80).0.to_string(),
+            "see link [http://a.b] here"
+        );
+    }
+
+    #[test]
+    fn link_urls_inline_wide() {
+        let line = Line::from("見て 링크 e\u{301} here");
+        let extras = vec![
+            LineExtra::Link(String::from("http://a"), 5, 9),
+            LineExtra::Link(String::from("http://b"), 10, 11),
+        ];
+        assert_eq!(
+            with_link_urls(&line, &extras, 80).0.to_string(),
+            "見て 링크 [http://a] e\u{301} [http://b] here",
+            "after the combining mark"
+        );
+    }
+
+    #[test]
+    fn link_urls_shift_extras() {
+        let line = Line::from("a b  c");
+        let extras = vec![
+            LineExtra::Link(String::from("http://a"), 0, 1),
+            LineExtra::Link(String::from("http://b"), 2, 3),
+            LineExtra::Thumbnail(String::from("c.png"), 3, 5),
+            LineExtra::Task(false, 0),
+        ];
+        let (line, extras) = with_link_urls(&line, &extras, 80);
+        assert_eq!(line.to_string(), "a [http://a] b [http://b]  c");
+        assert_eq!(
+            extras,
+            vec![
+                LineExtra::Link(String::from("http://a"), 0, 1),
+                LineExtra::Link(String::from("http://b"), 13, 14),
+                LineExtra::Thumbnail(String::from("c.png"), 25, 27),
+                LineExtra::Task(false, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn link_urls_inline_truncated() {
+        let line = Line::from("see link here");
+        let extras = vec![LineExtra::Link(String::from("http://a.b"), 4, 8)];
+        assert_eq!(
+            with_link_urls(&line, &extras, 20).0.to_string(),
+            "see link [htt…] here"
+        );
+        assert_eq!(
+            with_link_urls(&line, &extras, 15).0.to_string(),
+            "see link here",
+            "no room for the url"
+        );
+    }
 
     #[test]
     fn widgestsources_update() {
@@ -796,24 +1549,24 @@ mod tests {
             WidgetSource {
                 id: 1,
                 height: 2,
-                data: WidgetSourceData::Header(String::from("headerline1"), 1),
+                data: WidgetSourceData::Header(String::from("headerline1"), 1, Vec::new()),
             },
             WidgetSource {
                 id: 1,
                 height: 2,
-                data: WidgetSourceData::Header(String::from("headerline2"), 1),
+                data: WidgetSourceData::Header(String::from("headerline2"), 1, Vec::new()),
             },
         ]);
         assert_eq!(ws.sources.len(), 4);
         assert_eq!(0, ws.sources[0].id,);
         assert_eq!(1, ws.sources[1].id,);
         assert_eq!(
-            WidgetSourceData::Header(String::from("headerline1"), 1),
+            WidgetSourceData::Header(String::from("headerline1"), 1, Vec::new()),
             ws.sources[1].data
         );
         assert_eq!(1, ws.sources[2].id,);
         assert_eq!(
-            WidgetSourceData::Header(String::from("headerline2"), 1),
+            WidgetSourceData::Header(String::from("headerline2"), 1, Vec::new()),
             ws.sources[2].data
         );
         assert_eq!(2, ws.sources[3].id,);
@@ -822,36 +1575,123 @@ mod tests {
             WidgetSource {
                 id: 1,
                 height: 2,
-                data: WidgetSourceData::Header(String::from("headerline3"), 1),
+                data: WidgetSourceData::Header(String::from("headerline3"), 1, Vec::new()),
             },
             WidgetSource {
                 id: 1,
                 height: 2,
-                data: WidgetSourceData::Header(String::from("headerline4"), 1),
+                data: WidgetSourceData::Header(String::from("headerline4"), 1, Vec::new()),
             },
         ]);
         assert_eq!(ws.sources.len(), 4);
         assert_eq!(0, ws.sources[0].id,);
         assert_eq!(1, ws.sources[1].id,);
         assert_eq!(
-            WidgetSourceData::Header(String::from("headerline3"), 1),
+            WidgetSourceData::Header(String::from("headerline3"), 1, Vec::new()),
             ws.sources[1].data
         );
         assert_eq!(1, ws.sources[2].id,);
         assert_eq!(
-            WidgetSourceData::Header(String::from("headerline4"), 1),
+            WidgetSourceData::Header(String::from("headerline4"), 1, Vec::new()),
             ws.sources[2].data
         );
         assert_eq!(2, ws.sources[3].id,);
     }
 
+    #[test]
+    fn replace_reordered_identical_images() {
+        let mut ws = WidgetSources::default();
+        ws.push(line_source(0, "first"));
+        ws.push(image_source(1, "a.png"));
+        ws.push(line_source(2, "second"));
+        ws.push(image_source(3, "a.png"));
+        ws.push(image_source(4, "a.png"));
+
+        // The images move to the top, text to the bottom.
+        reparse_image(&mut ws, 0, "a.png");
+        reparse_image(&mut ws, 1, "a.png");
+        reparse_image(&mut ws, 2, "a.png");
+        ws.update(vec![line_source(3, "first")]);
+        ws.update(vec![line_source(4, "second")]);
+        ws.trim_last_source(Some(4));
+
+        assert_eq!(ws.sources.len(), 5);
+        for (i, source) in ws.sources.iter().enumerate() {
+            assert_eq!(i, source.id, "ids in order");
+            if i < 3 {
+                assert!(
+                    matches!(&source.data, WidgetSourceData::Image(url, _) if url == "a.png"),
+                    "#{i} should be an image, is {:?}",
+                    source.data
+                );
+            } else {
+                assert!(
+                    matches!(source.data, WidgetSourceData::Line(_, _)),
+                    "#{i} should be a line, is {:?}",
+                    source.data
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn replace_prefers_nearest_image() {
+        let mut ws = WidgetSources::default();
+        ws.push(image_source(0, "a.png"));
+        ws.push(line_source(1, "text"));
+        ws.push(line_source(2, "text"));
+        ws.push(line_source(3, "text"));
+        ws.push(image_source(4, "a.png"));
+
+        // The first image gets overwritten, and the same url shows up on the next source.
+        ws.update(vec![line_source(0, "text")]);
+        reparse_image(&mut ws, 1, "a.png");
+
+        assert!(
+            matches!(ws.sources[1].data, WidgetSourceData::Image(_, _)),
+            "reused image"
+        );
+        assert!(
+            matches!(ws.sources[4].data, WidgetSourceData::Image(_, _)),
+            "far image should not have been taken, is {:?}",
+            ws.sources[4].data
+        );
+    }
+
+    #[test]
+    fn replace_animated_image() {
+        let mut ws = WidgetSources::default();
+        let image = image_source(0, "a.gif");
+        let WidgetSourceData::Image(_, proto) = image.data else {
+            unreachable!()
+        };
+        ws.push(WidgetSource {
+            id: 0,
+            height: image.height,
+            data: WidgetSourceData::AnimatedImage(
+                String::from("a.gif"),
+                Animation::new(vec![(proto, Duration::ZERO)]),
+            ),
+        });
+
+        // Moved down by a new line above it.
+        ws.update(vec![line_source(0, "text")]);
+        reparse_image(&mut ws, 1, "a.gif");
+
+        assert!(
+            matches!(&ws.sources[1].data, WidgetSourceData::AnimatedImage(url, _) if url == "a.gif"),
+            "reused animation, is {:?}",
+            ws.sources[1].data
+        );
+    }
+
     #[test]
     fn get_y() {
         let mut ws = WidgetSources::default();
         ws.push(WidgetSource {
             id: 1,
             height: 2,
-            data: WidgetSourceData::Header(String::from("one"), 1),
+            data: WidgetSourceData::Header(String::from("one"), 1, Vec::new()),
         });
         ws.push(WidgetSource {
             id: 2,
@@ -866,7 +1706,7 @@ mod tests {
         ws.push(WidgetSource {
             id: 4,
             height: 2,
-            data: WidgetSourceData::Header(String::from("one"), 1),
+            data: WidgetSourceData::Header(String::from("one"), 1, Vec::new()),
         });
         ws.push(WidgetSource {
             id: 5,
@@ -884,10 +1724,299 @@ mod tests {
     fn add_search_offset() {
         let line = Line::from(vec![Span::from("▐").magenta(), Span::from(" hi")]);
         let mut wsd = WidgetSourceData::Line(line, Vec::new());
-        wsd.add_search(&Regex::new("hi").ok());
+        wsd.add_search(&[Regex::new("hi").ok()]);
+        let WidgetSourceData::Line(_, extra) = wsd else {
+            panic!("Line");
+        };
+        assert_eq!(
+            extra[0],
+            LineExtra::SearchMatch(2, 4, String::from("hi"), 0)
+        );
+    }
+
+    #[test]
+    fn add_search_wide_and_combining() {
+        for (text, needle, start, end) in [
+            ("日本語 hi", "hi", 7, 9),
+            ("日本語", "本", 2, 4),
+            ("🦀 hi 🦀", "hi", 3, 5),
+            ("🦀 hi 🦀", "🦀", 0, 2),
+            ("cafe\u{301} hi", "hi", 6, 8),
+            ("cafe\u{301} hi", "cafe\u{301}", 0, 4),
+        ] {
+            let mut wsd = WidgetSourceData::Line(Line::from(text), Vec::new());
+            wsd.add_search(&[Regex::new(needle).ok()]);
+            let WidgetSourceData::Line(_, extra) = wsd else {
+                panic!("Line");
+            };
+            assert_eq!(
+                extra[0],
+                LineExtra::SearchMatch(start, end, String::from(needle), 0),
+                "{needle} in {text}"
+            );
+        }
+    }
+
+    #[test]
+    fn add_search_groups() {
+        let mut wsd = WidgetSourceData::Line(Line::from("one two one"), Vec::new());
+        wsd.add_search(&[None, Regex::new("one").ok(), Regex::new("two").ok()]);
         let WidgetSourceData::Line(_, extra) = wsd else {
             panic!("Line");
         };
-        assert_eq!(extra[0], LineExtra::SearchMatch(2, 4, String::from("hi")));
+        assert_eq!(
+            extra,
+            vec![
+                LineExtra::SearchMatch(0, 3, String::from("one"), 1),
+                LineExtra::SearchMatch(8, 11, String::from("one"), 1),
+                LineExtra::SearchMatch(4, 7, String::from("two"), 2),
+            ]
+        );
+        assert!(
+            !FindTarget::Search.matches(&extra[0]),
+            "only the current needle is found"
+        );
+    }
+
+    #[test]
+    fn composite_transparent_image() {
+        let mut img = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 0]));
+        img.put_pixel(1, 0, Rgba([255, 0, 0, 255]));
+        let composited = composite(
+            DynamicImage::ImageRgba8(img),
+            BgColor::from([255, 255, 255]),
+        );
+        let composited = composited.to_rgba8();
+        assert_eq!(composited.get_pixel(0, 0), &Rgba([255, 255, 255, 255]));
+        assert_eq!(composited.get_pixel(1, 0), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn limit_image_pixels() {
+        let img = DynamicImage::new_rgb8(4000, 2000);
+        let limited = limit_pixels(img, 1_000_000);
+        assert!(
+            u64::from(limited.width()) * u64::from(limited.height()) <= 1_000_000,
+            "not downscaled: {}x{}",
+            limited.width(),
+            limited.height()
+        );
+        assert_eq!((limited.width(), limited.height()), (1414, 707));
+
+        let img = DynamicImage::new_rgb8(100, 50);
+        let limited = limit_pixels(img, 1_000_000);
+        assert_eq!((limited.width(), limited.height()), (100, 50));
+    }
+
+    #[test]
+    fn refuse_huge_images() {
+        let mut png = Vec::new();
+        DynamicImage::new_rgb8(100, 100)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("write png");
+        let source = ImageSource::Bytes(png, image::ImageFormat::Png);
+        assert!(
+            matches!(source.decode(1000), Err(Error::ImageTooBig(..))),
+            "not decoded"
+        );
+        assert_eq!(source.decode(5000).expect("decode").width(), 100);
+    }
+
+    #[test]
+    fn animation_frames() {
+        let mut gif = Vec::new();
+        {
+            let frames = [Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255])].map(|color| {
+                Frame::from_parts(
+                    RgbaImage::from_pixel(4, 4, color),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(50, 1),
+                )
+            });
+            let mut encoder = GifEncoder::new(&mut gif);
+            encoder.encode_frames(frames).expect("encode gif");
+        }
+        let frames = ImageSource::Bytes(gif, image::ImageFormat::Gif)
+            .animation_frames(u64::MAX, Ok)
+            .expect("animation_frames")
+            .expect("animated");
+        let delays: Vec<Duration> = frames.iter().map(|(_, delay)| *delay).collect();
+        assert_eq!(delays, [Duration::from_millis(50); 2]);
+
+        let mut png = Vec::new();
+        DynamicImage::new_rgb8(1, 1)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("write png");
+        let frames = ImageSource::Bytes(png, image::ImageFormat::Png)
+            .animation_frames(u64::MAX, Ok)
+            .expect("animation_frames");
+        assert!(frames.is_none(), "a still png is no animation");
+    }
+
+    #[test]
+    fn animation_advance() {
+        let proto = || {
+            Picker::halfblocks()
+                .new_protocol(
+                    DynamicImage::new_rgba8(8, 8),
+                    Rect::new(0, 0, 4, 4),
+                    Resize::Fit(None),
+                )
+                .expect("new_protocol")
+        };
+        let mut animation = Animation::new(vec![
+            (proto(), Duration::from_millis(50)),
+            (proto(), Duration::ZERO),
+        ]);
+        assert_eq!(
+            animation.frames[1].1, DEFAULT_FRAME_DELAY,
+            "frames without delay are slowed down"
+        );
+        assert!(!animation.advance(Duration::from_millis(40)), "too early");
+        assert!(animation.advance(Duration::from_millis(10)), "next frame");
+        assert_eq!(animation.current, 1);
+        assert!(
+            animation.advance(Duration::from_millis(150 * 1000 + 100)),
+            "whole cycles are skipped"
+        );
+        assert_eq!(animation.current, 0);
+    }
+
+    #[tokio::test]
+    async fn max_image_width() {
+        let dir = std::env::temp_dir().join(format!("mdfried_widths_{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("create_dir_all");
+        DynamicImage::new_rgb8(20, 2000)
+            .save(dir.join("tall.png"))
+            .expect("save tall");
+        DynamicImage::new_rgb8(2000, 20)
+            .save(dir.join("wide.png"))
+            .expect("save wide");
+        let picker = Arc::new(Picker::halfblocks());
+        let client = Arc::new(RwLock::new(http_client(true, 10).expect("http_client")));
+        let area = async |name: &str| {
+            let path = dir.join(name).to_string_lossy().into_owned();
+            let (source, _) = load_image(
+                &picker,
+                10,
+                Some(ImageWidth::Columns(60)),
+                80,
+                &None,
+                client.clone(),
+                1,
+                &path,
+                &path,
+                None,
+                false,
+                None,
+                false,
+                u64::MAX,
+                None,
+                false,
+                0,
+            )
+            .await
+            .expect("image_source");
+            source.data.protocol().expect("protocol").area()
+        };
+
+        let tall = area("tall.png").await;
+        assert!(tall.height <= 10, "bounded by the height: {tall:?}");
+        let wide = area("wide.png").await;
+        assert!(
+            wide.width > 15 && wide.width <= 60,
+            "wider than half again the height: {wide:?}"
+        );
+        fs::remove_dir_all(dir).expect("remove_dir_all");
+    }
+
+    #[test]
+    fn relative_image_paths() {
+        let basepath = Some(PathBuf::from("/docs/guide"));
+        let resolve = |url| PathBuf::from(image_path(&basepath, url));
+        assert_eq!(resolve("./x.png"), PathBuf::from("/docs/guide/x.png"));
+        assert_eq!(
+            resolve("images/x.png"),
+            PathBuf::from("/docs/guide/images/x.png")
+        );
+        #[cfg(not(windows))]
+        assert_eq!(resolve("/abs/z.png"), PathBuf::from("/abs/z.png"));
+        assert_eq!(image_path(&None, "images/x.png"), "images/x.png");
+
+        let dir = std::env::temp_dir().join(format!("mdfried_images_{}", std::process::id()));
+        fs::create_dir_all(dir.join("docs")).expect("create_dir_all");
+        fs::write(dir.join("y.png"), b"").expect("write");
+        let resolved = image_path(&Some(dir.join("docs")), "../y.png");
+        assert!(
+            PathBuf::from(resolved).exists(),
+            "../ resolves to the parent"
+        );
+        fs::remove_dir_all(dir).expect("remove_dir_all");
+    }
+
+    #[tokio::test]
+    async fn image_redirects() {
+        // Redirects `/moved.png` to `/image.png`, which is a 1x1 png.
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("local_addr");
+        let mut png = Vec::new();
+        DynamicImage::new_rgb8(1, 1)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("write png");
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    return;
+                };
+                // Only the request line matters, but read all headers before answering.
+                let request = BufReader::new(&stream)
+                    .lines()
+                    .map_while(Result::ok)
+                    .take_while(|line| !line.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let response = if request.starts_with("GET /moved.png") {
+                    format!(
+                        "HTTP/1.1 302 Found\r\n\
+                         Location: http://{addr}/image.png\r\n\
+                         Content-Length: 0\r\n\
+                         Connection: close\r\n\r\n"
+                    )
+                    .into_bytes()
+                } else {
+                    let mut response = format!(
+                        "HTTP/1.1 200 OK\r\n\
+                         Content-Type: image/png\r\n\
+                         Content-Length: {}\r\n\
+                         Connection: close\r\n\r\n",
+                        png.len()
+                    )
+                    .into_bytes();
+                    response.extend(&png);
+                    response
+                };
+                stream.write_all(&response).expect("write_all");
+            }
+        });
+
+        let url = format!("http://{addr}/moved.png");
+        let client = Arc::new(RwLock::new(http_client(true, 10).expect("http_client")));
+        let source = ImageSource::fetch(&None, client, 1, &url, None, 0).await;
+        assert!(
+            matches!(source, Ok(ImageSource::Bytes(_, image::ImageFormat::Png))),
+            "redirect followed"
+        );
+
+        let client = Arc::new(RwLock::new(http_client(false, 10).expect("http_client")));
+        let source = ImageSource::fetch(&None, client, 1, &url, None, 0).await;
+        assert!(
+            matches!(source, Err(Error::UnknownImage(1, _))),
+            "redirect not followed"
+        );
+
+        let client = Arc::new(RwLock::new(http_client(true, 0).expect("http_client")));
+        let source = ImageSource::fetch(&None, client, 1, &url, None, 0).await;
+        assert!(matches!(source, Err(_)), "too many redirects");
     }
 }
@@ -1,7 +1,10 @@
 use std::{
-    path::PathBuf,
+    collections::VecDeque,
+    fs,
+    hash::{DefaultHasher, Hash as _, Hasher as _},
+    path::{Path, PathBuf},
     sync::{
-        Arc,
+        Arc, Mutex,
         mpsc::{Receiver, Sender},
     },
     thread::{self, JoinHandle},
@@ -14,12 +17,23 @@ use tokio::{runtime::Builder, sync::RwLock};
 
 use crate::{
     Cmd, Event,
+    data_uri::is_data_uri,
+    diagram,
     error::Error,
-    markdown::parse,
+    image_cache::ImageCache,
+    markdown::{ParseOptions, parse},
+    model::DocumentId,
     setup::{BgColor, FontRenderer},
-    widget_sources::{WidgetSource, header_images, header_sources, image_source},
+    widget_sources::{
+        Download, LineExtra, SourceID, THUMBNAIL_WIDTH, WidgetSource, WidgetSourceData,
+        header_images, header_sources, http_client, image_path, image_source, is_remote_url,
+        thumbnail_protocol,
+    },
 };
 
+// Downloaded images kept in memory for `Cmd::OpenImage`, the oldest are dropped first.
+const MAX_DOWNLOADS: usize = 32;
+
 #[expect(clippy::too_many_arguments)]
 pub fn worker_thread(
     basepath: Option<PathBuf>,
@@ -28,10 +42,12 @@ pub fn worker_thread(
     skin: MadSkin,
     bg: Option<BgColor>,
     has_text_size_protocol: bool,
-    deep_fry: bool,
+    mut deep_fry_headers: bool,
+    mut deep_fry_images: bool,
     cmd_rx: Receiver<Cmd>,
     event_tx: Sender<Event<'static>>,
     config_max_image_height: u16,
+    mut parse_options: ParseOptions,
 ) -> JoinHandle<Result<(), Error>> {
     thread::spawn(move || {
         let runtime = Builder::new_multi_thread()
@@ -39,14 +55,27 @@ pub fn worker_thread(
             .enable_all()
             .build()?;
         runtime.block_on(async {
-            let basepath = basepath.clone();
-            let client = Arc::new(RwLock::new(Client::new()));
-            let protocol_type = picker.protocol_type(); // Won't change
+            let mut basepath = basepath.clone();
+            let client = Arc::new(RwLock::new(http_client(
+                parse_options.image_follow_redirects,
+                parse_options.image_max_redirects,
+            )?));
+            let mut protocol_type = picker.protocol_type();
+            let mut inline_image_thumbnails = parse_options.inline_image_thumbnails;
+            // Halfblocks can't show anything in a single row, keep the alt text instead.
+            parse_options.inline_image_thumbnails &= protocol_type != ProtocolType::Halfblocks;
             // Specifically not a tokio Mutex, because we use it in spawn_blocking.
-            let thread_renderer =
-                renderer.map(|renderer| Arc::new(std::sync::Mutex::new(renderer)));
-            let thread_picker = Arc::new(picker);
-            let skin = RatSkin { skin };
+            let thread_renderer = renderer.map(|renderer| Arc::new(Mutex::new(renderer)));
+            let mut thread_picker = Arc::new(picker);
+            let mut skin = RatSkin { skin };
+            // The latest downloaded images by url, to be written out for opening externally.
+            let downloads: Arc<Mutex<VecDeque<(String, Download)>>> = Arc::default();
+            let temp_dir = std::env::temp_dir().join(format!("mdfried_{}", std::process::id()));
+            // The first image of the document, see `ParseOptions::hero_image`.
+            let mut hero_image_id: Option<SourceID> = None;
+            if let Some(cache) = parse_options.image_cache.clone() {
+                tokio::task::spawn_blocking(move || cache.prune());
+            }
 
             for cmd in cmd_rx {
                 log::debug!("Cmd: {cmd}");
@@ -54,17 +83,51 @@ pub fn worker_thread(
                     Cmd::Parse(document_id, width, text) => {
                         log::info!("Parse {document_id}");
                         event_tx.send(Event::NewDocument(document_id))?;
+                        hero_image_id = None;
                         let mut last_parsed_source_id = None;
-                        for event in parse(&text, &skin, document_id, width, has_text_size_protocol)
-                        {
+                        for event in parse(
+                            &text,
+                            &skin,
+                            document_id,
+                            width,
+                            has_text_size_protocol,
+                            &parse_options,
+                        ) {
                             match &event {
                                 Event::Parsed(_, source) => {
                                     last_parsed_source_id = Some(source.id);
+                                    if let WidgetSourceData::Line(_, extras) = &source.data {
+                                        for extra in extras {
+                                            if let LineExtra::Thumbnail(url, column, _) = extra {
+                                                spawn_thumbnail(
+                                                    &event_tx,
+                                                    &thread_picker,
+                                                    &basepath,
+                                                    &client,
+                                                    document_id,
+                                                    source.id,
+                                                    *column,
+                                                    url.clone(),
+                                                    parse_options.resized_image_url(
+                                                        url,
+                                                        THUMBNAIL_WIDTH,
+                                                        thread_picker.font_size().0,
+                                                    ),
+                                                    parse_options.image_background,
+                                                    parse_options.max_image_pixels,
+                                                    parse_options.image_cache.clone(),
+                                                    parse_options.max_data_uri_bytes,
+                                                );
+                                            }
+                                        }
+                                    }
                                 }
                                 Event::ParseImage(_, source_id, _, _, _) => {
                                     last_parsed_source_id = Some(*source_id);
+                                    hero_image_id = hero_image_id.or(Some(*source_id));
                                 }
-                                Event::ParseHeader(_, source_id, _, _) => {
+                                Event::ParseHeader(_, source_id, _, _)
+                                | Event::ParseDiagram(_, source_id, _, _) => {
                                     last_parsed_source_id = Some(*source_id);
                                 }
                                 _ => {}
@@ -75,52 +138,116 @@ pub fn worker_thread(
                         event_tx.send(Event::ParseDone(document_id, last_parsed_source_id))?;
                     }
                     Cmd::Header(document_id, source_id, width, tier, text) => {
-                        debug_assert!(
-                            thread_renderer.is_some(),
-                            "should not have sent ImgCmd::Header without renderer"
-                        );
+                        // Without renderer (no fonts), or with halfblocks, the header stays as the plain text
+                        // line.
                         if let Some(thread_renderer) = &thread_renderer {
                             let task_tx = event_tx.clone();
                             if protocol_type != ProtocolType::Halfblocks {
                                 let renderer = thread_renderer.clone();
                                 let picker = thread_picker.clone();
+                                let options = parse_options.clone();
                                 tokio::spawn(async move {
-                                    let images = tokio::task::spawn_blocking(move || {
-                                        let mut r = renderer.lock()?;
-                                        header_images(bg, &mut r, width, text, tier, deep_fry)
-                                    })
-                                    .await??;
-
-                                    let headers = tokio::task::spawn_blocking(move || {
-                                        header_sources(&picker, width, source_id, images, deep_fry)
-                                    })
-                                    .await??;
-                                    task_tx.send(Event::Update(document_id, headers))?;
+                                    let headers = async {
+                                        let images = tokio::task::spawn_blocking(move || {
+                                            let mut r = renderer.lock()?;
+                                            header_images(
+                                                bg,
+                                                &mut r,
+                                                width,
+                                                text,
+                                                tier,
+                                                &options,
+                                                deep_fry_headers,
+                                            )
+                                        })
+                                        .await??;
+
+                                        tokio::task::spawn_blocking(move || {
+                                            header_sources(
+                                                &picker,
+                                                width,
+                                                source_id,
+                                                images,
+                                                deep_fry_headers,
+                                            )
+                                        })
+                                        .await?
+                                    }
+                                    .await;
+                                    match headers {
+                                        Ok(headers) => {
+                                            task_tx.send(Event::Update(document_id, headers))?
+                                        }
+                                        Err(err) => {
+                                            task_tx.send(Event::Error(format!(
+                                                "Failed to render header: {err}"
+                                            )))?;
+                                            task_tx
+                                                .send(Event::Unrendered(document_id, source_id))?
+                                        }
+                                    }
                                     Ok::<(), Error>(())
                                 });
+                                continue;
                             }
                         }
+                        event_tx.send(Event::Unrendered(document_id, source_id))?;
                     }
                     Cmd::UrlImage(document_id, source_id, width, url, text, _title) => {
                         let task_tx = event_tx.clone();
                         let basepath = basepath.clone();
                         let client = client.clone();
                         let picker = thread_picker.clone();
-                        // TODO: handle spawned task result errors, right now it's just discarded.
+                        let downloads = downloads.clone();
+                        let background = parse_options.image_background;
+                        let hero = parse_options.hero_image && hero_image_id == Some(source_id);
+                        let max_pixels = parse_options.max_image_pixels;
+                        let max_width = parse_options.max_image_width;
+                        let cache = parse_options.image_cache.clone();
+                        // Sixel and iTerm2 send the whole image on every draw, too slow for frames.
+                        let animate = parse_options.animate_images
+                            && matches!(
+                                picker.protocol_type(),
+                                ProtocolType::Kitty | ProtocolType::Halfblocks
+                            );
+                        let max_data_bytes = parse_options.max_data_uri_bytes;
+                        let fetch_url =
+                            parse_options.resized_image_url(&url, width, picker.font_size().0);
+                        // Shown again after a resize or protocol change, without downloading it.
+                        let downloaded = downloads.lock().ok().and_then(|downloads| {
+                            downloads
+                                .iter()
+                                .find(|(download_url, _)| *download_url == url)
+                                .map(|(_, download)| download.clone())
+                        });
                         tokio::spawn(async move {
                             match image_source(
                                 &picker,
                                 config_max_image_height,
+                                max_width,
                                 width,
                                 &basepath,
                                 client,
                                 source_id,
                                 &url,
-                                deep_fry,
+                                fetch_url.as_deref().unwrap_or(&url),
+                                downloaded,
+                                deep_fry_images,
+                                background,
+                                hero,
+                                max_pixels,
+                                cache,
+                                animate,
+                                max_data_bytes,
                             )
                             .await
                             {
-                                Ok(source) => {
+                                Ok((source, download)) => {
+                                    if let Some(download) = download
+                                        && let Ok(mut downloads) = downloads.lock()
+                                    {
+                                        keep_download(&mut downloads, url, download);
+                                    }
                                     task_tx.send(Event::Update(document_id, vec![source]))?
                                 }
                                 Err(Error::UnknownImage(id, link)) => {
@@ -129,18 +256,295 @@ pub fn worker_thread(
                                         vec![WidgetSource::image_unknown(id, link, text)],
                                     ))?
                                 }
-                                Err(_) => task_tx.send(Event::Update(
-                                    document_id,
-                                    vec![WidgetSource::image_unknown(source_id, url, text)],
-                                ))?,
+                                Err(err) => {
+                                    task_tx.send(Event::Error(format!(
+                                        "Failed to load image {url}: {err}"
+                                    )))?;
+                                    task_tx.send(Event::Update(
+                                        document_id,
+                                        vec![WidgetSource::image_unknown(source_id, url, text)],
+                                    ))?
+                                }
+                            }
+                            Ok::<(), Error>(())
+                        });
+                    }
+                    Cmd::Diagram(document_id, source_id, width, diagram, source) => {
+                        let Some(command) = parse_options.diagram_command(diagram) else {
+                            event_tx.send(Event::Unrendered(document_id, source_id))?;
+                            continue;
+                        };
+                        let command = command.to_owned();
+                        let task_tx = event_tx.clone();
+                        let client = client.clone();
+                        let picker = thread_picker.clone();
+                        let temp_dir = temp_dir.clone();
+                        let background = parse_options.image_background;
+                        let max_pixels = parse_options.max_image_pixels;
+                        let max_width = parse_options.max_image_width;
+                        tokio::spawn(async move {
+                            let name = format!("diagram_{document_id}_{source_id}");
+                            let image = async {
+                                let path = tokio::task::spawn_blocking(move || {
+                                    diagram::render(diagram, &command, &source, &temp_dir, &name)
+                                })
+                                .await??;
+                                let url = path.to_string_lossy().into_owned();
+                                image_source(
+                                    &picker,
+                                    config_max_image_height,
+                                    max_width,
+                                    width,
+                                    &None,
+                                    client,
+                                    source_id,
+                                    &url,
+                                    &url,
+                                    None,
+                                    deep_fry_images,
+                                    background,
+                                    false,
+                                    max_pixels,
+                                    None,
+                                    false,
+                                    0,
+                                )
+                                .await
+                            }
+                            .await;
+                            match image {
+                                Ok((source, _)) => {
+                                    task_tx.send(Event::Update(document_id, vec![source]))?
+                                }
+                                Err(err) => {
+                                    task_tx.send(Event::Error(format!(
+                                        "Failed to render {diagram} diagram: {err}"
+                                    )))?;
+                                    task_tx.send(Event::Update(
+                                        document_id,
+                                        vec![WidgetSource::image_unknown(
+                                            source_id,
+                                            diagram.to_string(),
+                                            String::new(),
+                                        )],
+                                    ))?
+                                }
                             }
                             Ok::<(), Error>(())
                         });
                     }
+                    Cmd::SetBasepath(new_basepath) => {
+                        basepath = new_basepath;
+                    }
+                    Cmd::SetProtocol(new_protocol_type) => {
+                        // Tasks still running keep the previous picker, the model reparses anyway.
+                        let mut picker = (*thread_picker).clone();
+                        picker.set_protocol_type(new_protocol_type);
+                        thread_picker = Arc::new(picker);
+                        protocol_type = new_protocol_type;
+                        parse_options.inline_image_thumbnails =
+                            inline_image_thumbnails && protocol_type != ProtocolType::Halfblocks;
+                    }
+                    Cmd::SetDeepFry(deep_fry) => {
+                        deep_fry_headers = deep_fry;
+                        deep_fry_images = deep_fry;
+                    }
+                    // The http client and image sizes stay as they were started with.
+                    Cmd::SetConfig(new_parse_options, new_skin) => {
+                        parse_options = *new_parse_options;
+                        inline_image_thumbnails = parse_options.inline_image_thumbnails;
+                        parse_options.inline_image_thumbnails &=
+                            protocol_type != ProtocolType::Halfblocks;
+                        skin = RatSkin { skin: new_skin };
+                    }
+                    Cmd::ForgetDownloads => {
+                        if let Ok(mut downloads) = downloads.lock() {
+                            downloads.clear();
+                        }
+                    }
+                    Cmd::OpenImage(url) => {
+                        let path = if is_remote_url(&url) || is_data_uri(&url) {
+                            write_download(&temp_dir, &downloads, &url)
+                        } else {
+                            Ok(PathBuf::from(image_path(&basepath, &url)))
+                        };
+                        match path {
+                            Ok(path) => event_tx.send(Event::OpenImage(path))?,
+                            Err(err) => event_tx
+                                .send(Event::Error(format!("Failed to open image {url}: {err}")))?,
+                        }
+                    }
                 }
             }
+            if temp_dir.exists() {
+                log::info!("removing {temp_dir:?}");
+                fs::remove_dir_all(&temp_dir)?;
+            }
             Ok::<(), Error>(())
         })?;
         Ok::<(), Error>(())
     })
 }
+
+#[expect(clippy::too_many_arguments)]
+fn spawn_thumbnail(
+    event_tx: &Sender<Event<'static>>,
+    picker: &Arc<Picker>,
+    basepath: &Option<PathBuf>,
+    client: &Arc<RwLock<Client>>,
+    document_id: DocumentId,
+    source_id: SourceID,
+    column: u16,
+    url: String,
+    // See `ParseOptions::resized_image_url`.
+    fetch_url: Option<String>,
+    background: Option<BgColor>,
+    max_pixels: u64,
+    cache: Option<ImageCache>,
+    max_data_bytes: usize,
+) {
+    let task_tx = event_tx.clone();
+    let picker = picker.clone();
+    let basepath = basepath.clone();
+    let client = client.clone();
+    tokio::spawn(async move {
+        let fetch_url = fetch_url.as_deref().unwrap_or(&url);
+        match thumbnail_protocol(
+            &picker,
+            &basepath,
+            client,
+            source_id,
+            fetch_url,
+            background,
+            max_pixels,
+            cache,
+            max_data_bytes,
+        )
+        .await
+        {
+            Ok(proto) => task_tx.send(Event::Thumbnail(
+                document_id,
+                source_id,
+                column,
+                WidgetSourceData::Image(url, proto),
+            ))?,
+            // The line keeps the blank space, like a broken image.
+            Err(err) => task_tx.send(Event::Error(format!("Failed to load image {url}: {err}")))?,
+        }
+        Ok::<(), Error>(())
+    });
+}
+
+// Keep the latest download of `url`, dropping the oldest ones beyond `MAX_DOWNLOADS`.
+fn keep_download(downloads: &mut VecDeque<(String, Download)>, url: String, download: Download) {
+    downloads.retain(|(download_url, _)| *download_url != url);
+    downloads.push_back((url, download));
+    while downloads.len() > MAX_DOWNLOADS {
+        downloads.pop_front();
+    }
+}
+
+// Write a downloaded image into the temp dir, to be opened by an external viewer. The file is
+// named by the url, so that opening it again overwrites the same file.
+fn write_download(
+    temp_dir: &Path,
+    downloads: &Mutex<VecDeque<(String, Download)>>,
+    url: &str,
+) -> Result<PathBuf, Error> {
+    let downloads = downloads
+        .lock()
+        .map_err(|err| Error::Thread(format!("PoisonError: {err}")))?;
+    let (_, download) = downloads
+        .iter()
+        .find(|(download_url, _)| download_url == url)
+        .ok_or_else(|| Error::NotDownloaded(url.to_owned()))?;
+
+    fs::create_dir_all(temp_dir)?;
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    let extension = download.format.extensions_str().first().unwrap_or(&"img");
+    let path = temp_dir.join(format!("image_{:016x}.{extension}", hasher.finish()));
+    fs::write(&path, &download.bytes)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+#[expect(clippy::unwrap_used)]
+mod tests {
+    use std::{collections::VecDeque, fs, sync::Mutex};
+
+    use image::ImageFormat;
+
+    use super::{MAX_DOWNLOADS, keep_download, write_download};
+    use crate::{error::Error, widget_sources::Download};
+
+    fn download(bytes: &[u8]) -> Download {
+        Download {
+            url: String::new(),
+            bytes: bytes.to_vec(),
+            format: ImageFormat::Png,
+        }
+    }
+
+    #[test]
+    fn write_downloads() {
+        let dir = std::env::temp_dir().join(format!("mdfried_downloads_{}", std::process::id()));
+        let mut downloads = VecDeque::new();
+        keep_download(
+            &mut downloads,
+            String::from("https://x/a.png"),
+            download(b"a"),
+        );
+        keep_download(
+            &mut downloads,
+            String::from("https://x/b.png"),
+            download(b"b"),
+        );
+        let downloads = Mutex::new(downloads);
+
+        let a = write_download(&dir, &downloads, "https://x/a.png").unwrap();
+        assert_eq!(a.extension().and_then(|ext| ext.to_str()), Some("png"));
+        assert_eq!(fs::read(&a).unwrap(), b"a");
+        let b = write_download(&dir, &downloads, "https://x/b.png").unwrap();
+        assert_ne!(a, b, "named by url");
+        assert_eq!(
+            write_download(&dir, &downloads, "https://x/a.png").unwrap(),
+            a,
+            "same url, same file"
+        );
+        assert!(
+            matches!(
+                write_download(&dir, &downloads, "https://x/c.png"),
+                Err(Error::NotDownloaded(_))
+            ),
+            "not downloaded"
+        );
+
+        fs::remove_dir_all(dir).expect("remove_dir_all");
+    }
+
+    #[test]
+    fn keep_downloads() {
+        let mut downloads = VecDeque::new();
+        for i in 0..=MAX_DOWNLOADS {
+            keep_download(&mut downloads, format!("https://x/{i}.png"), download(b"x"));
+        }
+        keep_download(
+            &mut downloads,
+            String::from("https://x/1.png"),
+            download(b"new"),
+        );
+        assert_eq!(downloads.len(), MAX_DOWNLOADS);
+        assert!(
+            downloads.iter().all(|(url, _)| url != "https://x/0.png"),
+            "the oldest is dropped"
+        );
+        assert_eq!(
+            downloads
+                .back()
+                .map(|(url, download)| (url.as_str(), download.bytes.as_slice())),
+            Some(("https://x/1.png", b"new".as_slice())),
+            "downloaded again, kept as the latest"
+        );
+    }
+}